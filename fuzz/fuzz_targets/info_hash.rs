@@ -0,0 +1,38 @@
+//! Fuzz target for the info-hash parser.
+//!
+//! It checks the parser never panics and only accepts 40 hex characters or
+//! 32 characters of the strict RFC 4648 base32 alphabet. Run it with:
+//!
+//! ```text
+//! cargo +nightly fuzz run info_hash
+//! ```
+#![no_main]
+
+use std::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use torrust_hash2torrent::bit_torrent::info_hash::InfoHash;
+
+fn is_hex(s: &str) -> bool {
+    s.len() == 40 && s.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_base32(s: &str) -> bool {
+    s.len() == 32
+        && s
+            .bytes()
+            .all(|c| c.is_ascii_alphabetic() || (b'2'..=b'7').contains(&c))
+}
+
+fuzz_target!(|s: &str| {
+    match InfoHash::from_str(s) {
+        Ok(info_hash) => {
+            assert!(is_hex(s) || is_base32(s), "accepted invalid input: {s:?}");
+
+            if is_hex(s) {
+                assert_eq!(info_hash.to_hex_string(), s.to_ascii_lowercase());
+            }
+        }
+        Err(_) => assert!(!is_hex(s) && !is_base32(s), "rejected valid input: {s:?}"),
+    }
+});
@@ -0,0 +1,243 @@
+//! Allowlist and denylist of info hashes, for legal compliance.
+//!
+//! Denied info hashes get a `451 Unavailable For Legal Reasons`. When there
+//! is an allowlist, the info hashes not in it get a `403 Forbidden`. The
+//! denylist takes precedence, so an info hash in both lists is denied.
+//!
+//! The lists are loaded on startup, and the list files are read again when
+//! the config is reloaded with `POST /admin/reload`.
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+use crate::api::error::ApiError;
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::config::{AccessLists, InfoHashList};
+
+/// The marker of the comment lines in the info hash list files.
+const COMMENT_PREFIX: char = '#';
+
+#[derive(Debug, Default)]
+pub struct AccessList {
+    lists: RwLock<Lists>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Lists {
+    denied: HashSet<InfoHash>,
+    /// `None` when all the info hashes not denied are allowed.
+    allowed: Option<HashSet<InfoHash>>,
+}
+
+impl AccessList {
+    /// Loads the configured lists, reading the list files.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if any of the list files can't be read or has an
+    /// invalid info hash.
+    pub fn load(config: &AccessLists) -> io::Result<Self> {
+        Ok(Self {
+            lists: RwLock::new(Lists::load(config)?),
+        })
+    }
+
+    /// Loads the configured lists again, reading the list files. It returns
+    /// true if they changed.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if any of the list files can't be read or has an
+    /// invalid info hash. The current lists are kept then.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn reload(&self, config: &AccessLists) -> io::Result<bool> {
+        let new = Lists::load(config)?;
+
+        let mut lists = self.lists.write().expect("access list lock poisoned");
+
+        if *lists == new {
+            return Ok(false);
+        }
+
+        *lists = new;
+
+        Ok(true)
+    }
+
+    /// # Errors
+    ///
+    /// Will return an error if the info hash is denied or it's not in the
+    /// allowlist.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn check(&self, info_hash: &InfoHash) -> Result<(), ApiError> {
+        let lists = self.lists.read().expect("access list lock poisoned");
+
+        if lists.denied.contains(info_hash) {
+            return Err(ApiError::Denied);
+        }
+
+        if lists
+            .allowed
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(info_hash))
+        {
+            return Err(ApiError::NotAllowed);
+        }
+
+        Ok(())
+    }
+}
+
+impl Lists {
+    fn load(config: &AccessLists) -> io::Result<Self> {
+        Ok(Self {
+            denied: match &config.denylist {
+                Some(list) => load_list(list)?,
+                None => HashSet::new(),
+            },
+            allowed: config.allowlist.as_ref().map(load_list).transpose()?,
+        })
+    }
+}
+
+fn load_list(list: &InfoHashList) -> io::Result<HashSet<InfoHash>> {
+    Ok(load_info_hashes(list)?.into_iter().collect())
+}
+
+/// Returns the info hashes of the list, in order, reading the list file.
+///
+/// # Errors
+///
+/// Will return an error if the list file can't be read or has an invalid
+/// info hash.
+pub fn load_info_hashes(list: &InfoHashList) -> io::Result<Vec<InfoHash>> {
+    match list {
+        InfoHashList::Inline(info_hashes) => Ok(info_hashes.clone()),
+        InfoHashList::File(path) => parse_list(&fs::read_to_string(path)?).map_err(|line| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid info hash in line {line} of {path}"),
+            )
+        }),
+    }
+}
+
+/// Parses a list with an info hash (hex or base32) per line. Empty lines and
+/// comments are ignored.
+///
+/// It returns the number of the first line with an invalid info hash.
+fn parse_list(contents: &str) -> Result<Vec<InfoHash>, usize> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(COMMENT_PREFIX))
+        .map(|(number, line)| InfoHash::from_str(line).map_err(|_| number))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::str::FromStr;
+
+    use camino::Utf8PathBuf;
+    use tempfile::TempDir;
+
+    use super::AccessList;
+    use crate::api::error::ApiError;
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::config::{AccessLists, InfoHashList};
+
+    fn info_hash(hex: &str) -> InfoHash {
+        InfoHash::from_str(hex).unwrap()
+    }
+
+    #[test]
+    fn it_should_deny_the_info_hashes_in_the_denylist_even_if_they_are_allowed() {
+        let denied = info_hash("443c7602b4fde83d1154d6d9da48808418b181b6");
+        let allowed = info_hash("0000000000000000000000000000000000000001");
+        let other = info_hash("0000000000000000000000000000000000000002");
+
+        let access_list = AccessList::load(&AccessLists {
+            denylist: Some(InfoHashList::Inline(vec![denied])),
+            allowlist: Some(InfoHashList::Inline(vec![denied, allowed])),
+        })
+        .unwrap();
+
+        assert!(matches!(access_list.check(&denied), Err(ApiError::Denied)));
+        assert!(access_list.check(&allowed).is_ok());
+        assert!(matches!(
+            access_list.check(&other),
+            Err(ApiError::NotAllowed)
+        ));
+        assert!(AccessList::default().check(&other).is_ok());
+    }
+
+    #[test]
+    fn it_should_load_the_lists_from_files_ignoring_empty_lines_and_comments() {
+        let dir = TempDir::new().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("denylist.txt")).unwrap();
+        fs::write(
+            &path,
+            "# Takedown notices\n\n443c7602b4fde83d1154d6d9da48808418b181b6\n",
+        )
+        .unwrap();
+
+        let access_list = AccessList::load(&AccessLists {
+            denylist: Some(InfoHashList::File(path.clone())),
+            allowlist: None,
+        })
+        .unwrap();
+
+        assert!(matches!(
+            access_list.check(&info_hash("443c7602b4fde83d1154d6d9da48808418b181b6")),
+            Err(ApiError::Denied)
+        ));
+
+        fs::write(&path, "443c7602b4fde83d1154d6d9da48808418b181b6\ninvalid\n").unwrap();
+
+        let err = AccessList::load(&AccessLists {
+            denylist: Some(InfoHashList::File(path)),
+            allowlist: None,
+        })
+        .unwrap_err();
+
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn it_should_apply_the_reloaded_list_files_and_keep_the_current_lists_on_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("denylist.txt")).unwrap();
+        fs::write(&path, "").unwrap();
+
+        let config = AccessLists {
+            denylist: Some(InfoHashList::File(path.clone())),
+            allowlist: None,
+        };
+        let access_list = AccessList::load(&config).unwrap();
+        let denied = info_hash("443c7602b4fde83d1154d6d9da48808418b181b6");
+
+        assert!(access_list.check(&denied).is_ok());
+
+        fs::write(&path, "443c7602b4fde83d1154d6d9da48808418b181b6\n").unwrap();
+
+        assert!(access_list.reload(&config).unwrap());
+        assert!(!access_list.reload(&config).unwrap());
+        assert!(matches!(access_list.check(&denied), Err(ApiError::Denied)));
+
+        fs::write(&path, "invalid\n").unwrap();
+
+        assert!(access_list.reload(&config).is_err());
+        assert!(matches!(access_list.check(&denied), Err(ApiError::Denied)));
+    }
+}
@@ -0,0 +1,104 @@
+//! Token authentication.
+//!
+//! Clients send the token in the `Authorization` header using the bearer
+//! scheme: `Authorization: Bearer <token>`. Tokens are compared in constant
+//! time, so they can't be guessed by measuring the response times.
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::{header, HeaderMap};
+
+use crate::api::error::ApiError;
+use crate::AppState;
+
+/// Middleware that only lets through the requests with the API token, when
+/// it's configured. The admin token is also accepted, so admins can use the
+/// admin endpoints with a single token.
+pub async fn require_api_token(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let api = &app_state.config.api;
+
+    if let Some(auth_token) = &api.auth_token {
+        let is_admin = api
+            .admin_token
+            .as_ref()
+            .is_some_and(|admin_token| has_token(request.headers(), admin_token));
+
+        if !is_admin && !has_token(request.headers(), auth_token) {
+            return ApiError::Unauthorized.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+/// Middleware that only lets through the requests with the admin token. All
+/// of them are rejected when there is no admin token configured.
+pub async fn require_admin_token(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(admin_token) = &app_state.config.api.admin_token else {
+        return ApiError::Unauthorized.into_response();
+    };
+
+    if !has_token(request.headers(), admin_token) {
+        return ApiError::Unauthorized.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Returns true if the `Authorization` header contains the bearer token.
+fn has_token(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .is_some_and(|bearer| constant_time_eq(bearer.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Compares the values without returning early on the first difference. Only
+/// the length of the values can be learned from the comparison time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, HeaderMap};
+
+    use super::{constant_time_eq, has_token};
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn it_should_accept_only_the_bearer_token() {
+        assert!(has_token(&headers("Bearer secret"), "secret"));
+        assert!(!has_token(&headers("Bearer other"), "secret"));
+        assert!(!has_token(&headers("Basic secret"), "secret"));
+        assert!(!has_token(&headers("secret"), "secret"));
+        assert!(!has_token(&HeaderMap::new(), "secret"));
+    }
+
+    #[test]
+    fn it_should_compare_the_tokens() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
@@ -1,43 +1,166 @@
 use std::{
+    collections::HashMap,
     fs::File,
     io::{self, Read, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime},
 };
 
 use bytes::Bytes;
 use camino::Utf8PathBuf;
-use tracing::info;
+use librqbit::{torrent_from_bytes, ByteBuf};
+use lru::LruCache;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
 
 use crate::bit_torrent::info_hash::InfoHash;
+use crate::clock::{Clock, SystemClock};
+use crate::config::Cache as CacheConfig;
 
+/// Cache for resolved torrents.
+///
+/// Torrents are kept in memory (bounded by `max_entries`, evicting the least
+/// recently used one). If the disk cache is enabled, they are also written to
+/// the cache dir, so they can be loaded again when they are evicted from
+/// memory or after a restart. When the files in the cache dir exceed
+/// `max_disk_size`, the oldest ones are removed.
+///
+/// Torrents loaded from the cache dir are only returned if their info-hash
+/// matches the requested one, so corrupt files are never served.
+///
+/// If a TTL is configured, torrents older than the TTL are treated as misses.
 pub struct Cache {
     pub cache_dir: Utf8PathBuf,
+    /// It can be changed at runtime with [`Cache::set_ttl`].
+    ttl: RwLock<Option<Duration>>,
+    disk: bool,
+    max_disk_size: Option<u64>,
+    memory: Mutex<LruCache<InfoHash, CachedTorrent>>,
+    /// Bounds the number of torrent files written at the same time.
+    disk_writes: Semaphore,
+    disk_writes_in_progress: AtomicUsize,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedTorrent {
+    pub bytes: Bytes,
+    /// When the torrent was resolved and added to the cache.
+    pub cached_at: SystemTime,
+}
+
+/// A cached torrent, without its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    pub info_hash: InfoHash,
+    pub cached_at: SystemTime,
+    /// Size in bytes of the torrent file.
+    pub size: u64,
 }
 
 impl Cache {
     #[must_use]
-    pub fn new(cache_dir: Utf8PathBuf) -> Self {
-        Self { cache_dir }
+    pub fn new(cache_dir: Utf8PathBuf, config: &CacheConfig) -> Self {
+        Self::with_clock(cache_dir, config, Arc::new(SystemClock))
     }
 
-    /// Returns true if the cache contains the torrent.
+    /// It uses the given clock to calculate the age of the cached torrents.
+    #[must_use]
+    pub fn with_clock(cache_dir: Utf8PathBuf, config: &CacheConfig, clock: Arc<dyn Clock>) -> Self {
+        // At least one torrent is always kept in memory, and one written to
+        // the cache dir.
+        let capacity = NonZeroUsize::new(config.max_entries).unwrap_or(NonZeroUsize::MIN);
+        let max_concurrent_disk_writes =
+            NonZeroUsize::new(config.max_concurrent_disk_writes).unwrap_or(NonZeroUsize::MIN);
+
+        Self {
+            cache_dir,
+            ttl: RwLock::new(config.ttl),
+            disk: config.disk,
+            max_disk_size: config.max_disk_size,
+            memory: Mutex::new(LruCache::new(capacity)),
+            disk_writes: Semaphore::new(max_concurrent_disk_writes.get()),
+            disk_writes_in_progress: AtomicUsize::new(0),
+            clock,
+        }
+    }
+
+    /// Returns true if the cache contains the torrent and it has not expired.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
     #[must_use]
     pub fn contains(&self, info_hash: &InfoHash) -> bool {
-        self.path(info_hash).exists()
+        if let Some(entry) = self
+            .memory
+            .lock()
+            .expect("cache lock poisoned")
+            .peek(info_hash)
+        {
+            if !self.is_expired(entry.cached_at) {
+                return true;
+            }
+        }
+
+        self.disk_cached_at(info_hash)
+            .is_some_and(|cached_at| !self.is_expired(cached_at))
     }
 
     /// Adds a torrent to the cache.
     ///
+    /// It's added to memory right away. The writes to the cache dir are
+    /// bounded by the configured maximum of concurrent disk writes, waiting
+    /// for a free slot, so slow storage is not saturated.
+    ///
     /// # Errors
     ///
-    /// Will return an error if tt can't create or write the cache file.
-    pub fn add(&self, info_hash: &InfoHash, data: &Bytes) -> io::Result<()> {
+    /// Will return an error if it can't create or write the cache file, or
+    /// remove the oldest ones.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub async fn add(&self, info_hash: &InfoHash, data: &Bytes) -> io::Result<()> {
+        self.memory.lock().expect("cache lock poisoned").put(
+            *info_hash,
+            CachedTorrent {
+                bytes: data.clone(),
+                cached_at: self.clock.now(),
+            },
+        );
+
+        if !self.disk {
+            return Ok(());
+        }
+
         let filepath = self.path(info_hash);
 
+        // The semaphore is never closed.
+        let _permit = self
+            .disk_writes
+            .acquire()
+            .await
+            .map_err(|_| io::Error::other("disk writes semaphore closed"))?;
+        let _write_in_progress = WriteInProgress::new(&self.disk_writes_in_progress);
+
         info!("adding torrent to cache in {filepath}");
 
-        let mut file = File::create(self.path(info_hash))?;
+        let data = data.clone();
 
-        file.write_all(data)?;
+        tokio::task::spawn_blocking(move || write_file(&filepath, &data))
+            .await
+            .map_err(io::Error::other)??;
+
+        if let Some(max_disk_size) = self.max_disk_size {
+            self.evict_oldest_files(max_disk_size)?;
+        }
 
         Ok(())
     }
@@ -46,15 +169,228 @@ impl Cache {
     ///
     /// # Errors
     ///
-    /// Will return an error if it can't read the cache file.
-    pub fn get(&self, info_hash: &InfoHash) -> io::Result<Bytes> {
+    /// Will return an error if the torrent is not cached, it has expired, it
+    /// can't read the cache file or the file does not contain the torrent.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn get(&self, info_hash: &InfoHash) -> io::Result<CachedTorrent> {
+        {
+            let mut memory = self.memory.lock().expect("cache lock poisoned");
+
+            if let Some(entry) = memory.get(info_hash) {
+                if !self.is_expired(entry.cached_at) {
+                    return Ok(entry.clone());
+                }
+
+                memory.pop(info_hash);
+            }
+        }
+
+        let Some(cached_at) = self.disk_cached_at(info_hash) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "torrent not cached",
+            ));
+        };
+
+        if self.is_expired(cached_at) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "cached torrent expired",
+            ));
+        }
+
         let mut file = File::open(self.path(info_hash))?;
 
         let mut buffer = Vec::new();
 
         file.read_to_end(&mut buffer)?;
 
-        Ok(Bytes::from(buffer))
+        if !is_torrent_for(&buffer, info_hash) {
+            warn!(
+                "removing corrupt torrent from cache: {}",
+                self.path(info_hash)
+            );
+
+            std::fs::remove_file(self.path(info_hash))?;
+
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "cached torrent is corrupt",
+            ));
+        }
+
+        let cached_torrent = CachedTorrent {
+            bytes: Bytes::from(buffer),
+            cached_at,
+        };
+
+        self.memory
+            .lock()
+            .expect("cache lock poisoned")
+            .put(*info_hash, cached_torrent.clone());
+
+        Ok(cached_torrent)
+    }
+
+    /// Removes the torrent from memory and the cache dir.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if it can't remove the cache file.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn remove(&self, info_hash: &InfoHash) -> io::Result<()> {
+        self.memory
+            .lock()
+            .expect("cache lock poisoned")
+            .pop(info_hash);
+
+        match std::fs::remove_file(self.path(info_hash)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes all the torrents from memory and the cache dir.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if it can't remove the cache files.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn clear(&self) -> io::Result<()> {
+        self.memory.lock().expect("cache lock poisoned").clear();
+
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "torrent")
+            {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of torrents kept in memory.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.memory.lock().expect("cache lock poisoned").len()
+    }
+
+    /// Returns the number of torrent files being written to the cache dir.
+    #[must_use]
+    pub fn disk_writes_in_progress(&self) -> usize {
+        self.disk_writes_in_progress.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if there are no torrents kept in memory.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the total size in bytes of the torrents kept in memory. It's
+    /// approximate, as it doesn't include the overhead of the entries.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn memory_size(&self) -> u64 {
+        self.memory
+            .lock()
+            .expect("cache lock poisoned")
+            .iter()
+            .map(|(_, entry)| entry.bytes.len() as u64)
+            .sum()
+    }
+
+    /// Returns the total size in bytes of the torrent files in the cache
+    /// dir, or `None` if the disk cache is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if it can't read the cache dir.
+    pub fn disk_size(&self) -> io::Result<Option<u64>> {
+        if !self.disk {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            self.torrent_files()?.iter().map(|(_, len, _)| len).sum(),
+        ))
+    }
+
+    /// Returns the cached torrents that have not expired, from memory and
+    /// the cache dir, sorted by info hash.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if it can't read the cache dir.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let mut entries: HashMap<InfoHash, CacheEntry> = self
+            .memory
+            .lock()
+            .expect("cache lock poisoned")
+            .iter()
+            .map(|(info_hash, entry)| {
+                (
+                    *info_hash,
+                    CacheEntry {
+                        info_hash: *info_hash,
+                        cached_at: entry.cached_at,
+                        size: entry.bytes.len() as u64,
+                    },
+                )
+            })
+            .collect();
+
+        if self.disk {
+            for (cached_at, size, path) in self.torrent_files()? {
+                // Other files named `*.torrent` are ignored.
+                let Some(info_hash) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| InfoHash::from_str(stem).ok())
+                else {
+                    continue;
+                };
+
+                entries.entry(info_hash).or_insert(CacheEntry {
+                    info_hash,
+                    cached_at,
+                    size,
+                });
+            }
+        }
+
+        let mut entries: Vec<CacheEntry> = entries
+            .into_values()
+            .filter(|entry| !self.is_expired(entry.cached_at))
+            .collect();
+
+        entries.sort_unstable_by_key(|entry| entry.info_hash.0);
+
+        Ok(entries)
     }
 
     /// Returns the cache file path.
@@ -64,4 +400,454 @@ impl Cache {
         cached_torrent_path.push(format!("{}.torrent", info_hash.to_hex_string()));
         cached_torrent_path
     }
+
+    /// Returns when the torrent was written to the cache dir, if it was.
+    fn disk_cached_at(&self, info_hash: &InfoHash) -> Option<SystemTime> {
+        if !self.disk {
+            return None;
+        }
+
+        std::fs::metadata(self.path(info_hash))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Removes the oldest torrent files from the cache dir until their total
+    /// size is not greater than `max_disk_size`.
+    fn evict_oldest_files(&self, max_disk_size: u64) -> io::Result<()> {
+        let mut files = self.torrent_files()?;
+
+        let mut disk_size: u64 = files.iter().map(|(_, len, _)| len).sum();
+
+        files.sort();
+
+        for (_, len, path) in files {
+            if disk_size <= max_disk_size {
+                break;
+            }
+
+            info!("removing oldest torrent from cache: {}", path.display());
+
+            std::fs::remove_file(path)?;
+
+            disk_size -= len;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the modification time, size and path of the torrent files in
+    /// the cache dir.
+    fn torrent_files(&self) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+        let mut files = vec![];
+
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+
+            if path
+                .extension()
+                .is_none_or(|extension| extension != "torrent")
+            {
+                continue;
+            }
+
+            let metadata = std::fs::metadata(&path)?;
+
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        Ok(files)
+    }
+
+    /// Replaces the TTL, for example when the config is reloaded. It also
+    /// applies to the torrents already cached.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.write().expect("cache TTL lock poisoned") = ttl;
+    }
+
+    fn is_expired(&self, cached_at: SystemTime) -> bool {
+        let Some(ttl) = *self.ttl.read().expect("cache TTL lock poisoned") else {
+            return false;
+        };
+
+        // A time in the future (clock changes) is not considered expired.
+        self.clock
+            .now()
+            .duration_since(cached_at)
+            .is_ok_and(|age| age > ttl)
+    }
+}
+
+/// Counts a disk write while it's alive.
+struct WriteInProgress<'a> {
+    count: &'a AtomicUsize,
+}
+
+impl<'a> WriteInProgress<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self { count }
+    }
+}
+
+impl Drop for WriteInProgress<'_> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Writes the torrent file. It's written to a temporary file first, so a
+/// partially written file is never loaded.
+fn write_file(filepath: &Utf8PathBuf, data: &[u8]) -> io::Result<()> {
+    let tmp_filepath = filepath.with_extension("torrent.tmp");
+
+    let mut file = File::create(&tmp_filepath)?;
+
+    file.write_all(data)?;
+    file.sync_all()?;
+
+    std::fs::rename(tmp_filepath, filepath)
+}
+
+/// Returns true if the bytes are a valid torrent file for the info-hash.
+fn is_torrent_for(bytes: &[u8], info_hash: &InfoHash) -> bool {
+    torrent_from_bytes::<ByteBuf>(bytes)
+        .is_ok_and(|torrent| InfoHash(torrent.info_hash.0) == *info_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use bytes::Bytes;
+    use camino::Utf8PathBuf;
+    use librqbit::{torrent_from_bytes, ByteBuf};
+    use tempfile::TempDir;
+
+    use super::Cache;
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::clock::StoppedClock;
+    use crate::config::Cache as CacheConfig;
+
+    fn info_hash(n: u8) -> InfoHash {
+        InfoHash::from_str(&format!("{n:02x}").repeat(20)).unwrap()
+    }
+
+    /// Returns a valid torrent file with the given name and its info-hash.
+    fn torrent(name: &str) -> (InfoHash, Bytes) {
+        let mut bytes = format!(
+            "d4:infod6:lengthi11e4:name{}:{name}12:piece lengthi16384e6:pieces20:",
+            name.len()
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&[0u8; 20]);
+        bytes.extend_from_slice(b"ee");
+
+        let info_hash = InfoHash(torrent_from_bytes::<ByteBuf>(&bytes).unwrap().info_hash.0);
+
+        (info_hash, Bytes::from(bytes))
+    }
+
+    fn cache(
+        dir: &TempDir,
+        ttl: Option<Duration>,
+        max_entries: usize,
+    ) -> (Cache, Arc<StoppedClock>) {
+        cache_with_config(
+            dir,
+            &CacheConfig {
+                ttl,
+                max_entries,
+                disk: true,
+                max_disk_size: None,
+                max_concurrent_disk_writes: 1,
+                negative_ttl: None,
+            },
+        )
+    }
+
+    fn cache_with_config(dir: &TempDir, config: &CacheConfig) -> (Cache, Arc<StoppedClock>) {
+        let clock = Arc::new(StoppedClock::new(SystemTime::now()));
+        let cache_dir = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+        let cache = Cache::with_clock(cache_dir, config, clock.clone());
+
+        (cache, clock)
+    }
+
+    #[tokio::test]
+    async fn it_should_return_a_cached_torrent() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 10);
+
+        cache
+            .add(&info_hash(1), &Bytes::from("torrent"))
+            .await
+            .unwrap();
+
+        assert!(cache.contains(&info_hash(1)));
+        assert_eq!(
+            cache.get(&info_hash(1)).unwrap().bytes,
+            Bytes::from("torrent")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_treat_torrents_older_than_the_ttl_as_misses() {
+        let dir = TempDir::new().unwrap();
+        let (cache, clock) = cache(&dir, Some(Duration::from_secs(100)), 10);
+
+        cache
+            .add(&info_hash(1), &Bytes::from("torrent"))
+            .await
+            .unwrap();
+
+        clock.advance(Duration::from_secs(50));
+
+        assert!(cache.contains(&info_hash(1)));
+
+        clock.advance(Duration::from_secs(51));
+
+        assert!(!cache.contains(&info_hash(1)));
+        assert!(cache.get(&info_hash(1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_evict_the_least_recently_used_torrent_when_the_max_number_of_entries_is_reached(
+    ) {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 2);
+
+        cache
+            .add(&info_hash(1), &Bytes::from("torrent 1"))
+            .await
+            .unwrap();
+        cache
+            .add(&info_hash(2), &Bytes::from("torrent 2"))
+            .await
+            .unwrap();
+
+        // Use the first one so the second one is the least recently used.
+        cache.get(&info_hash(1)).unwrap();
+
+        cache
+            .add(&info_hash(3), &Bytes::from("torrent 3"))
+            .await
+            .unwrap();
+
+        assert_eq!(cache.len(), 2);
+
+        let memory = cache.memory.lock().unwrap();
+        assert!(memory.contains(&info_hash(1)));
+        assert!(!memory.contains(&info_hash(2)));
+        assert!(memory.contains(&info_hash(3)));
+    }
+
+    #[tokio::test]
+    async fn it_should_load_evicted_torrents_from_the_cache_dir() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 1);
+
+        let (info_hash_1, torrent_1) = torrent("torrent 1");
+        let (info_hash_2, torrent_2) = torrent("torrent 2");
+
+        cache.add(&info_hash_1, &torrent_1).await.unwrap();
+        cache.add(&info_hash_2, &torrent_2).await.unwrap();
+
+        assert_eq!(cache.get(&info_hash_1).unwrap().bytes, torrent_1);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_size_of_the_torrents_in_memory_and_the_cache_dir() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 1);
+
+        let (info_hash_1, torrent_1) = torrent("torrent 1");
+        let (info_hash_2, torrent_2) = torrent("torrent 2");
+
+        cache.add(&info_hash_1, &torrent_1).await.unwrap();
+        cache.add(&info_hash_2, &torrent_2).await.unwrap();
+
+        assert_eq!(cache.memory_size(), torrent_2.len() as u64);
+        assert_eq!(
+            cache.disk_size().unwrap(),
+            Some((torrent_1.len() + torrent_2.len()) as u64)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_list_the_torrents_in_memory_and_the_cache_dir_sorted_by_info_hash() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 1);
+
+        cache
+            .add(&info_hash(2), &Bytes::from("torrent 2"))
+            .await
+            .unwrap();
+        cache
+            .add(&info_hash(1), &Bytes::from("torrent"))
+            .await
+            .unwrap();
+        std::fs::write(dir.path().join("not-an-info-hash.torrent"), "torrent").unwrap();
+
+        let entries = cache.entries().unwrap();
+
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.info_hash, entry.size))
+                .collect::<Vec<_>>(),
+            vec![(info_hash(1), 7), (info_hash(2), 9)]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_never_exceed_the_max_number_of_concurrent_disk_writes() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache_with_config(
+            &dir,
+            &CacheConfig {
+                ttl: None,
+                max_entries: 10,
+                disk: true,
+                max_disk_size: None,
+                max_concurrent_disk_writes: 2,
+                negative_ttl: None,
+            },
+        );
+        let cache = Arc::new(cache);
+
+        let writes = (1..=10)
+            .map(|n| {
+                let cache = cache.clone();
+                tokio::spawn(async move { cache.add(&info_hash(n), &Bytes::from("torrent")).await })
+            })
+            .collect::<Vec<_>>();
+
+        let mut max_in_progress = 0;
+
+        while !writes.iter().all(tokio::task::JoinHandle::is_finished) {
+            max_in_progress = max_in_progress.max(cache.disk_writes_in_progress());
+            tokio::task::yield_now().await;
+        }
+
+        for write in writes {
+            write.await.unwrap().unwrap();
+        }
+
+        assert!(max_in_progress <= 2);
+        assert_eq!(cache.disk_writes_in_progress(), 0);
+        assert!((1..=10).all(|n| cache.path(&info_hash(n)).exists()));
+    }
+
+    #[tokio::test]
+    async fn it_should_remove_torrents_from_memory_and_the_cache_dir() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 10);
+
+        cache
+            .add(&info_hash(1), &Bytes::from("torrent 1"))
+            .await
+            .unwrap();
+        cache
+            .add(&info_hash(2), &Bytes::from("torrent 2"))
+            .await
+            .unwrap();
+        cache
+            .add(&info_hash(3), &Bytes::from("torrent 3"))
+            .await
+            .unwrap();
+
+        cache.remove(&info_hash(1)).unwrap();
+
+        assert!(!cache.contains(&info_hash(1)));
+        assert!(!cache.path(&info_hash(1)).exists());
+        assert!(cache.contains(&info_hash(2)));
+
+        cache.clear().unwrap();
+
+        assert!(cache.is_empty());
+        assert!(!cache.contains(&info_hash(2)));
+        assert!(!cache.path(&info_hash(3)).exists());
+    }
+
+    #[tokio::test]
+    async fn it_should_not_write_torrents_to_the_cache_dir_when_the_disk_cache_is_disabled() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache_with_config(
+            &dir,
+            &CacheConfig {
+                ttl: None,
+                max_entries: 1,
+                disk: false,
+                max_disk_size: None,
+                max_concurrent_disk_writes: 1,
+                negative_ttl: None,
+            },
+        );
+
+        let (info_hash_1, torrent_1) = torrent("torrent 1");
+        let (info_hash_2, torrent_2) = torrent("torrent 2");
+
+        cache.add(&info_hash_1, &torrent_1).await.unwrap();
+        cache.add(&info_hash_2, &torrent_2).await.unwrap();
+
+        assert!(!cache.path(&info_hash_1).exists());
+        assert!(!cache.contains(&info_hash_1));
+        assert!(cache.get(&info_hash_1).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_remove_the_oldest_torrent_files_when_the_max_disk_size_is_exceeded() {
+        let dir = TempDir::new().unwrap();
+
+        let (info_hash_1, torrent_1) = torrent("torrent 1");
+        let (info_hash_2, torrent_2) = torrent("torrent 2");
+
+        let (cache, _clock) = cache_with_config(
+            &dir,
+            &CacheConfig {
+                ttl: None,
+                max_entries: 10,
+                disk: true,
+                max_disk_size: Some(torrent_1.len() as u64),
+                max_concurrent_disk_writes: 1,
+                negative_ttl: None,
+            },
+        );
+
+        cache.add(&info_hash_1, &torrent_1).await.unwrap();
+
+        std::fs::File::options()
+            .write(true)
+            .open(cache.path(&info_hash_1))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(100))
+            .unwrap();
+
+        cache.add(&info_hash_2, &torrent_2).await.unwrap();
+
+        assert!(!cache.path(&info_hash_1).exists());
+        assert!(cache.path(&info_hash_2).exists());
+    }
+
+    #[test]
+    fn it_should_not_return_corrupt_torrents_from_the_cache_dir() {
+        let dir = TempDir::new().unwrap();
+        let (cache, _clock) = cache(&dir, None, 10);
+
+        let (info_hash, _torrent) = torrent("torrent");
+        let (_other_info_hash, other_torrent) = torrent("other torrent");
+
+        std::fs::write(cache.path(&info_hash), other_torrent).unwrap();
+
+        assert!(cache.get(&info_hash).is_err());
+        assert!(!cache.path(&info_hash).exists());
+    }
 }
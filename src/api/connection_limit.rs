@@ -0,0 +1,243 @@
+//! Global limit of open connections.
+//!
+//! The rate limit is per client IP, so many clients can still exhaust the
+//! resources of a small instance. With a cap on the connections open at the
+//! same time, the new ones are closed right after they are accepted until
+//! one of the others is closed.
+use std::future::Ready;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use axum_server::accept::Accept;
+use futures_util::future::Either;
+use pin_project_lite::pin_project;
+use prometheus_client::metrics::gauge::Gauge;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::debug;
+
+pub struct OpenConnections {
+    /// New connections are closed when there are already that many. There
+    /// is no limit when it's `None`.
+    max: Option<usize>,
+    count: AtomicUsize,
+    /// Exposes the count in the metrics.
+    gauge: Gauge,
+}
+
+/// An open connection. It's removed from the count when it's dropped.
+pub struct OpenConnection {
+    connections: Arc<OpenConnections>,
+}
+
+impl OpenConnections {
+    #[must_use]
+    pub fn new(max: Option<usize>, gauge: Gauge) -> Self {
+        Self {
+            max,
+            count: AtomicUsize::new(0),
+            gauge,
+        }
+    }
+
+    /// Adds a connection to the count, unless the limit is reached.
+    #[must_use]
+    pub fn try_add(self: &Arc<Self>) -> Option<OpenConnection> {
+        let count = self
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                match self.max {
+                    Some(max) if count >= max => None,
+                    _ => Some(count + 1),
+                }
+            })
+            .ok()?;
+
+        self.gauge.set(gauge_value(count + 1));
+
+        Some(OpenConnection {
+            connections: self.clone(),
+        })
+    }
+
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        let count = self.connections.count.fetch_sub(1, Ordering::AcqRel);
+        self.connections.gauge.set(gauge_value(count - 1));
+    }
+}
+
+/// Counts the open connections and closes the new ones when the limit is
+/// reached. The other connections are accepted by the inner acceptor.
+#[derive(Clone)]
+pub struct ConnectionLimitAcceptor<A> {
+    inner: A,
+    connections: Arc<OpenConnections>,
+}
+
+impl<A> ConnectionLimitAcceptor<A> {
+    #[must_use]
+    pub fn new(inner: A, connections: Arc<OpenConnections>) -> Self {
+        Self { inner, connections }
+    }
+}
+
+impl<A, I, S> Accept<I, S> for ConnectionLimitAcceptor<A>
+where
+    A: Accept<CountedStream<I>, S>,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Either<Ready<io::Result<(A::Stream, A::Service)>>, A::Future>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let Some(connection) = self.connections.try_add() else {
+            debug!("too many open connections, closing the new connection");
+
+            // The stream is dropped, so the connection is closed.
+            return Either::Left(std::future::ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "too many open connections",
+            ))));
+        };
+
+        Either::Right(self.inner.accept(
+            CountedStream {
+                inner: stream,
+                _connection: connection,
+            },
+            service,
+        ))
+    }
+}
+
+pin_project! {
+    /// A stream counted as an open connection until it's dropped.
+    pub struct CountedStream<I> {
+        #[pin]
+        inner: I,
+        _connection: OpenConnection,
+    }
+}
+
+impl<I: AsyncRead> AsyncRead for CountedStream<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().inner.poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite> AsyncWrite for CountedStream<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().inner.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+fn gauge_value(count: usize) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use axum::Router;
+    use axum_server::accept::DefaultAcceptor;
+    use prometheus_client::metrics::gauge::Gauge;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{ConnectionLimitAcceptor, OpenConnections};
+
+    /// Sends a request and returns the response, which is empty if the
+    /// connection was closed.
+    async fn request(address: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+
+        // The server may have closed the connection already.
+        let _ = stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await;
+
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response).await;
+
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[tokio::test]
+    async fn it_should_close_the_new_connections_while_the_limit_is_reached() {
+        let gauge = Gauge::default();
+        let connections = Arc::new(OpenConnections::new(Some(1), gauge.clone()));
+
+        let socket = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+        let acceptor = ConnectionLimitAcceptor::new(DefaultAcceptor::new(), connections.clone());
+
+        tokio::spawn(async move {
+            axum_server::from_tcp(socket)
+                .acceptor(acceptor)
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        // An idle connection takes the only slot.
+        let idle = TcpStream::connect(address).await.unwrap();
+
+        while connections.count() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(gauge.get(), 1);
+        assert_eq!(request(address).await, "");
+
+        drop(idle);
+
+        while connections.count() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(request(address).await.starts_with("HTTP/1.1 200 OK"));
+    }
+}
@@ -0,0 +1,157 @@
+//! Guard against filling the disk of the session output dir.
+//!
+//! The `BitTorrent` client writes to the session output dir, and the whole
+//! service wedges when its disk is full. The free space is checked on startup
+//! and periodically. While it's below the minimum, the torrents are not
+//! resolved, only the cached ones are served, and the resolutions get a
+//! `503 Service Unavailable` until space is freed.
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{info, warn};
+
+use crate::config::FreeDiskSpace;
+
+pub struct DiskSpaceGuard {
+    /// A path in the filesystem checked.
+    path: Utf8PathBuf,
+    /// `None` when the guard is disabled.
+    config: Option<FreeDiskSpace>,
+    low: AtomicBool,
+}
+
+impl DiskSpaceGuard {
+    /// It checks the free space right away, so the service never starts
+    /// resolving torrents with the disk full.
+    #[must_use]
+    pub fn new(path: &Utf8Path, config: Option<FreeDiskSpace>) -> Self {
+        let guard = Self {
+            path: path.to_path_buf(),
+            config,
+            low: AtomicBool::new(false),
+        };
+
+        guard.check();
+
+        guard
+    }
+
+    /// Returns true if the free space was below the minimum in the last
+    /// check.
+    #[must_use]
+    pub fn is_low(&self) -> bool {
+        self.low.load(Ordering::Relaxed)
+    }
+
+    /// Checks the free space every check interval. It returns right away if
+    /// the guard is disabled.
+    pub async fn run(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+
+        let mut interval = tokio::time::interval(config.check_interval);
+
+        // The first tick completes immediately, and the space was checked
+        // on creation.
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+
+            self.check();
+        }
+    }
+
+    /// Updates the state with the current free space, logging the changes.
+    /// The state is kept when the free space can't be read.
+    fn check(&self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+
+        let free = match free_space(&self.path) {
+            Ok(free) => free,
+            Err(err) => {
+                warn!("error reading the free disk space of {}: {err}", self.path);
+                return;
+            }
+        };
+
+        let low = free < config.min_free_bytes;
+
+        let was_low = self.low.swap(low, Ordering::Relaxed);
+
+        if low && !was_low {
+            warn!(
+                "free disk space of {} is {free} bytes, below the minimum of {} bytes: \
+                 only cached torrents are served",
+                self.path, config.min_free_bytes
+            );
+        } else if !low && was_low {
+            info!(
+                "free disk space of {} is {free} bytes again: torrents are resolved",
+                self.path
+            );
+        }
+    }
+}
+
+/// Returns the space available to unprivileged users in the filesystem of
+/// the path, in bytes.
+#[cfg(unix)]
+fn free_space(path: &Utf8Path) -> io::Result<u64> {
+    let stats = rustix::fs::statvfs(path.as_std_path())?;
+
+    Ok(stats.f_bavail.saturating_mul(stats.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Utf8Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "the free disk space can only be read on Unix",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use camino::Utf8PathBuf;
+    use tempfile::TempDir;
+
+    use super::DiskSpaceGuard;
+    use crate::config::FreeDiskSpace;
+
+    fn guard(dir: &TempDir, min_free_bytes: u64) -> DiskSpaceGuard {
+        DiskSpaceGuard::new(
+            &Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            Some(FreeDiskSpace {
+                min_free_bytes,
+                check_interval: Duration::from_secs(60),
+            }),
+        )
+    }
+
+    #[test]
+    fn it_should_be_low_when_the_free_space_is_below_the_minimum() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(guard(&dir, u64::MAX).is_low());
+        assert!(!guard(&dir, 0).is_low());
+    }
+
+    #[test]
+    fn it_should_never_be_low_when_it_is_disabled() {
+        let dir = TempDir::new().unwrap();
+
+        let guard = DiskSpaceGuard::new(
+            &Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            None,
+        );
+
+        assert!(!guard.is_low());
+    }
+}
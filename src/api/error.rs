@@ -0,0 +1,383 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use hyper::header::HeaderValue;
+use hyper::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::AppState;
+
+/// Errors returned by the API endpoints.
+///
+/// They are serialized as JSON, with a stable `error` code for each error
+/// and a human-readable `message`:
+///
+/// ```json
+/// {"error":"invalid_info_hash","message":"Invalid info hash: ..."}
+/// ```
+///
+/// Clients that prefer `text/plain` (using the `Accept` header) get only the
+/// message. See [`negotiate_error_format`].
+#[derive(Error, Debug, Clone)]
+pub enum ApiError {
+    #[error("Invalid info hash: it must be 40 hex characters (v1) or 32 base32 characters")]
+    InvalidInfoHashLength,
+    #[error(
+        "Invalid info hash: v2 info hashes (64 hex characters, with or without the 1220 \
+         multihash prefix) are not supported yet, use the v1 info hash"
+    )]
+    UnsupportedInfoHashV2,
+    #[error(
+        "Invalid info hash: it contains non-hex characters \
+         (or non-base32 characters for the 32 characters encoding)"
+    )]
+    InvalidInfoHashCharacters,
+    #[error(
+        "Invalid timeout: timeout_ms must be between {} and {}",
+        min.as_millis(),
+        max.as_millis()
+    )]
+    InvalidTimeout { min: Duration, max: Duration },
+    #[error("Too many info hashes: the maximum is {max}")]
+    BatchTooLarge { max: usize },
+    #[error("The info hash could not be resolved in time")]
+    ResolveTimeout,
+    #[error("Torrent not found: no peers with the torrent metadata were found")]
+    NotFound,
+    #[error("Torrent not found: it's not cached and only cached torrents are served")]
+    NotCached,
+    #[error("Not enough peers have the torrent: the minimum is {min}")]
+    NotEnoughPeers { min: usize },
+    #[error("Torrent not found: no peers served a valid piece of the torrent content")]
+    ContentNotVerified,
+    #[error("The disk is almost full: only cached torrents are served")]
+    LowDiskSpace,
+    #[error("Missing or invalid token")]
+    Unauthorized,
+    #[error("The torrent is not available for legal reasons")]
+    Denied,
+    #[error("The torrent is not in the allowlist")]
+    NotAllowed,
+    #[error("Too many torrents are being resolved, try again later")]
+    Busy,
+    #[error("Too many requests, try again in {retry_after} seconds")]
+    TooManyRequests { retry_after: u64 },
+    #[error("{message}")]
+    Maintenance { message: String, status: StatusCode },
+    #[error("The torrent metadata is larger than the maximum of {max} bytes")]
+    MetadataTooLarge { max: usize },
+    #[error("The torrent has more files than the maximum of {max}")]
+    TooManyFiles { max: usize },
+    #[error("The torrent metadata returned by the peers does not match the info hash")]
+    InfoHashMismatch,
+    #[error(
+        "The torrent was added to the BitTorrent client for downloading instead of only listing \
+         it. It was removed, so the request can be retried"
+    )]
+    AddedForDownloading,
+    #[error("BitTorrent client error")]
+    ClientError,
+    #[error("Invalid torrent metadata")]
+    InvalidTorrent,
+    #[error("Torrents cache error")]
+    CacheError,
+    #[error("The config could not be reloaded: {reason}")]
+    ReloadFailed { reason: String },
+}
+
+/// The JSON error response body.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ErrorBody {
+    pub error: String,
+    pub message: String,
+}
+
+impl ApiError {
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidInfoHashLength
+            | ApiError::InvalidInfoHashCharacters
+            | ApiError::UnsupportedInfoHashV2
+            | ApiError::InvalidTimeout { .. }
+            | ApiError::BatchTooLarge { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Denied => StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+            ApiError::NotAllowed => StatusCode::FORBIDDEN,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::NotFound
+            | ApiError::NotCached
+            | ApiError::NotEnoughPeers { .. }
+            | ApiError::ContentNotVerified => StatusCode::NOT_FOUND,
+            ApiError::ResolveTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Busy | ApiError::LowDiskSpace => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Maintenance { status, .. } => *status,
+            ApiError::MetadataTooLarge { .. } | ApiError::TooManyFiles { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            ApiError::InfoHashMismatch => StatusCode::BAD_GATEWAY,
+            ApiError::AddedForDownloading
+            | ApiError::ClientError
+            | ApiError::InvalidTorrent
+            | ApiError::CacheError
+            | ApiError::ReloadFailed { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Returns true if the same request could succeed later, for example
+    /// when peers with the torrent are found. Invalid requests are not.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ApiError::ResolveTimeout
+                | ApiError::NotFound
+                | ApiError::NotEnoughPeers { .. }
+                | ApiError::ContentNotVerified
+                | ApiError::Busy
+                | ApiError::TooManyRequests { .. }
+                | ApiError::LowDiskSpace
+                | ApiError::Maintenance { .. }
+        )
+    }
+
+    /// The stable error code. Clients can rely on it, unlike the message.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidInfoHashLength | ApiError::InvalidInfoHashCharacters => {
+                "invalid_info_hash"
+            }
+            ApiError::UnsupportedInfoHashV2 => "unsupported_info_hash_v2",
+            ApiError::InvalidTimeout { .. } => "invalid_timeout",
+            ApiError::BatchTooLarge { .. } => "batch_too_large",
+            ApiError::ResolveTimeout => "resolve_timeout",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Denied => "denied",
+            ApiError::NotAllowed => "not_allowed",
+            ApiError::NotFound => "not_found",
+            ApiError::NotCached => "not_cached",
+            ApiError::NotEnoughPeers { .. } => "not_enough_peers",
+            ApiError::ContentNotVerified => "content_not_verified",
+            ApiError::Busy => "busy",
+            ApiError::TooManyRequests { .. } => "too_many_requests",
+            ApiError::LowDiskSpace => "low_disk_space",
+            ApiError::Maintenance { .. } => "maintenance",
+            ApiError::MetadataTooLarge { .. } => "metadata_too_large",
+            ApiError::TooManyFiles { .. } => "too_many_files",
+            ApiError::InfoHashMismatch => "info_hash_mismatch",
+            ApiError::AddedForDownloading => "added_for_downloading",
+            ApiError::ClientError => "client_error",
+            ApiError::InvalidTorrent => "invalid_torrent",
+            ApiError::CacheError => "cache_error",
+            ApiError::ReloadFailed { .. } => "reload_failed",
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: self.code().to_string(),
+            message: self.to_string(),
+        };
+
+        let mut response = (self.status_code(), Json(body)).into_response();
+
+        if matches!(self, ApiError::Unauthorized) {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+
+        // Keep the error, so the format can be negotiated later.
+        response.extensions_mut().insert(self);
+
+        response
+    }
+}
+
+/// Middleware that adds the `Retry-After` header to the transient errors, so
+/// well-behaved clients can back off.
+pub async fn add_retry_after(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+
+    // The rate limited requests can be retried when the next token is
+    // available.
+    let retry_after = match response.extensions().get::<ApiError>() {
+        Some(ApiError::TooManyRequests { retry_after }) => Some(*retry_after),
+        Some(err) if err.is_transient() => Some(app_state.config.api.retry_after.as_secs()),
+        _ => None,
+    };
+
+    if let Some(retry_after) = retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+
+    response
+}
+
+/// Middleware that returns the API errors as `text/plain` when the client
+/// prefers it to JSON in the `Accept` header. The other headers of the error
+/// response are kept.
+pub async fn negotiate_error_format(request: Request, next: Next) -> Response {
+    let plain_text = request
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(prefers_plain_text);
+
+    let response = next.run(request).await;
+
+    if !plain_text {
+        return response;
+    }
+
+    let Some(message) = response
+        .extensions()
+        .get::<ApiError>()
+        .map(ToString::to_string)
+    else {
+        return response;
+    };
+
+    let (mut parts, _body) = response.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    Response::from_parts(parts, Body::from(message))
+}
+
+/// Returns true if `text/plain` has a higher quality than `application/json`
+/// in the `Accept` header value.
+fn prefers_plain_text(accept: &str) -> bool {
+    quality(accept, "text", "plain") > quality(accept, "application", "json")
+}
+
+/// Returns the quality of the media type in the `Accept` header value using
+/// the most specific media range that matches it, or 0 if none matches.
+pub(crate) fn quality(accept: &str, media_type: &str, subtype: &str) -> f32 {
+    let mut best: Option<(u8, f32)> = None;
+
+    for media_range in accept.split(',') {
+        let mut params = media_range.split(';').map(str::trim);
+
+        let Some((range_type, range_subtype)) =
+            params.next().and_then(|range| range.split_once('/'))
+        else {
+            continue;
+        };
+
+        let specificity = match (range_type, range_subtype) {
+            (t, s) if t.eq_ignore_ascii_case(media_type) && s.eq_ignore_ascii_case(subtype) => 2,
+            (t, "*") if t.eq_ignore_ascii_case(media_type) => 1,
+            ("*", "*") => 0,
+            _ => continue,
+        };
+
+        let q = params
+            .filter_map(|param| param.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if best.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+            best = Some((specificity, q));
+        }
+    }
+
+    best.map_or(0.0, |(_, q)| q)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::response::IntoResponse;
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use hyper::{header, Request, StatusCode};
+    use tower::ServiceExt;
+
+    use super::{negotiate_error_format, prefers_plain_text, ApiError, ErrorBody};
+
+    #[tokio::test]
+    async fn it_should_return_the_error_code_and_message_as_json() {
+        let response = ApiError::InvalidInfoHashLength.into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ErrorBody>(&body).unwrap(),
+            ErrorBody {
+                error: "invalid_info_hash".to_string(),
+                message: ApiError::InvalidInfoHashLength.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_prefer_plain_text_only_when_it_has_a_higher_quality_than_json() {
+        assert!(prefers_plain_text("text/plain"));
+        assert!(prefers_plain_text("text/*"));
+        assert!(prefers_plain_text("text/plain, application/json;q=0.5"));
+        assert!(!prefers_plain_text("application/json"));
+        assert!(!prefers_plain_text("*/*"));
+        assert!(!prefers_plain_text("text/plain, application/json"));
+        assert!(!prefers_plain_text("text/plain;q=0.5, */*"));
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_the_error_headers_in_plain_text() {
+        let router = Router::new()
+            .route("/", get(|| async { ApiError::Unauthorized }))
+            .layer(middleware::from_fn(negotiate_error_format));
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/")
+                    .header(header::ACCEPT, "text/plain")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, ApiError::Unauthorized.to_string());
+    }
+
+    #[test]
+    fn it_should_only_consider_transient_the_errors_that_could_succeed_later() {
+        assert!(ApiError::NotFound.is_transient());
+        assert!(ApiError::Busy.is_transient());
+        assert!(!ApiError::InvalidInfoHashLength.is_transient());
+        assert!(!ApiError::NotCached.is_transient());
+    }
+}
@@ -0,0 +1,184 @@
+//! Filename templates for the downloaded torrent files.
+//!
+//! The template is a string with placeholders between braces, like
+//! `{name}-{short_hash}.torrent`. The placeholders are:
+//!
+//! - `{name}`: the torrent name.
+//! - `{info_hash}`: the 40 hex characters info hash.
+//! - `{short_hash}`: the first 8 hex characters of the info hash.
+use crate::bit_torrent::info_hash::InfoHash;
+
+/// The number of hex characters of the `{short_hash}` placeholder.
+const SHORT_HASH_LENGTH: usize = 8;
+
+/// The maximum number of characters of the sanitized names.
+const MAX_NAME_LENGTH: usize = 200;
+
+enum Part<'a> {
+    Literal(&'a str),
+    Name,
+    InfoHash,
+    ShortHash,
+}
+
+/// Checks the template only contains known placeholders, so invalid
+/// templates are rejected when the configuration is loaded.
+///
+/// # Errors
+///
+/// Will return an error with the reason if the template is empty, has unknown
+/// placeholders or unbalanced braces.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    parse(template).map(|_| ())
+}
+
+/// Returns true if the template uses the torrent name, which requires
+/// parsing the torrent.
+#[must_use]
+pub fn uses_name(template: &str) -> bool {
+    parse(template).is_ok_and(|parts| parts.iter().any(|part| matches!(part, Part::Name)))
+}
+
+/// Builds the filename replacing the placeholders. Path separators in the
+/// torrent name are replaced with `_`.
+///
+/// An invalid template is returned as is.
+#[must_use]
+pub fn render(template: &str, info_hash: &InfoHash, name: &str) -> String {
+    let Ok(parts) = parse(template) else {
+        return template.to_string();
+    };
+
+    let info_hash = info_hash.to_hex_string();
+
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Literal(literal) => (*literal).to_string(),
+            Part::Name => name.replace(['/', '\\'], "_"),
+            Part::InfoHash => info_hash.clone(),
+            Part::ShortHash => info_hash[..SHORT_HASH_LENGTH].to_string(),
+        })
+        .collect()
+}
+
+/// Makes a name provided by the client safe to use in a filename, like the
+/// display name of magnet links. Characters not allowed in filenames on the
+/// common filesystems are replaced with `_`, and leading dots and whitespaces
+/// are removed.
+///
+/// It returns `None` if nothing is left.
+#[must_use]
+pub fn sanitize(name: &str) -> Option<String> {
+    let sanitized: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect();
+
+    let sanitized = sanitized.trim_start_matches(['.', ' ']).trim_end();
+
+    if sanitized.is_empty() {
+        None
+    } else {
+        Some(sanitized.to_string())
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    if template.is_empty() {
+        return Err("it can't be empty".to_string());
+    }
+
+    let mut parts = vec![];
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        match rest.find(['{', '}']) {
+            Some(start) if rest[start..].starts_with('}') => {
+                return Err("unexpected `}`".to_string());
+            }
+            Some(start) => {
+                if start > 0 {
+                    parts.push(Part::Literal(&rest[..start]));
+                }
+
+                let Some(end) = rest[start..].find('}') else {
+                    return Err("unclosed `{`".to_string());
+                };
+
+                let placeholder = &rest[start + 1..start + end];
+
+                parts.push(match placeholder {
+                    "name" => Part::Name,
+                    "info_hash" => Part::InfoHash,
+                    "short_hash" => Part::ShortHash,
+                    _ => return Err(format!("unknown placeholder `{{{placeholder}}}`")),
+                });
+
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                parts.push(Part::Literal(rest));
+                rest = "";
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{render, sanitize, uses_name, validate_template};
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    #[test]
+    fn it_should_replace_the_placeholders() {
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        assert_eq!(
+            render("{name}-{short_hash}.torrent", &info_hash, "ubuntu/desktop"),
+            "ubuntu_desktop-443c7602.torrent"
+        );
+        assert_eq!(
+            render("{info_hash}.torrent", &info_hash, "ubuntu"),
+            "443c7602b4fde83d1154d6d9da48808418b181b6.torrent"
+        );
+        assert!(uses_name("{name}.torrent"));
+        assert!(!uses_name("{info_hash}.torrent"));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_templates() {
+        assert!(validate_template("{name}.torrent").is_ok());
+        assert!(validate_template("").is_err());
+        assert!(validate_template("{size}.torrent").is_err());
+        assert!(validate_template("{name.torrent").is_err());
+        assert!(validate_template("name}.torrent").is_err());
+    }
+
+    #[test]
+    fn it_should_sanitize_the_names_provided_by_clients() {
+        assert_eq!(
+            sanitize("ubuntu desktop"),
+            Some("ubuntu desktop".to_string())
+        );
+        assert_eq!(
+            sanitize("../../etc/passwd"),
+            Some("_.._etc_passwd".to_string())
+        );
+        assert_eq!(sanitize("a:b*c?\n"), Some("a_b_c__".to_string()));
+        assert_eq!(sanitize(" .hidden "), Some("hidden".to_string()));
+        assert_eq!(sanitize("..."), None);
+        assert_eq!(sanitize(&"a".repeat(300)).unwrap().len(), 200);
+    }
+}
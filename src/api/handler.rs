@@ -1,17 +1,56 @@
-use axum::extract::{Path, State};
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path, Query, State};
 use axum::response::{Html, IntoResponse, Response};
+use axum::Json;
 use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use hyper::header::{HeaderName, HeaderValue};
 use hyper::{header, HeaderMap, StatusCode};
+use librqbit::{
+    torrent_from_bytes, torrent_from_bytes_ext, ByteBuf, ByteBufOwned, TorrentMetaV1,
+    TorrentMetaV1Info,
+};
+use ring::digest;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
 use std::sync::Arc;
-use tracing::{debug, error, info, trace};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, error, field, info, info_span, trace, warn, Instrument, Span};
 
+use crate::api::cache::CachedTorrent;
+use crate::api::error::{quality, ApiError};
+use crate::api::filename;
+use crate::api::placeholder;
+use crate::api::server_timing::{self, ServerTiming};
+use crate::bit_torrent::client::{ResolveMagnetError, ResolvedTorrent};
+use crate::bit_torrent::file_tree::Node;
 use crate::bit_torrent::info_hash::InfoHash;
+use crate::bit_torrent::metadata::Metadata;
+use crate::bit_torrent::torrent_file;
+use crate::config::{ETag, TorrentContentType};
+use crate::metrics::{CacheResult, Endpoint, Outcome};
+use crate::stats::Summary;
 
 use crate::AppState;
 
+/// The filename used when the torrent filename is empty.
+const FALLBACK_FILENAME: &str = "download.torrent";
+
+/// The version of the info hashes in the info hash version header. Only v1
+/// info hashes are supported.
+const INFO_HASH_VERSION: &str = "1";
+
+/// Maximum size of the chunks of the streamed torrent files.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Linked from the entrypoint.
+const DOCUMENTATION_URL: &str = "https://github.com/torrust/torrust-hash2torrent";
+
 /// The info hash URL path parameter.
 ///
 /// For example: ` http://127.0.0.1:3000/torrents/443c7602b4fde83d1154d6d9da48808418b181b6`.
@@ -19,98 +58,1695 @@ use crate::AppState;
 /// The info hash represents the value collected from the URL path parameter.
 /// It does not include validation as this is done by the API endpoint handler,
 /// in order to provide a more specific error message.
+///
+/// Both the hex (40 chars) and the base32 (32 chars) encodings are accepted.
+/// The value is passed as is to the parser because both encodings are
+/// case-insensitive and base32 is uppercase-canonical.
 #[derive(Deserialize)]
 pub struct InfoHashParam(pub String);
 
-impl InfoHashParam {
-    fn lowercase(&self) -> String {
-        self.0.to_lowercase()
+/// Query params for the endpoints that need to resolve the torrent.
+///
+/// For example: `http://127.0.0.1:3000/torrents/443c7602b4fde83d1154d6d9da48808418b181b6?timeout_ms=5000`.
+#[derive(Deserialize)]
+pub struct ResolveParams {
+    /// Maximum time to wait for the `BitTorrent` client to resolve the magnet
+    /// link. It must be within the bounds defined in the configuration.
+    pub timeout_ms: Option<u64>,
+}
+
+/// Query params for the torrent file endpoint.
+///
+/// For example: `http://127.0.0.1:3000/torrents/443c7602b4fde83d1154d6d9da48808418b181b6?cache_only=true`.
+#[derive(Deserialize)]
+pub struct TorrentParams {
+    /// See [`ResolveParams::timeout_ms`].
+    pub timeout_ms: Option<u64>,
+    /// Only return the torrent if it's cached. Otherwise, a `404` is returned
+    /// immediately, without resolving the magnet link. It's always the case
+    /// when the cache-only mode is enabled in the configuration.
+    #[serde(default)]
+    pub cache_only: bool,
+    /// The display name, like in magnet links. It's used as the torrent
+    /// name in the filename when the torrent doesn't have one.
+    pub dn: Option<String>,
+    /// With `magnet`, the client is redirected to the magnet link instead of
+    /// getting the torrent file.
+    #[serde(default)]
+    pub format: TorrentFormat,
+    /// Adds the configured trackers (and the ones in the remote list) to the
+    /// `announce-list` of the torrent file. The info hash doesn't change.
+    #[serde(default)]
+    pub inject_trackers: bool,
+}
+
+/// The format of the torrent endpoint response.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TorrentFormat {
+    /// The `.torrent` file.
+    #[default]
+    Torrent,
+    /// A `302 Found` redirect to the magnet link, including the configured
+    /// trackers and peers. The torrent is not resolved.
+    Magnet,
+}
+
+/// Query params for the metadata endpoint.
+///
+/// For example: `http://127.0.0.1:3000/torrents/443c7602b4fde83d1154d6d9da48808418b181b6/metadata?include_pieces=true`.
+#[derive(Deserialize)]
+pub struct MetadataParams {
+    /// See [`ResolveParams::timeout_ms`].
+    pub timeout_ms: Option<u64>,
+    /// Include the SHA-1 piece hashes. They are omitted by default because
+    /// there can be thousands of them.
+    #[serde(default)]
+    pub include_pieces: bool,
+}
+
+/// It returns the torrent file.
+///
+/// The info hash is used as the `ETag` by default, as the torrent file for
+/// an info hash never changes. If the `If-None-Match` header matches it, a
+/// `304 Not Modified` response is returned without resolving the torrent.
+/// With content `ETag`s (see [`ETag`]) the torrent is needed to compare them.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_metainfo_file_handler(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<TorrentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let start = Instant::now();
+    let mut timing = ServerTiming::default();
+
+    let mut response = match get_torrent_file(
+        &app_state,
+        client_addr,
+        &info_hash,
+        &params,
+        &headers,
+        &mut timing,
+    )
+    .await
+    {
+        Ok(TorrentFile::NotModified(etag)) => {
+            not_modified_response(&etag, app_state.config.api.torrent_max_age)
+        }
+        Ok(TorrentFile::Magnet(info_hash)) => magnet_redirect_response(&app_state, info_hash),
+        Ok(TorrentFile::Placeholder(info_hash)) => {
+            let bytes = placeholder::torrent(&info_hash);
+            let headers = placeholder_headers(&app_state, &info_hash, bytes.len());
+
+            (StatusCode::OK, headers, bytes).into_response()
+        }
+        Ok(TorrentFile::Resolved(info_hash, torrent, etag)) => {
+            let filename = torrent_filename(&app_state, &info_hash, &torrent, params.dn.as_deref());
+
+            torrent_file_response(
+                torrent,
+                &filename,
+                &info_hash.to_hex_string(),
+                &etag,
+                &app_state.config.api.info_hash_header_prefix,
+                app_state.config.api.torrent_content_type,
+                app_state.config.api.torrent_max_age,
+            )
+        }
+        Err(err) => err.into_response(),
+    };
+
+    timing.record(server_timing::TOTAL, start.elapsed());
+    response
+        .headers_mut()
+        .insert(server_timing::SERVER_TIMING, timing.header_value());
+
+    response
+}
+
+/// It returns the same headers as the `GET` request, including the
+/// `Content-Length` of the torrent file, but without the body.
+///
+/// The torrent is resolved (and cached) like in the `GET` request, so a
+/// subsequent `GET` request is served from the cache.
+#[allow(clippy::module_name_repetitions)]
+pub async fn head_metainfo_file_handler(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<TorrentParams>,
+    headers: HeaderMap,
+) -> Response {
+    let start = Instant::now();
+    let mut timing = ServerTiming::default();
+
+    let mut response = match get_torrent_file(
+        &app_state,
+        client_addr,
+        &info_hash,
+        &params,
+        &headers,
+        &mut timing,
+    )
+    .await
+    {
+        Ok(TorrentFile::NotModified(etag)) => {
+            not_modified_response(&etag, app_state.config.api.torrent_max_age)
+        }
+        Ok(TorrentFile::Magnet(info_hash)) => magnet_redirect_response(&app_state, info_hash),
+        Ok(TorrentFile::Placeholder(info_hash)) => {
+            let length = placeholder::torrent(&info_hash).len();
+
+            (
+                StatusCode::OK,
+                placeholder_headers(&app_state, &info_hash, length),
+            )
+                .into_response()
+        }
+        Ok(TorrentFile::Resolved(info_hash, torrent, etag)) => {
+            let mut headers = torrent_file_headers(
+                &torrent_filename(&app_state, &info_hash, &torrent, params.dn.as_deref()),
+                &info_hash.to_hex_string(),
+                &etag,
+                &app_state.config.api.info_hash_header_prefix,
+                app_state.config.api.torrent_content_type,
+                torrent.cached_at,
+                app_state.config.api.torrent_max_age,
+            );
+            headers.insert(
+                header::CONTENT_LENGTH,
+                HeaderValue::from(torrent.bytes.len()),
+            );
+
+            (StatusCode::OK, headers).into_response()
+        }
+        Err(err) => err.into_response(),
+    };
+
+    timing.record(server_timing::TOTAL, start.elapsed());
+    response
+        .headers_mut()
+        .insert(server_timing::SERVER_TIMING, timing.header_value());
+
+    response
+}
+
+/// Returns the filename of the torrent file using the configured template.
+///
+/// The torrent is only parsed when the template uses its name. If the torrent
+/// doesn't have a name, the sanitized display name is used, or the info hash
+/// if there is no display name either.
+fn torrent_filename(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    torrent: &CachedTorrent,
+    display_name: Option<&str>,
+) -> String {
+    let template = &app_state.config.api.filename_template;
+
+    let name = if filename::uses_name(template) {
+        torrent_from_bytes::<ByteBuf>(&torrent.bytes)
+            .ok()
+            .and_then(|torrent| torrent.info.name)
+            .map(|name| String::from_utf8_lossy(name.as_ref()).to_string())
+            .filter(|name| !name.trim().is_empty())
+            .or_else(|| display_name.and_then(filename::sanitize))
+            .unwrap_or_else(|| info_hash.to_hex_string())
+    } else {
+        String::new()
+    };
+
+    filename::render(template, info_hash, &name)
+}
+
+enum TorrentFile {
+    /// The client already has the torrent file with the `ETag`.
+    NotModified(String),
+    /// The client asked to be redirected to the magnet link.
+    Magnet(InfoHash),
+    /// The torrent file and its `ETag`.
+    Resolved(InfoHash, CachedTorrent, String),
+    /// The torrent could not be resolved, and a placeholder is served
+    /// instead of the error.
+    Placeholder(InfoHash),
+}
+
+/// Validates the request and returns the torrent file bytes, unless the
+/// client already has them.
+///
+/// The request is logged with structured fields (info hash, client IP, cache
+/// hit, duration and outcome), so logs can be parsed by log aggregators.
+async fn get_torrent_file(
+    app_state: &AppState,
+    client_addr: SocketAddr,
+    info_hash: &InfoHashParam,
+    params: &TorrentParams,
+    headers: &HeaderMap,
+    timing: &mut ServerTiming,
+) -> Result<TorrentFile, ApiError> {
+    let span = info_span!(
+        "torrent_request",
+        info_hash = field::Empty,
+        client_ip = %client_addr.ip(),
+        cache_hit = field::Empty,
+        duration_ms = field::Empty,
+        outcome = field::Empty,
+    );
+
+    async move {
+        let start = Instant::now();
+
+        let result = resolve_torrent_file(app_state, info_hash, params, headers, timing).await;
+
+        let span = Span::current();
+        span.record(
+            "duration_ms",
+            u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        );
+        span.record("outcome", request_outcome(&result));
+
+        info!("torrent request completed");
+
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+/// The outcome of a torrent request for the logs.
+fn request_outcome(result: &Result<TorrentFile, ApiError>) -> &'static str {
+    match result {
+        Ok(TorrentFile::Resolved(..)) => "success",
+        Ok(TorrentFile::NotModified(_)) => "not_modified",
+        Ok(TorrentFile::Magnet(_)) => "magnet_redirect",
+        Ok(TorrentFile::Placeholder(_)) => "placeholder",
+        Err(
+            ApiError::InvalidInfoHashLength
+            | ApiError::InvalidInfoHashCharacters
+            | ApiError::UnsupportedInfoHashV2
+            | ApiError::InvalidTimeout { .. },
+        ) => "invalid_request",
+        Err(ApiError::ResolveTimeout) => "timeout",
+        Err(ApiError::NotFound) => "not_found",
+        Err(ApiError::NotCached) => "not_cached",
+        Err(ApiError::Denied | ApiError::NotAllowed) => "denied",
+        Err(_) => "error",
+    }
+}
+
+async fn resolve_torrent_file(
+    app_state: &AppState,
+    info_hash: &InfoHashParam,
+    params: &TorrentParams,
+    headers: &HeaderMap,
+    timing: &mut ServerTiming,
+) -> Result<TorrentFile, ApiError> {
+    let info_hash = parse_info_hash(info_hash)?;
+
+    Span::current().record("info_hash", info_hash.to_hex_string());
+
+    app_state.access_list.check(&info_hash)?;
+
+    if params.format == TorrentFormat::Magnet {
+        return Ok(TorrentFile::Magnet(info_hash));
+    }
+
+    let resolve_params = ResolveParams {
+        timeout_ms: params.timeout_ms,
+    };
+
+    let timeout = resolve_timeout(app_state, &resolve_params)?;
+
+    let etag_source = app_state.config.api.etag;
+
+    if etag_source == ETag::InfoHash {
+        let etag = etag(&info_hash.to_hex_string());
+
+        if if_none_match(headers, &etag) {
+            return Ok(TorrentFile::NotModified(etag));
+        }
+    }
+
+    app_state.metrics.inc_requests(Endpoint::Torrent);
+
+    let start = Instant::now();
+    let cached_torrent = get_cached_torrent(app_state, &info_hash);
+    timing.record(server_timing::CACHE_LOOKUP, start.elapsed());
+
+    let result = if let Some(cached_torrent) = cached_torrent {
+        Ok(cached_torrent)
+    } else {
+        let start = Instant::now();
+        let result = get_uncached_torrent(app_state, &info_hash, timeout, params.cache_only).await;
+        timing.record(server_timing::DHT_RESOLVE, start.elapsed());
+
+        result
+    };
+
+    let mut torrent = match result {
+        Ok(torrent) => torrent,
+        // Only when the torrent is not found. The other errors are not about
+        // the torrent, and clients would keep the placeholder.
+        Err(
+            err @ (ApiError::NotFound
+            | ApiError::NotEnoughPeers { .. }
+            | ApiError::ResolveTimeout
+            | ApiError::ContentNotVerified),
+        ) if app_state.config.api.placeholder_on_failure => {
+            debug!("serving a placeholder for {info_hash}: {err}");
+            return Ok(TorrentFile::Placeholder(info_hash));
+        }
+        Err(err) => return Err(err),
+    };
+
+    if params.inject_trackers {
+        let trackers = configured_trackers(app_state, info_hash);
+
+        torrent.bytes = torrent_file::with_trackers(&torrent.bytes, &trackers).map_err(|err| {
+            error!("can't add the trackers to {info_hash}: {err}");
+            ApiError::InvalidTorrent
+        })?;
+    }
+
+    let etag = torrent_etag(etag_source, &info_hash, &torrent.bytes);
+
+    // Content `ETag`s are only known once the torrent is resolved.
+    if etag_source == ETag::Content && if_none_match(headers, &etag) {
+        return Ok(TorrentFile::NotModified(etag));
+    }
+
+    Ok(TorrentFile::Resolved(info_hash, torrent, etag))
+}
+
+/// It returns the torrent metadata (name, sizes and file list) as JSON.
+///
+/// If the `Accept` header prefers `application/x-bencode`, it returns the
+/// original bencoded `info` dictionary instead, so clients can re-hash it.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_metadata_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<MetadataParams>,
+    headers: HeaderMap,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    if let Err(err) = app_state.access_list.check(&info_hash) {
+        return err.into_response();
+    }
+
+    let resolve_params = ResolveParams {
+        timeout_ms: params.timeout_ms,
+    };
+
+    let timeout = match resolve_timeout(&app_state, &resolve_params) {
+        Ok(timeout) => timeout,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("req metadata: {}", info_hash.to_hex_string());
+
+    app_state.metrics.inc_requests(Endpoint::Metadata);
+
+    let response = if prefers_bencode(&headers) {
+        get_info_dict(&app_state, &info_hash, timeout)
+            .await
+            .map(|info_dict| {
+                ([(header::CONTENT_TYPE, BENCODE_CONTENT_TYPE)], info_dict).into_response()
+            })
+    } else {
+        get_metadata(&app_state, &info_hash, timeout, params.include_pieces)
+            .await
+            .map(|metadata| Json(metadata).into_response())
+    };
+
+    match response {
+        Ok(mut response) => {
+            response
+                .headers_mut()
+                .insert(header::VARY, HeaderValue::from_static("accept"));
+            response
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// The media type of the bencoded `info` dictionary.
+const BENCODE_CONTENT_TYPE: &str = "application/x-bencode";
+
+/// It returns true if the `Accept` header prefers the bencoded `info`
+/// dictionary to JSON. JSON is the default.
+fn prefers_bencode(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| {
+            quality(accept, "application", "x-bencode") > quality(accept, "application", "json")
+        })
+}
+
+/// It returns the torrent files as a JSON directory tree. Directories
+/// include the total length of their files.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_file_tree_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<ResolveParams>,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    if let Err(err) = app_state.access_list.check(&info_hash) {
+        return err.into_response();
+    }
+
+    let timeout = match resolve_timeout(&app_state, &params) {
+        Ok(timeout) => timeout,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("req files: {}", info_hash.to_hex_string());
+
+    app_state.metrics.inc_requests(Endpoint::Files);
+
+    match get_torrent_info(&app_state, &info_hash, timeout).await {
+        Ok(info) => Json(Node::from_info(&info_hash, &info)).into_response(),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// It returns the trackers of the torrent (its `announce` and
+/// `announce-list`) followed by the configured ones, without duplicates, as a
+/// JSON array. They can be used to build complete magnet links for re-sharing.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_announce_list_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<ResolveParams>,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    if let Err(err) = app_state.access_list.check(&info_hash) {
+        return err.into_response();
+    }
+
+    let timeout = match resolve_timeout(&app_state, &params) {
+        Ok(timeout) => timeout,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("req announce list: {}", info_hash.to_hex_string());
+
+    app_state.metrics.inc_requests(Endpoint::AnnounceList);
+
+    let cached_torrent = match get_torrent(&app_state, &info_hash, timeout, false).await {
+        Ok(cached_torrent) => cached_torrent,
+        Err(err) => return err.into_response(),
+    };
+
+    let Ok(torrent) = torrent_from_bytes::<ByteBufOwned>(&cached_torrent.bytes) else {
+        error!("can't parse torrent: {}", info_hash.to_hex_string());
+        return ApiError::InvalidTorrent.into_response();
+    };
+
+    Json(announce_list(
+        &torrent,
+        &configured_trackers(&app_state, info_hash),
+    ))
+    .into_response()
+}
+
+/// Returns the configured trackers and the ones in the remote list, if there
+/// is one. They may have duplicates.
+fn configured_trackers(app_state: &AppState, info_hash: InfoHash) -> Vec<String> {
+    // The magnet link also has the trackers of the remote list.
+    app_state
+        .reloader
+        .trackers()
+        .into_iter()
+        .chain(app_state.resolver.magnet_link(info_hash).trackers)
+        .collect()
+}
+
+/// Returns the torrent trackers, in the order they are in the torrent, and
+/// then the configured ones. The tracker URLs that are not valid UTF-8 are
+/// skipped.
+fn announce_list(torrent: &TorrentMetaV1<ByteBufOwned>, trackers: &[String]) -> Vec<String> {
+    let mut announce_list: Vec<String> = vec![];
+
+    let torrent_trackers = torrent
+        .announce
+        .iter()
+        .chain(torrent.announce_list.iter().flatten())
+        .filter_map(|tracker| std::str::from_utf8(tracker.as_ref()).ok());
+
+    for tracker in torrent_trackers.chain(trackers.iter().map(String::as_str)) {
+        if !announce_list.iter().any(|added| added == tracker) {
+            announce_list.push(tracker.to_string());
+        }
+    }
+
+    announce_list
+}
+
+/// Where the torrent was found.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    Cache,
+    /// It was resolved by the `BitTorrent` client, using the DHT and the
+    /// configured trackers and peers.
+    Dht,
+}
+
+/// Whether a torrent can be resolved, for monitoring.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub resolved: bool,
+    pub source: Source,
+    pub duration_ms: u64,
+    /// Number of peers found while resolving it. `None` for cached torrents.
+    pub peer_count: Option<usize>,
+    /// One of the [`ApiError`] codes, when it could not be resolved.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+/// It resolves the torrent (or finds it in the cache) like the torrent file
+/// endpoint and returns whether it was resolved as JSON, without the torrent
+/// file. Uptime checkers can use it to check the torrents can be resolved.
+///
+/// It returns `200` even if the torrent can't be resolved. See
+/// [`Status::resolved`].
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_status_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+    Query(params): Query<ResolveParams>,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    if let Err(err) = app_state.access_list.check(&info_hash) {
+        return err.into_response();
+    }
+
+    let timeout = match resolve_timeout(&app_state, &params) {
+        Ok(timeout) => timeout,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("req status: {}", info_hash.to_hex_string());
+
+    app_state.metrics.inc_requests(Endpoint::Status);
+
+    let start = Instant::now();
+
+    let (source, result) = if get_cached_torrent(&app_state, &info_hash).is_some() {
+        (Source::Cache, Ok(None))
+    } else if app_state.config.api.cache_only {
+        (Source::Dht, Err(ApiError::NotCached))
+    } else {
+        let result = resolve_torrent(&app_state, &info_hash, timeout).await;
+        (Source::Dht, result.map(|resolved| resolved.seen_peers))
+    };
+
+    let duration_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    let status = match result {
+        Ok(peer_count) => Status {
+            resolved: true,
+            source,
+            duration_ms,
+            peer_count,
+            error: None,
+        },
+        Err(err) => Status {
+            resolved: false,
+            source,
+            duration_ms,
+            peer_count: None,
+            error: Some(err.code().to_string()),
+        },
+    };
+
+    Json(status).into_response()
+}
+
+/// A request to resolve many torrents at once.
+///
+/// For example: `["443c7602b4fde83d1154d6d9da48808418b181b6", "IQ6HMAVU7XUD2EKU23M5USEAQQMLDANW"]`.
+#[derive(Deserialize)]
+pub struct BatchRequest(pub Vec<String>);
+
+/// The result of resolving one of the torrents in a batch request.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchResult {
+    Ok {
+        info_hash: InfoHash,
+        metadata: Metadata,
+    },
+    Error {
+        info_hash: InfoHash,
+        /// One of the [`ApiError`] codes.
+        error: String,
+        message: String,
+    },
+}
+
+/// It resolves many torrents concurrently and returns the metadata of each
+/// one of them, or the reason why it could not be resolved, in the same
+/// order as in the request.
+///
+/// The whole request is rejected if it has more info hashes than the
+/// configured maximum or any of them is not valid.
+#[allow(clippy::module_name_repetitions)]
+pub async fn batch_metadata_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ResolveParams>,
+    Json(request): Json<BatchRequest>,
+) -> Response {
+    let max = app_state.config.api.max_batch_size;
+
+    if request.0.len() > max {
+        return ApiError::BatchTooLarge { max }.into_response();
+    }
+
+    let info_hashes = match request
+        .0
+        .iter()
+        .map(|info_hash| parse_info_hash_str(info_hash))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(info_hashes) => info_hashes,
+        Err(err) => return err.into_response(),
+    };
+
+    let timeout = match resolve_timeout(&app_state, &params) {
+        Ok(timeout) => timeout,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("req batch: {} info hashes", info_hashes.len());
+
+    app_state.metrics.inc_requests(Endpoint::Batch);
+
+    // At least one of them is resolved at a time. The client limits the
+    // resolutions of all the requests.
+    let concurrency = NonZeroUsize::new(app_state.config.api.max_batch_concurrency)
+        .unwrap_or(NonZeroUsize::MIN)
+        .get();
+
+    // The results are returned in the same order as the info hashes.
+    let results: Vec<BatchResult> = stream::iter(info_hashes.into_iter().map(|info_hash| {
+        let app_state = &app_state;
+        async move {
+            let result = match app_state.access_list.check(&info_hash) {
+                Ok(()) => get_metadata(app_state, &info_hash, timeout, false).await,
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(metadata) => BatchResult::Ok {
+                    info_hash,
+                    metadata,
+                },
+                Err(err) => BatchResult::Error {
+                    info_hash,
+                    error: err.code().to_string(),
+                    message: err.to_string(),
+                },
+            }
+        }
+    }))
+    .buffered(concurrency)
+    .collect()
+    .await;
+
+    Json(results).into_response()
+}
+
+/// Returns the torrent metadata from the cache or resolving the torrent.
+async fn get_metadata(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+    include_pieces: bool,
+) -> Result<Metadata, ApiError> {
+    let info = get_torrent_info(app_state, info_hash, timeout).await?;
+
+    let metadata = Metadata::from_info(info_hash, &info);
+
+    if include_pieces {
+        return Ok(metadata.with_pieces(&info));
+    }
+
+    Ok(metadata)
+}
+
+/// Returns the torrent `info` dictionary, getting the torrent from the cache
+/// or resolving it.
+///
+/// Torrents with more files than the configured maximum are rejected, so
+/// they don't produce huge responses.
+async fn get_torrent_info(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+) -> Result<TorrentMetaV1Info<ByteBufOwned>, ApiError> {
+    let cached_torrent = get_torrent(app_state, info_hash, timeout, false).await?;
+
+    let Ok(torrent) = torrent_from_bytes::<ByteBufOwned>(&cached_torrent.bytes) else {
+        error!("can't parse torrent: {}", info_hash.to_hex_string());
+        return Err(ApiError::InvalidTorrent);
+    };
+
+    check_max_files(app_state, &torrent.info)?;
+
+    Ok(torrent.info)
+}
+
+/// Returns the original bencoded torrent `info` dictionary, getting the
+/// torrent from the cache or resolving it.
+async fn get_info_dict(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+) -> Result<Bytes, ApiError> {
+    let cached_torrent = get_torrent(app_state, info_hash, timeout, false).await?;
+
+    let Ok(torrent) = torrent_from_bytes_ext::<ByteBuf>(&cached_torrent.bytes) else {
+        error!("can't parse torrent: {}", info_hash.to_hex_string());
+        return Err(ApiError::InvalidTorrent);
+    };
+
+    check_max_files(app_state, &torrent.meta.info)?;
+
+    Ok(cached_torrent.bytes.slice_ref(&torrent.info_bytes))
+}
+
+fn check_max_files<BufType>(
+    app_state: &AppState,
+    info: &TorrentMetaV1Info<BufType>,
+) -> Result<(), ApiError> {
+    if let Some(max) = app_state.config.api.max_files {
+        let files = info.files.as_ref().map_or(1, Vec::len);
+
+        if files > max {
+            return Err(ApiError::TooManyFiles { max });
+        }
+    }
+
+    Ok(())
+}
+
+/// Admin endpoint. It removes the torrent from the cache, so it's resolved
+/// again on the next request. It returns `204` even if it was not cached.
+#[allow(clippy::module_name_repetitions)]
+pub async fn delete_torrent_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    info!("purge torrent: {}", info_hash.to_hex_string());
+
+    match app_state.cache.remove(&info_hash) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => {
+            error!("error removing torrent from cache: {}", err);
+            ApiError::CacheError.into_response()
+        }
+    }
+}
+
+/// Admin endpoint. It removes all the torrents from the cache.
+#[allow(clippy::module_name_repetitions)]
+pub async fn clear_cache_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    info!("purge cache");
+
+    match app_state.cache.clear() {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(err) => {
+            error!("error clearing the cache: {}", err);
+            ApiError::CacheError.into_response()
+        }
+    }
+}
+
+/// The cache statistics, in the admin endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Number of torrents kept in memory.
+    pub entries: usize,
+    /// Approximate size in bytes of the torrents kept in memory.
+    pub memory_bytes: u64,
+    /// Cache lookups since startup.
+    pub hits: u64,
+    pub misses: u64,
+    /// Size in bytes of the torrent files in the cache dir. `None` when the
+    /// disk cache is disabled.
+    pub disk_bytes: Option<u64>,
+}
+
+/// Admin endpoint. It returns the cache statistics, to size deployments
+/// without scraping the metrics.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_cache_stats_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    let disk_bytes = match app_state.cache.disk_size() {
+        Ok(disk_bytes) => disk_bytes,
+        Err(err) => {
+            error!("error reading the cache dir: {}", err);
+            return ApiError::CacheError.into_response();
+        }
+    };
+
+    Json(CacheStats {
+        entries: app_state.cache.len(),
+        memory_bytes: app_state.cache.memory_size(),
+        hits: app_state.metrics.cache_lookups(CacheResult::Hit),
+        misses: app_state.metrics.cache_lookups(CacheResult::Miss),
+        disk_bytes,
+    })
+    .into_response()
+}
+
+/// The default number of cached torrents listed per page.
+const DEFAULT_CACHE_KEYS_LIMIT: usize = 100;
+
+/// The maximum number of cached torrents listed per page.
+const MAX_CACHE_KEYS_LIMIT: usize = 1000;
+
+/// The pagination of the cached torrents list.
+///
+/// For example: `http://127.0.0.1:3000/admin/cache/keys?limit=100&offset=200`.
+#[derive(Deserialize)]
+pub struct CacheKeysParams {
+    /// Defaults to 100. Larger values are capped at 1000.
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// A page of the cached torrents, in the admin endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CacheKeys {
+    /// Total number of cached torrents.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub keys: Vec<CacheKey>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct CacheKey {
+    pub info_hash: InfoHash,
+    /// Unix timestamp in seconds.
+    pub cached_at: u64,
+    /// Size in bytes of the torrent file.
+    pub size: u64,
+}
+
+/// Admin endpoint. It returns the info hashes that failed to be resolved
+/// most often, with their number of failures, as JSON. The list is empty when
+/// the tracking is disabled.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_top_failures_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    Json(app_state.top_failures.top()).into_response()
+}
+
+/// Admin endpoint. It returns a page of the cached torrents, sorted by info
+/// hash, for debugging and cache warming.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_cache_keys_handler(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<CacheKeysParams>,
+) -> Response {
+    let entries = match app_state.cache.entries() {
+        Ok(entries) => entries,
+        Err(err) => {
+            error!("error reading the cache dir: {}", err);
+            return ApiError::CacheError.into_response();
+        }
+    };
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_CACHE_KEYS_LIMIT)
+        .min(MAX_CACHE_KEYS_LIMIT);
+
+    Json(CacheKeys {
+        total: entries.len(),
+        offset: params.offset,
+        limit,
+        keys: entries
+            .into_iter()
+            .skip(params.offset)
+            .take(limit)
+            .map(|entry| CacheKey {
+                info_hash: entry.info_hash,
+                cached_at: entry
+                    .cached_at
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map_or(0, |since_epoch| since_epoch.as_secs()),
+                size: entry.size,
+            })
+            .collect(),
+    })
+    .into_response()
+}
+
+/// The maintenance mode state, in the admin endpoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+}
+
+/// Admin endpoint. It returns whether the maintenance mode is enabled.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_maintenance_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    Json(MaintenanceStatus {
+        enabled: app_state.maintenance.is_enabled(),
+    })
+    .into_response()
+}
+
+/// Admin endpoint. It enables or disables the maintenance mode, for example
+/// with `{"enabled":true}`.
+#[allow(clippy::module_name_repetitions)]
+pub async fn set_maintenance_handler(
+    State(app_state): State<Arc<AppState>>,
+    Json(status): Json<MaintenanceStatus>,
+) -> Response {
+    info!("maintenance mode enabled: {}", status.enabled);
+
+    app_state.maintenance.set_enabled(status.enabled);
+
+    Json(status).into_response()
+}
+
+/// Admin endpoint. It reads the config file again and applies the settings
+/// that can be changed at runtime. It returns the changed settings, and the
+/// ones that require a restart.
+#[allow(clippy::module_name_repetitions)]
+pub async fn reload_config_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    match app_state.reloader.reload(&app_state) {
+        Ok(report) => Json(report).into_response(),
+        Err(err) => {
+            warn!("config reload failed: {err}");
+
+            ApiError::ReloadFailed {
+                reason: err.to_string(),
+            }
+            .into_response()
+        }
+    }
+}
+
+/// It returns the magnet link for the info hash, including the configured
+/// trackers and peers, as plain text. It does not resolve the torrent.
+#[allow(clippy::module_name_repetitions)]
+pub async fn get_magnet_link_handler(
+    State(app_state): State<Arc<AppState>>,
+    Path(info_hash): Path<InfoHashParam>,
+) -> Response {
+    let info_hash = match parse_info_hash(&info_hash) {
+        Ok(info_hash) => info_hash,
+        Err(err) => return err.into_response(),
+    };
+
+    app_state
+        .resolver
+        .magnet_link(info_hash)
+        .to_string()
+        .into_response()
+}
+
+fn parse_info_hash(info_hash: &InfoHashParam) -> Result<InfoHash, ApiError> {
+    parse_info_hash_str(&info_hash.0)
+}
+
+/// It checks the length first, so clients get a specific error message.
+/// The v2 info hashes get their own error, so users know why they are
+/// rejected.
+fn parse_info_hash_str(info_hash: &str) -> Result<InfoHash, ApiError> {
+    InfoHash::from_str(info_hash).map_err(|err| match err {
+        binascii::ConvertError::InvalidInputLength if is_info_hash_v2(info_hash) => {
+            ApiError::UnsupportedInfoHashV2
+        }
+        binascii::ConvertError::InvalidInputLength => ApiError::InvalidInfoHashLength,
+        _ => ApiError::InvalidInfoHashCharacters,
+    })
+}
+
+/// Length of a v2 info hash (SHA-256) encoded as an hexadecimal string.
+const INFO_HASH_V2_HEX_LEN: usize = 64;
+
+/// The multihash prefix of the SHA-256 digests, used by the `urn:btmh:`
+/// magnet links: the hash function code (`12`) and the digest length (`20`,
+/// 32 bytes).
+const SHA256_MULTIHASH_PREFIX: &str = "1220";
+
+/// Returns true if it's a v2 info hash in hex, with or without the multihash
+/// prefix. It's case-insensitive, like the v1 info hashes.
+fn is_info_hash_v2(info_hash: &str) -> bool {
+    let digest = info_hash
+        .strip_prefix(SHA256_MULTIHASH_PREFIX)
+        .filter(|digest| digest.len() == INFO_HASH_V2_HEX_LEN)
+        .unwrap_or(info_hash);
+
+    digest.len() == INFO_HASH_V2_HEX_LEN && digest.bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Returns the requested resolve timeout, if any, after checking it's within
+/// the configured bounds.
+fn resolve_timeout(
+    app_state: &AppState,
+    params: &ResolveParams,
+) -> Result<Option<Duration>, ApiError> {
+    let Some(timeout_ms) = params.timeout_ms else {
+        return Ok(None);
+    };
+
+    let timeout = Duration::from_millis(timeout_ms);
+    let (min, max) = app_state.reloader.resolve_timeout_bounds();
+
+    if timeout < min || timeout > max {
+        return Err(ApiError::InvalidTimeout { min, max });
+    }
+
+    Ok(Some(timeout))
+}
+
+/// Returns the torrent file from the cache or, if it's not cached yet,
+/// resolving the magnet link with the `BitTorrent` client.
+///
+/// When a `timeout` is given, the resolution is aborted if it takes longer.
+/// With `cache_only` (or the cache-only mode enabled in the configuration),
+/// it fails on cache misses without resolving the magnet link.
+pub(crate) async fn get_torrent(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+    cache_only: bool,
+) -> Result<CachedTorrent, ApiError> {
+    if let Some(cached_torrent) = get_cached_torrent(app_state, info_hash) {
+        return Ok(cached_torrent);
+    }
+
+    get_uncached_torrent(app_state, info_hash, timeout, cache_only).await
+}
+
+/// Resolves the magnet link of a torrent that is not in the cache, unless
+/// it's a cache-only request or the torrent is in the negative cache.
+async fn get_uncached_torrent(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+    cache_only: bool,
+) -> Result<CachedTorrent, ApiError> {
+    if cache_only || app_state.config.api.cache_only {
+        return Err(ApiError::NotCached);
+    }
+
+    if app_state.negative_cache.contains(info_hash) {
+        debug!(
+            "torrent not resolved recently: {}",
+            info_hash.to_hex_string()
+        );
+        app_state.metrics.inc_negative_cache_hits();
+        return Err(ApiError::NotFound);
+    }
+
+    let resolved = resolve_torrent(app_state, info_hash, timeout).await?;
+
+    Ok(CachedTorrent {
+        bytes: resolved.torrent_bytes,
+        cached_at: SystemTime::now(),
+    })
+}
+
+/// Returns the torrent file if it's cached, recording the cache lookup.
+fn get_cached_torrent(app_state: &AppState, info_hash: &InfoHash) -> Option<CachedTorrent> {
+    if app_state.cache.contains(info_hash) {
+        if let Ok(cached_torrent) = app_state.cache.get(info_hash) {
+            debug!("cached torrent: {}", app_state.cache.path(info_hash));
+
+            app_state.metrics.inc_cache_lookups(CacheResult::Hit);
+            app_state.stats.observe_cache_lookup(true);
+            Span::current().record("cache_hit", true);
+
+            return Some(cached_torrent);
+        }
+    }
+
+    app_state.metrics.inc_cache_lookups(CacheResult::Miss);
+    app_state.stats.observe_cache_lookup(false);
+    Span::current().record("cache_hit", false);
+
+    None
+}
+
+/// Resolves the magnet link with the `BitTorrent` client and adds the
+/// torrent to the cache, or to the negative cache if it's not found.
+///
+/// Nothing is resolved while the disk of the session output dir is almost
+/// full.
+async fn resolve_torrent(
+    app_state: &AppState,
+    info_hash: &InfoHash,
+    timeout: Option<Duration>,
+) -> Result<ResolvedTorrent, ApiError> {
+    if app_state.disk_space.is_low() {
+        return Err(ApiError::LowDiskSpace);
+    }
+
+    let resolver = app_state.resolver.clone();
+    let pending_resolutions = app_state.pending_resolutions.clone();
+    let cache = app_state.cache.clone();
+    let negative_cache = app_state.negative_cache.clone();
+    let top_failures = app_state.top_failures.clone();
+    let metrics = app_state.metrics.clone();
+    let stats = app_state.stats.clone();
+    let key = *info_hash;
+
+    // The resolution is traced in a child span of the first request.
+    let span = info_span!(
+        "resolve_magnet",
+        info_hash = %key,
+        duration_ms = field::Empty,
+        outcome = field::Empty,
+    );
+
+    // Concurrent requests for the same info-hash share the same resolution,
+    // and only the first one adds the torrent to the cache.
+    let resolution = app_state.resolutions.run(*info_hash, move || {
+        // Only new resolutions count, the requests for an info hash already
+        // being resolved share it.
+        let pending_resolution = pending_resolutions.try_add();
+
+        async move {
+            let Some(_pending_resolution) = pending_resolution else {
+                debug!("too many pending resolutions, load shedding");
+                return Err(ResolveMagnetError::Busy);
+            };
+
+            let start = Instant::now();
+
+            let result = resolver.resolve_magnet(key).await;
+
+            let outcome = match &result {
+                Ok(_) => Outcome::Success,
+                Err(err) => Outcome::from(err),
+            };
+            metrics.observe_resolution(outcome, start.elapsed());
+            stats.observe_resolution(start.elapsed());
+
+            let span = Span::current();
+            span.record(
+                "duration_ms",
+                u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+            );
+            span.record("outcome", outcome.as_str());
+
+            // The recent p95 shows the effect of tuning the client, for
+            // example, the peer connection timeouts.
+            debug!(
+                resolve_duration_p95_ms = stats.summary().resolve_duration_p95_ms,
+                "magnet link resolution completed"
+            );
+
+            // Only the torrents without (enough) peers serving them are
+            // remembered. The other errors are not about the torrent.
+            if matches!(
+                result,
+                Err(ResolveMagnetError::NotFound
+                    | ResolveMagnetError::NotEnoughPeers { .. }
+                    | ResolveMagnetError::ContentNotVerified)
+            ) {
+                negative_cache.add(&key);
+            }
+
+            // The errors that are not about the torrent are not counted.
+            if let Err(err) = &result {
+                if !matches!(
+                    err,
+                    ResolveMagnetError::NoSession
+                        | ResolveMagnetError::Busy
+                        | ResolveMagnetError::VerificationFailed
+                ) {
+                    top_failures.add(&key, outcome);
+                }
+            }
+
+            if let Ok(resolved) = &result {
+                // The torrent is served even if it can't be written to the
+                // cache dir.
+                match cache.add(&key, &resolved.torrent_bytes).await {
+                    Ok(()) => {
+                        trace!("added torrent to cache: {}", key.to_hex_string());
+                    }
+                    Err(err) => {
+                        warn!("error adding torrent to cache: {}", err);
+                    }
+                };
+            }
+
+            result
+        }
+        .instrument(span)
+    });
+
+    let result = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, resolution)
+            .await
+            .map_err(|_| ApiError::ResolveTimeout)?,
+        None => resolution.await,
+    };
+
+    match result {
+        Ok(resolved) => Ok(resolved),
+        Err(ResolveMagnetError::Busy) => Err(ApiError::Busy),
+        Err(ResolveMagnetError::AddedForDownloading) => Err(ApiError::AddedForDownloading),
+        Err(ResolveMagnetError::NotFound) => Err(ApiError::NotFound),
+        Err(ResolveMagnetError::MetadataTooLarge { max, .. }) => {
+            Err(ApiError::MetadataTooLarge { max })
+        }
+        Err(ResolveMagnetError::NotEnoughPeers { min, .. }) => {
+            Err(ApiError::NotEnoughPeers { min })
+        }
+        Err(ResolveMagnetError::InfoHashMismatch { .. }) => Err(ApiError::InfoHashMismatch),
+        Err(ResolveMagnetError::InvalidMetadata) => Err(ApiError::InvalidTorrent),
+        Err(ResolveMagnetError::ContentNotVerified) => Err(ApiError::ContentNotVerified),
+        Err(_) => Err(ApiError::ClientError),
+    }
+}
+
+/// Builds the binary response for a torrent file.
+///
+/// It includes cache hints for browsers and CDNs: the `max_age` (torrent
+/// files for an info hash never change) and when the torrent was resolved as
+/// `Last-Modified`.
+#[must_use]
+pub fn torrent_file_response(
+    torrent: CachedTorrent,
+    filename: &str,
+    info_hash: &str,
+    etag: &str,
+    header_prefix: &str,
+    content_type: TorrentContentType,
+    max_age: Duration,
+) -> Response {
+    let mut headers = torrent_file_headers(
+        filename,
+        info_hash,
+        etag,
+        header_prefix,
+        content_type,
+        torrent.cached_at,
+        max_age,
+    );
+    headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from(torrent.bytes.len()),
+    );
+
+    (StatusCode::OK, headers, stream_body(torrent.bytes)).into_response()
+}
+
+/// Returns a streaming body with the bytes, sent in chunks of at most
+/// [`STREAM_CHUNK_SIZE`] bytes. The chunks are slices of the same buffer,
+/// so they are not copied.
+fn stream_body(bytes: Bytes) -> Body {
+    let chunks = (0..bytes.len())
+        .step_by(STREAM_CHUNK_SIZE)
+        .map(move |start| {
+            Ok::<_, Infallible>(bytes.slice(start..bytes.len().min(start + STREAM_CHUNK_SIZE)))
+        });
+
+    Body::from_stream(stream::iter(chunks))
+}
+
+/// Returns the names of the headers with the info hash and its version, using
+/// the configured prefix. For example, `x-torrust-torrent-infohash` and
+/// `x-torrust-torrent-infohash-version`.
+#[must_use]
+pub fn info_hash_headers(prefix: &str) -> (String, String) {
+    (
+        format!("{prefix}-infohash"),
+        format!("{prefix}-infohash-version"),
+    )
+}
+
+/// Builds the headers for a torrent file response.
+///
+/// The filename is encoded, so it's always a valid header value. If the
+/// info hash or the `ETag` are not valid header values, the info hash headers
+/// (see [`info_hash_headers`]) or the `ETag` header are omitted.
+#[must_use]
+pub fn torrent_file_headers(
+    filename: &str,
+    info_hash: &str,
+    etag: &str,
+    header_prefix: &str,
+    content_type: TorrentContentType,
+    last_modified: SystemTime,
+    max_age: Duration,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(content_type.as_str()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition(filename))
+            .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+    );
+    // The prefix is validated with the configuration.
+    let (info_hash_header, version_header) = info_hash_headers(header_prefix);
+    if let (Ok(name), Ok(version_name), Ok(value)) = (
+        HeaderName::try_from(info_hash_header),
+        HeaderName::try_from(version_header),
+        HeaderValue::from_str(info_hash),
+    ) {
+        headers.insert(name, value);
+        headers.insert(version_name, HeaderValue::from_static(INFO_HASH_VERSION));
+    }
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(header::CACHE_CONTROL, cache_control(max_age));
+    if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+
+    headers
+}
+
+/// Builds the headers for a placeholder torrent file. They are not cached,
+/// so the torrent is resolved again on the next request, and they don't have
+/// the info hash headers, as the placeholder has its own info hash.
+fn placeholder_headers(app_state: &AppState, info_hash: &InfoHash, length: usize) -> HeaderMap {
+    let filename = filename::render(
+        &app_state.config.api.filename_template,
+        info_hash,
+        &info_hash.to_hex_string(),
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(app_state.config.api.torrent_content_type.as_str()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&content_disposition(&filename))
+            .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+    );
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers.insert(
+        placeholder::RESOLUTION_HEADER,
+        HeaderValue::from_static(placeholder::RESOLUTION_FAILED),
+    );
+
+    headers
+}
+
+/// Redirects the client to the magnet link, so it can hand it to its
+/// `BitTorrent` client.
+fn magnet_redirect_response(app_state: &AppState, info_hash: InfoHash) -> Response {
+    let magnet_link = app_state.resolver.magnet_link(info_hash).to_string();
+
+    match HeaderValue::from_str(&magnet_link) {
+        Ok(location) => (StatusCode::FOUND, [(header::LOCATION, location)]).into_response(),
+        Err(_) => {
+            error!("invalid magnet link header value: {magnet_link}");
+            ApiError::ClientError.into_response()
+        }
     }
 }
 
-#[allow(clippy::module_name_repetitions)]
-pub async fn get_metainfo_file_handler(
-    State(app_state): State<Arc<AppState>>,
-    Path(info_hash): Path<InfoHashParam>,
-) -> Response {
-    let Ok(info_hash) = InfoHash::from_str(&info_hash.lowercase()) else {
-        return (StatusCode::BAD_REQUEST, "Invalid info hash").into_response();
-    };
+fn not_modified_response(etag: &str, max_age: Duration) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+    headers.insert(header::CACHE_CONTROL, cache_control(max_age));
 
-    info!("req: {}", info_hash.to_hex_string());
+    (StatusCode::NOT_MODIFIED, headers).into_response()
+}
 
-    if app_state.cache.contains(&info_hash) {
-        if let Ok(bytes) = app_state.cache.get(&info_hash) {
-            debug!("cached torrent: {}", app_state.cache.path(&info_hash));
+fn cache_control(max_age: Duration) -> HeaderValue {
+    HeaderValue::from_str(&format!("public, max-age={}", max_age.as_secs()))
+        .expect("Cache-Control header should be valid")
+}
 
-            return torrent_file_response(
-                bytes,
-                &format!("{}.torrent", info_hash.to_hex_string()),
-                &info_hash.to_hex_string(),
-            );
-        }
+/// The torrent file `ETag`, computed from the info hash or the torrent file
+/// contents.
+fn torrent_etag(source: ETag, info_hash: &InfoHash, torrent_bytes: &[u8]) -> String {
+    match source {
+        ETag::InfoHash => etag(&info_hash.to_hex_string()),
+        ETag::Content => etag(
+            &digest::digest(&digest::SHA256, torrent_bytes)
+                .as_ref()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>(),
+        ),
     }
+}
+
+/// Quotes the `ETag` value.
+fn etag(value: &str) -> String {
+    format!("\"{value}\"")
+}
 
-    let magnet_link = format!("magnet:?xt=urn:btih:{}", info_hash.to_hex_string());
+/// Returns true if any of the `If-None-Match` header values matches the
+/// `ETag`. Weak validators are also accepted, as the comparison for
+/// `If-None-Match` is always weak.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
 
-    let Ok((_info, bytes)) = app_state.client.resolve_magnet(magnet_link).await else {
-        return (StatusCode::INTERNAL_SERVER_ERROR, "BitTorrent client error").into_response();
+/// Builds the `Content-Disposition` header value for a download.
+///
+/// It includes a quoted ASCII-only `filename` for old clients and the full
+/// UTF-8 name in the `filename*` param, encoded as described in
+/// [RFC 5987](https://datatracker.ietf.org/doc/html/rfc5987).
+fn content_disposition(filename: &str) -> String {
+    let filename = if filename.is_empty() {
+        FALLBACK_FILENAME
+    } else {
+        filename
     };
 
-    match app_state.cache.add(&info_hash, &bytes) {
-        Ok(()) => {
-            trace!("added torrent to cache: {}", info_hash.to_hex_string());
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii_filename(filename),
+        rfc5987_encode(filename)
+    )
+}
+
+/// Replaces the characters that can't be used in a quoted ASCII header
+/// param with `_`.
+fn ascii_filename(filename: &str) -> String {
+    filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() || c == ' ') && !matches!(c, '"' | '\\') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Percent-encodes all the bytes that are not an `attr-char`.
+fn rfc5987_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
         }
+    }
+
+    encoded
+}
+
+/// It returns the Prometheus metrics in the `OpenMetrics` text format.
+#[allow(clippy::module_name_repetitions)]
+pub async fn metrics_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    match app_state.metrics.encode() {
+        Ok(metrics) => (
+            [(
+                header::CONTENT_TYPE,
+                "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            )],
+            metrics,
+        )
+            .into_response(),
         Err(err) => {
-            error!("error adding torrent to cache: {}", err);
+            error!("error encoding metrics: {}", err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
-    };
+    }
+}
 
-    torrent_file_response(
-        bytes,
-        &format!("{}.torrent", info_hash.to_hex_string()),
-        &info_hash.to_hex_string(),
-    )
+/// The health check response body.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HealthCheck {
+    pub session_started: bool,
+    /// Only cached torrents are served because the disk is almost full.
+    pub low_disk_space: bool,
+    /// Recent resolution latencies and cache hit rate.
+    pub stats: Summary,
 }
 
-/// Builds the binary response for a torrent file.
+/// It returns `200` if the `BitTorrent` client session is started, so the
+/// service can resolve torrents, or `503` otherwise.
 ///
-/// # Panics
-///
-/// Panics if the filename is not a valid header value for the `content-disposition`
-/// header.
-#[must_use]
-pub fn torrent_file_response(bytes: Bytes, filename: &str, info_hash: &str) -> Response {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "application/x-bittorrent"
-            .parse()
-            .expect("HTTP content type header should be valid"),
-    );
-    headers.insert(
-        header::CONTENT_DISPOSITION,
-        format!("attachment; filename={filename}").parse().expect(
-            "Torrent filename should be a valid header value for the content disposition header",
-        ),
-    );
-    headers.insert(
-        "x-torrust-torrent-infohash",
-        info_hash.parse().expect(
-            "Torrent infohash should be a valid header value for the content disposition header",
-        ),
-    );
+/// The body also includes a summary of the recent resolution latencies and
+/// cache hit rate.
+#[allow(clippy::module_name_repetitions)]
+pub async fn health_check_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    let session_started = app_state.resolver.is_session_started();
+
+    let status = if session_started {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
 
-    (StatusCode::OK, headers, bytes).into_response()
+    (
+        status,
+        Json(HealthCheck {
+            session_started,
+            low_disk_space: app_state.disk_space.is_low(),
+            stats: app_state.stats.summary(),
+        }),
+    )
+        .into_response()
 }
 
+/// Liveness probe. It always returns `200` while the process is able to
+/// handle requests. It does not check any dependency, so orchestrators
+/// should only restart the service when it fails.
 #[allow(clippy::module_name_repetitions)]
-pub async fn health_check_handler() -> Response {
+pub async fn liveness_handler() -> Response {
     (StatusCode::OK, "OK").into_response()
 }
 
+/// The readiness probe response body.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct Readiness {
+    pub session_started: bool,
+    pub dht_enabled: bool,
+    /// Number of nodes in the DHT routing table.
+    pub dht_nodes: usize,
+    /// Minimum number of nodes in the DHT routing table to be ready.
+    pub min_dht_nodes: usize,
+}
+
+impl Readiness {
+    /// Returns true if the session is started and, if it's enabled, the DHT
+    /// routing table has at least the minimum number of nodes.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        self.session_started && (!self.dht_enabled || self.dht_nodes >= self.min_dht_nodes)
+    }
+}
+
+/// Readiness probe. It returns `200` when the `BitTorrent` client session is
+/// started and, if it's enabled, the DHT routing table has at least the
+/// configured minimum number of nodes (so torrents can be resolved), or `503`
+/// otherwise. Orchestrators should stop sending traffic to the service while
+/// it fails, but not restart it.
+#[allow(clippy::module_name_repetitions)]
+pub async fn readiness_handler(State(app_state): State<Arc<AppState>>) -> Response {
+    let readiness = Readiness {
+        session_started: app_state.resolver.is_session_started(),
+        dht_enabled: app_state.resolver.is_dht_enabled(),
+        dht_nodes: app_state.resolver.dht_nodes().unwrap_or_default(),
+        min_dht_nodes: app_state.config.client.min_dht_nodes,
+    };
+
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(readiness)).into_response()
+}
+
+/// The service info returned by the entrypoint.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub version: String,
+    pub session_started: bool,
+    pub documentation: String,
+}
+
+/// It returns the service info as JSON, so operators can quickly check the
+/// running version and whether the `BitTorrent` client session is up.
+///
+/// Browsers, which prefer HTML to JSON in the `Accept` header, get a page to
+/// download torrents instead.
 #[allow(clippy::module_name_repetitions)]
-pub async fn entrypoint_handler() -> Html<&'static str> {
+pub async fn entrypoint_handler(
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let prefers_html = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| {
+            quality(accept, "text", "html") > quality(accept, "application", "json")
+        });
+
+    if prefers_html {
+        return entrypoint_page().into_response();
+    }
+
+    Json(ServiceInfo {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        session_started: app_state.resolver.is_session_started(),
+        documentation: DOCUMENTATION_URL.to_string(),
+    })
+    .into_response()
+}
+
+fn entrypoint_page() -> Html<&'static str> {
     let html = r#"
     <!DOCTYPE html>
     <html lang="en">
@@ -225,3 +1861,959 @@ pub async fn entrypoint_handler() -> Html<&'static str> {
 
     Html(html) // Wrap HTML content in Html response type
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use std::str::FromStr;
+
+    use axum::body::Body;
+    use axum::extract::{ConnectInfo, Path, Query, State};
+    use axum::response::Response;
+    use axum::routing::get;
+    use axum::{middleware, Json, Router};
+    use bytes::Bytes;
+    use camino::Utf8PathBuf;
+    use hyper::header::HeaderValue;
+    use hyper::{header, HeaderMap, Request, StatusCode};
+    use librqbit::{torrent_from_bytes, ByteBufOwned};
+    use prometheus_client::metrics::gauge::Gauge;
+    use tempfile::TempDir;
+    use tower::ServiceExt;
+
+    use super::{
+        announce_list, batch_metadata_handler, entrypoint_handler, etag, get_metadata_handler,
+        get_metainfo_file_handler, get_status_handler, health_check_handler, if_none_match,
+        liveness_handler, parse_info_hash_str, readiness_handler, set_maintenance_handler,
+        torrent_etag, torrent_file_headers, torrent_file_response, torrent_filename, BatchRequest,
+        BatchResult, HealthCheck, InfoHashParam, MaintenanceStatus, MetadataParams, Readiness,
+        ResolveParams, ServiceInfo, Source, Status, TorrentFormat, TorrentParams,
+        STREAM_CHUNK_SIZE,
+    };
+    use crate::api::access_list::AccessList;
+    use crate::api::cache::{Cache, CachedTorrent};
+    use crate::api::disk_space::DiskSpaceGuard;
+    use crate::api::error::{ApiError, ErrorBody};
+    use crate::api::load_shedding::PendingResolutions;
+    use crate::api::maintenance::{reject_in_maintenance, MaintenanceMode};
+    use crate::api::negative_cache::NegativeCache;
+    use crate::api::rate_limit::RateLimiter;
+    use crate::api::reload::ConfigReloader;
+    use crate::api::single_flight::SingleFlight;
+    use crate::api::top_failures::TopFailures;
+    use crate::bit_torrent::client::Client;
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::bit_torrent::metadata::{File, Metadata};
+    use crate::config::{self, Config, ETag, TorrentContentType};
+    use crate::metrics::Metrics;
+    use crate::stats::{Stats, Summary};
+    use crate::AppState;
+
+    fn app_state(dir: &TempDir) -> AppState {
+        let cache_dir = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+        let config = Config {
+            api: config::Api {
+                bind_addresses: vec!["127.0.0.1:0".parse().unwrap()],
+                torrents_cache_dir: cache_dir.clone(),
+                torrent_max_age: Duration::from_secs(100),
+                max_batch_size: 2,
+                max_batch_concurrency: 2,
+                max_pending_resolutions: None,
+                max_files: None,
+                maintenance: config::Maintenance {
+                    message: "Under maintenance".to_string(),
+                    ..config::Maintenance::default()
+                },
+                ..config::Api::default()
+            },
+            client: config::Client {
+                listen_ports: None,
+                session_output_dir: cache_dir.clone(),
+                max_concurrent_resolutions: 1,
+                resolution_queue_timeout: None,
+                resolve_timeout: Duration::from_secs(10),
+                session_cleanup: None,
+                free_disk_space: None,
+                ..config::Client::default()
+            },
+            cache: config::Cache {
+                max_entries: 10,
+                max_disk_size: None,
+                max_concurrent_disk_writes: 1,
+                negative_ttl: None,
+                ..config::Cache::default()
+            },
+            ..Config::default()
+        };
+
+        AppState {
+            reloader: Arc::new(ConfigReloader::without_config_file(&config)),
+            resolver: Arc::new(Client::new(config.client.clone())),
+            cache: Arc::new(Cache::new(cache_dir, &config.cache)),
+            negative_cache: Arc::new(NegativeCache::new(&config.cache)),
+            top_failures: Arc::new(TopFailures::new(config.api.top_failures.as_ref())),
+            disk_space: Arc::new(DiskSpaceGuard::new(&config.client.session_output_dir, None)),
+            config: Arc::new(config),
+            resolutions: Arc::new(SingleFlight::new()),
+            pending_resolutions: Arc::new(PendingResolutions::new(None, Gauge::default())),
+            metrics: Arc::new(Metrics::new()),
+            stats: Arc::new(Stats::new()),
+            rate_limiter: Arc::new(RateLimiter::new(None)),
+            maintenance: Arc::new(MaintenanceMode::new(false)),
+            access_list: Arc::new(AccessList::default()),
+        }
+    }
+
+    fn response(filename: &str) -> Response {
+        torrent_file_response(
+            CachedTorrent {
+                bytes: Bytes::from("torrent"),
+                // Sun, 06 Nov 1994 08:49:37 GMT
+                cached_at: SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777),
+            },
+            filename,
+            "443c7602b4fde83d1154d6d9da48808418b181b6",
+            "\"443c7602b4fde83d1154d6d9da48808418b181b6\"",
+            "x-torrust-torrent",
+            TorrentContentType::default(),
+            Duration::from_secs(100),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_should_stream_the_whole_torrent_file_with_its_length() {
+        let bytes = Bytes::from(vec![7u8; 3 * STREAM_CHUNK_SIZE + 1]);
+
+        let response = torrent_file_response(
+            CachedTorrent {
+                bytes: bytes.clone(),
+                cached_at: SystemTime::now(),
+            },
+            "sample.torrent",
+            "443c7602b4fde83d1154d6d9da48808418b181b6",
+            "\"443c7602b4fde83d1154d6d9da48808418b181b6\"",
+            "x-torrust-torrent",
+            TorrentContentType::default(),
+            Duration::from_secs(100),
+        );
+
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            bytes.len().to_string()
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, bytes);
+    }
+
+    #[test]
+    fn it_should_use_the_display_name_in_the_filename_only_when_the_torrent_has_no_name() {
+        let dir = TempDir::new().unwrap();
+        let mut app_state = app_state(&dir);
+        Arc::make_mut(&mut app_state.config).api.filename_template = "{name}.torrent".to_string();
+
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let named = CachedTorrent {
+            bytes: sample_torrent(),
+            cached_at: SystemTime::now(),
+        };
+        let mut nameless = b"d4:infod6:lengthi11e12:piece lengthi16384e6:pieces20:".to_vec();
+        nameless.extend_from_slice(&[0u8; 20]);
+        nameless.extend_from_slice(b"ee");
+        let nameless = CachedTorrent {
+            bytes: Bytes::from(nameless),
+            cached_at: SystemTime::now(),
+        };
+
+        assert_eq!(
+            torrent_filename(&app_state, &info_hash, &named, Some("other")),
+            "sample.txt.torrent"
+        );
+        assert_eq!(
+            torrent_filename(
+                &app_state,
+                &info_hash,
+                &nameless,
+                Some("../Ubuntu: desktop")
+            ),
+            "_Ubuntu_ desktop.torrent"
+        );
+        assert_eq!(
+            torrent_filename(&app_state, &info_hash, &nameless, None),
+            "443c7602b4fde83d1154d6d9da48808418b181b6.torrent"
+        );
+    }
+
+    #[test]
+    fn it_should_sanitize_torrent_filenames_that_are_not_valid_header_values() {
+        let response = response("Ubuntu\r\nУбунту.torrent");
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"Ubuntu________.torrent\"; \
+             filename*=UTF-8''Ubuntu%0D%0A%D0%A3%D0%B1%D1%83%D0%BD%D1%82%D1%83.torrent"
+        );
+    }
+
+    #[test]
+    fn it_should_encode_non_ascii_filenames_as_described_in_rfc_5987() {
+        let response = response("日本語.torrent");
+
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"___.torrent\"; \
+             filename*=UTF-8''%E6%97%A5%E6%9C%AC%E8%AA%9E.torrent"
+        );
+    }
+
+    #[test]
+    fn it_should_escape_double_quotes_in_filenames() {
+        let response = response("the \"best\" movie.torrent");
+
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"the _best_ movie.torrent\"; \
+             filename*=UTF-8''the%20%22best%22%20movie.torrent"
+        );
+    }
+
+    #[test]
+    fn it_should_use_a_fallback_filename_when_the_filename_is_empty() {
+        let response = response("");
+
+        assert_eq!(
+            response.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"download.torrent\"; filename*=UTF-8''download.torrent"
+        );
+    }
+
+    #[test]
+    fn it_should_tell_apart_info_hashes_with_an_invalid_length_from_the_ones_with_invalid_characters(
+    ) {
+        assert!(matches!(
+            parse_info_hash_str(&"4".repeat(39)),
+            Err(ApiError::InvalidInfoHashLength)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&"4".repeat(41)),
+            Err(ApiError::InvalidInfoHashLength)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&"g".repeat(40)),
+            Err(ApiError::InvalidInfoHashCharacters)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&"1".repeat(32)),
+            Err(ApiError::InvalidInfoHashCharacters)
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_the_v2_info_hashes_with_or_without_the_multihash_prefix() {
+        let digest = "CAFEbabe".repeat(8);
+
+        assert!(matches!(
+            parse_info_hash_str(&digest),
+            Err(ApiError::UnsupportedInfoHashV2)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&format!("1220{digest}")),
+            Err(ApiError::UnsupportedInfoHashV2)
+        ));
+
+        // Other multihash prefixes or lengths are ambiguous.
+        assert!(matches!(
+            parse_info_hash_str(&format!("1320{digest}")),
+            Err(ApiError::InvalidInfoHashLength)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&format!("1220{digest}0")),
+            Err(ApiError::InvalidInfoHashLength)
+        ));
+        assert!(matches!(
+            parse_info_hash_str(&"g".repeat(64)),
+            Err(ApiError::InvalidInfoHashLength)
+        ));
+    }
+
+    #[test]
+    fn it_should_use_the_info_hash_or_the_sha256_of_the_torrent_file_as_the_etag() {
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        assert_eq!(
+            torrent_etag(ETag::InfoHash, &info_hash, b"torrent"),
+            "\"443c7602b4fde83d1154d6d9da48808418b181b6\""
+        );
+        assert_eq!(
+            torrent_etag(ETag::Content, &info_hash, b"torrent"),
+            "\"cad4058f8c157d4d98682319b31129b4a48d3bd2d7a563149338370c0785c381\""
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_only_return_not_modified_for_matching_content_etags_after_getting_the_torrent(
+    ) {
+        let dir = TempDir::new().unwrap();
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let mut app_state = app_state(&dir);
+        Arc::make_mut(&mut app_state.config).api.etag = ETag::Content;
+        let app_state = Arc::new(app_state);
+
+        let get_with_etag = |etag: String| {
+            let app_state = app_state.clone();
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, etag.parse().unwrap());
+
+            async move {
+                get_metainfo_file_handler(
+                    State(app_state),
+                    ConnectInfo("127.0.0.1:8080".parse().unwrap()),
+                    Path(InfoHashParam(
+                        "443c7602b4fde83d1154d6d9da48808418b181b6".to_string(),
+                    )),
+                    Query(TorrentParams {
+                        timeout_ms: None,
+                        cache_only: true,
+                        dn: None,
+                        format: TorrentFormat::Torrent,
+                        inject_trackers: false,
+                    }),
+                    headers,
+                )
+                .await
+            }
+        };
+
+        // The info hash is not the `ETag` anymore, so the torrent is needed.
+        let response = get_with_etag(etag(&info_hash.to_hex_string())).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        app_state
+            .cache
+            .add(&info_hash, &sample_torrent())
+            .await
+            .unwrap();
+
+        let content_etag = torrent_etag(ETag::Content, &info_hash, &sample_torrent());
+
+        let response = get_with_etag(content_etag.clone()).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], content_etag);
+    }
+
+    #[test]
+    fn it_should_send_the_info_hash_and_its_version_with_the_configured_prefix() {
+        let headers = torrent_file_headers(
+            "sample.torrent",
+            "443c7602b4fde83d1154d6d9da48808418b181b6",
+            "\"443c7602b4fde83d1154d6d9da48808418b181b6\"",
+            "x-example",
+            TorrentContentType::default(),
+            SystemTime::now(),
+            Duration::from_secs(100),
+        );
+
+        assert_eq!(
+            headers["x-example-infohash"],
+            "443c7602b4fde83d1154d6d9da48808418b181b6"
+        );
+        assert_eq!(headers["x-example-infohash-version"], "1");
+        assert!(!headers.contains_key("x-torrust-torrent-infohash"));
+    }
+
+    #[test]
+    fn it_should_merge_the_torrent_trackers_with_the_configured_ones_without_duplicates() {
+        let mut torrent = b"d8:announce17:udp://a.example:113:announce-listll17:udp://a.example:1\
+              17:udp://b.example:2el17:udp://c.example:3ee4:infod6:lengthi11e\
+              4:name10:sample.txt12:piece lengthi16384e6:pieces20:"
+            .to_vec();
+        torrent.extend_from_slice(&[0u8; 20]);
+        torrent.extend_from_slice(b"ee");
+        let torrent = torrent_from_bytes::<ByteBufOwned>(&torrent).unwrap();
+
+        assert_eq!(
+            announce_list(
+                &torrent,
+                &[
+                    "udp://c.example:3".to_string(),
+                    "udp://d.example:4".to_string()
+                ]
+            ),
+            vec![
+                "udp://a.example:1",
+                "udp://b.example:2",
+                "udp://c.example:3",
+                "udp://d.example:4"
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_send_the_configured_content_type() {
+        let headers = |content_type| {
+            torrent_file_headers(
+                "sample.torrent",
+                "443c7602b4fde83d1154d6d9da48808418b181b6",
+                "\"443c7602b4fde83d1154d6d9da48808418b181b6\"",
+                "x-torrust-torrent",
+                content_type,
+                SystemTime::now(),
+                Duration::from_secs(100),
+            )
+        };
+
+        assert_eq!(
+            headers(TorrentContentType::BitTorrent)[header::CONTENT_TYPE],
+            "application/x-bittorrent"
+        );
+        assert_eq!(
+            headers(TorrentContentType::OctetStream)[header::CONTENT_TYPE],
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn it_should_match_the_etag_with_the_if_none_match_header() {
+        let etag = "\"443c7602b4fde83d1154d6d9da48808418b181b6\"";
+
+        let if_none_match_header = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+            headers
+        };
+
+        assert!(if_none_match(&if_none_match_header(etag), etag));
+        assert!(if_none_match(&if_none_match_header("*"), etag));
+        assert!(if_none_match(
+            &if_none_match_header(&format!("\"other\", W/{etag}")),
+            etag
+        ));
+        assert!(!if_none_match(&if_none_match_header("\"other\""), etag));
+        assert!(!if_none_match(&HeaderMap::new(), etag));
+    }
+
+    #[test]
+    fn it_should_send_the_cache_hints_for_the_torrent_file() {
+        let response = response("sample.torrent");
+
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=100"
+        );
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_report_the_service_as_unavailable_when_the_session_is_not_started() {
+        let dir = TempDir::new().unwrap();
+
+        let response = health_check_handler(State(Arc::new(app_state(&dir)))).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<HealthCheck>(&body).unwrap(),
+            HealthCheck {
+                session_started: false,
+                low_disk_space: false,
+                stats: Summary {
+                    resolve_duration_p50_ms: None,
+                    resolve_duration_p95_ms: None,
+                    resolve_duration_p99_ms: None,
+                    cache_hit_rate: None,
+                },
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_always_report_the_service_as_alive() {
+        assert_eq!(liveness_handler().await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn it_should_report_the_service_as_not_ready_when_the_session_is_not_started() {
+        let dir = TempDir::new().unwrap();
+
+        let response = readiness_handler(State(Arc::new(app_state(&dir)))).await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Readiness>(&body).unwrap(),
+            Readiness {
+                session_started: false,
+                dht_enabled: true,
+                dht_nodes: 0,
+                min_dht_nodes: 1
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_only_report_the_service_as_ready_when_the_dht_has_the_minimum_number_of_nodes() {
+        let readiness = |dht_enabled, dht_nodes| Readiness {
+            session_started: true,
+            dht_enabled,
+            dht_nodes,
+            min_dht_nodes: 10,
+        };
+
+        assert!(!readiness(true, 9).is_ready());
+        assert!(readiness(true, 10).is_ready());
+        assert!(readiness(false, 0).is_ready());
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_service_info_unless_the_client_prefers_html() {
+        let dir = TempDir::new().unwrap();
+        let app_state = Arc::new(app_state(&dir));
+
+        let response = entrypoint_handler(State(app_state.clone()), HeaderMap::new()).await;
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ServiceInfo>(&body).unwrap(),
+            ServiceInfo {
+                name: "torrust-hash2torrent".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                session_started: false,
+                documentation: "https://github.com/torrust/torrust-hash2torrent".to_string(),
+            }
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            "text/html,application/xhtml+xml,*/*;q=0.8".parse().unwrap(),
+        );
+
+        let response = entrypoint_handler(State(app_state), headers).await;
+
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+    }
+
+    async fn status(app_state: &Arc<AppState>, info_hash: &InfoHash) -> Status {
+        let response = get_status_handler(
+            State(app_state.clone()),
+            Path(InfoHashParam(info_hash.to_hex_string())),
+            Query(ResolveParams { timeout_ms: None }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_status_of_the_torrent_resolution() {
+        let dir = TempDir::new().unwrap();
+        let app_state = Arc::new(app_state(&dir));
+
+        let cached = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+        let not_cached = InfoHash::from_str("543c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        app_state
+            .cache
+            .add(&cached, &sample_torrent())
+            .await
+            .unwrap();
+
+        let cached_status = status(&app_state, &cached).await;
+
+        assert!(cached_status.resolved);
+        assert_eq!(cached_status.source, Source::Cache);
+        assert_eq!(cached_status.peer_count, None);
+
+        let not_cached_status = status(&app_state, &not_cached).await;
+
+        // The BitTorrent client session is not started.
+        assert!(!not_cached_status.resolved);
+        assert_eq!(not_cached_status.source, Source::Dht);
+        assert_eq!(not_cached_status.error, Some("client_error".to_string()));
+    }
+
+    /// A single-file torrent with one piece.
+    fn sample_torrent() -> Bytes {
+        let mut torrent =
+            b"d4:infod6:lengthi11e4:name10:sample.txt12:piece lengthi16384e6:pieces20:".to_vec();
+        torrent.extend_from_slice(&[0u8; 20]);
+        torrent.extend_from_slice(b"ee");
+        Bytes::from(torrent)
+    }
+
+    async fn metadata_json(
+        app_state: &Arc<AppState>,
+        info_hash: &InfoHash,
+        include_pieces: bool,
+    ) -> serde_json::Value {
+        let response = get_metadata_handler(
+            State(app_state.clone()),
+            Path(InfoHashParam(info_hash.to_hex_string())),
+            Query(MetadataParams {
+                timeout_ms: None,
+                include_pieces,
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_only_include_the_piece_hashes_in_the_metadata_on_request() {
+        let dir = TempDir::new().unwrap();
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let app_state = Arc::new(app_state(&dir));
+        app_state
+            .cache
+            .add(&info_hash, &sample_torrent())
+            .await
+            .unwrap();
+
+        let metadata = metadata_json(&app_state, &info_hash, false).await;
+
+        assert!(metadata.get("pieces").is_none());
+
+        let metadata = metadata_json(&app_state, &info_hash, true).await;
+
+        assert_eq!(metadata["piece_length"], 16384);
+        assert_eq!(
+            metadata["pieces"],
+            serde_json::json!(["0000000000000000000000000000000000000000"])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_bencoded_info_dict_when_preferred_to_json() {
+        let dir = TempDir::new().unwrap();
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let app_state = Arc::new(app_state(&dir));
+        app_state
+            .cache
+            .add(&info_hash, &sample_torrent())
+            .await
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json;q=0.5, application/x-bencode"),
+        );
+
+        let response = get_metadata_handler(
+            State(app_state.clone()),
+            Path(InfoHashParam(info_hash.to_hex_string())),
+            Query(MetadataParams {
+                timeout_ms: None,
+                include_pieces: false,
+            }),
+            headers,
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-bencode"
+        );
+        assert_eq!(response.headers()[header::VARY], "accept");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let torrent = sample_torrent();
+
+        assert_eq!(body, torrent.slice(7..torrent.len() - 1));
+
+        // JSON is the default.
+        assert_eq!(
+            metadata_json(&app_state, &info_hash, false).await["name"],
+            "sample.txt"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_not_resolve_the_torrent_when_requesting_only_cached_torrents() {
+        let dir = TempDir::new().unwrap();
+
+        let response = get_metainfo_file_handler(
+            State(Arc::new(app_state(&dir))),
+            ConnectInfo("127.0.0.1:8080".parse().unwrap()),
+            Path(InfoHashParam(
+                "443c7602b4fde83d1154d6d9da48808418b181b6".to_string(),
+            )),
+            Query(TorrentParams {
+                timeout_ms: None,
+                cache_only: true,
+                dn: None,
+                format: TorrentFormat::Torrent,
+                inject_trackers: false,
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+
+        // Resolving it would fail, as the BitTorrent client session is not
+        // started.
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn it_should_redirect_to_the_magnet_link_without_resolving_the_torrent() {
+        let dir = TempDir::new().unwrap();
+
+        let response = get_metainfo_file_handler(
+            State(Arc::new(app_state(&dir))),
+            ConnectInfo("127.0.0.1:8080".parse().unwrap()),
+            Path(InfoHashParam(
+                "443c7602b4fde83d1154d6d9da48808418b181b6".to_string(),
+            )),
+            Query(TorrentParams {
+                timeout_ms: None,
+                cache_only: false,
+                dn: None,
+                format: TorrentFormat::Magnet,
+                inject_trackers: false,
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "magnet:?xt=urn:btih:443c7602b4fde83d1154d6d9da48808418b181b6"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_not_resolve_any_torrent_in_cache_only_mode() {
+        let dir = TempDir::new().unwrap();
+
+        let mut app_state = app_state(&dir);
+        Arc::make_mut(&mut app_state.config).api.cache_only = true;
+
+        let response = get_metadata_handler(
+            State(Arc::new(app_state)),
+            Path(InfoHashParam(
+                "443c7602b4fde83d1154d6d9da48808418b181b6".to_string(),
+            )),
+            Query(MetadataParams {
+                timeout_ms: None,
+                include_pieces: false,
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_the_requests_while_in_maintenance_mode() {
+        let dir = TempDir::new().unwrap();
+        let app_state = Arc::new(app_state(&dir));
+
+        let router = Router::new()
+            .route("/torrents/:info_hash/metadata", get(get_metadata_handler))
+            .route_layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                reject_in_maintenance,
+            ))
+            .with_state(app_state.clone());
+
+        let request = || {
+            Request::builder()
+                .uri("/torrents/443c7602b4fde83d1154d6d9da48808418b181b6/metadata")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        set_maintenance_handler(
+            State(app_state.clone()),
+            Json(MaintenanceStatus { enabled: true }),
+        )
+        .await;
+
+        let response = router.clone().oneshot(request()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ErrorBody>(&body).unwrap().message,
+            "Under maintenance"
+        );
+
+        set_maintenance_handler(
+            State(app_state.clone()),
+            Json(MaintenanceStatus { enabled: false }),
+        )
+        .await;
+
+        let response = router.oneshot(request()).await.unwrap();
+
+        // It's not cached and the BitTorrent client session is not started.
+        assert_ne!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_torrents_with_more_files_than_the_maximum() {
+        let dir = TempDir::new().unwrap();
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let mut app_state = app_state(&dir);
+        Arc::make_mut(&mut app_state.config).api.max_files = Some(0);
+        app_state
+            .cache
+            .add(&info_hash, &sample_torrent())
+            .await
+            .unwrap();
+
+        let response = get_metadata_handler(
+            State(Arc::new(app_state)),
+            Path(InfoHashParam(info_hash.to_hex_string())),
+            Query(MetadataParams {
+                timeout_ms: None,
+                include_pieces: false,
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    async fn batch(app_state: AppState, info_hashes: &[&str]) -> Response {
+        batch_metadata_handler(
+            State(Arc::new(app_state)),
+            Query(ResolveParams { timeout_ms: None }),
+            Json(BatchRequest(
+                info_hashes.iter().map(ToString::to_string).collect(),
+            )),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_batches_with_more_info_hashes_than_the_maximum() {
+        let dir = TempDir::new().unwrap();
+
+        let response = batch(
+            app_state(&dir),
+            &[
+                "443c7602b4fde83d1154d6d9da48808418b181b6",
+                "543c7602b4fde83d1154d6d9da48808418b181b6",
+                "643c7602b4fde83d1154d6d9da48808418b181b6",
+            ],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_batches_with_an_invalid_info_hash() {
+        let dir = TempDir::new().unwrap();
+
+        let response = batch(
+            app_state(&dir),
+            &["443c7602b4fde83d1154d6d9da48808418b181b6", "invalid"],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_result_of_each_info_hash_in_the_batch() {
+        let dir = TempDir::new().unwrap();
+        let app_state = app_state(&dir);
+
+        let cached = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+        let not_cached = InfoHash::from_str("543c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        app_state
+            .cache
+            .add(&cached, &sample_torrent())
+            .await
+            .unwrap();
+
+        let response = batch(
+            app_state,
+            &[&cached.to_hex_string(), &not_cached.to_hex_string()],
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Vec<BatchResult>>(&body).unwrap(),
+            vec![
+                BatchResult::Ok {
+                    info_hash: cached,
+                    metadata: Metadata {
+                        info_hash: cached,
+                        name: "sample.txt".to_string(),
+                        name_valid_utf8: true,
+                        total_size: 11,
+                        piece_length: 16384,
+                        files: vec![File {
+                            path: "sample.txt".to_string(),
+                            length: 11
+                        }],
+                        pieces: None,
+                    },
+                },
+                // The BitTorrent client session is not started.
+                BatchResult::Error {
+                    info_hash: not_cached,
+                    error: "client_error".to_string(),
+                    message: "BitTorrent client error".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Load shedding for the magnet link resolutions.
+//!
+//! Resolutions wait for a free slot in the `BitTorrent` client when there are
+//! too many of them, so under overload the pending ones pile up and the
+//! latency collapses. With a ceiling on the resolutions in progress (running
+//! or waiting for a slot), the new ones are rejected right away with a `503`
+//! instead.
+//!
+//! Under heavier overload, new connections can also be closed right after
+//! they are accepted (see [`OverloadAcceptor`]), which is cheaper than
+//! reading their requests to reject them.
+use std::future::Ready;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use axum_server::accept::Accept;
+use futures_util::future::Either;
+use prometheus_client::metrics::gauge::Gauge;
+use tracing::debug;
+
+pub struct PendingResolutions {
+    /// No new resolutions are accepted when there are already that many.
+    /// There is no limit when it's `None`.
+    max: Option<usize>,
+    count: AtomicUsize,
+    /// Exposes the count in the metrics.
+    gauge: Gauge,
+}
+
+/// A pending resolution. It's removed from the count when it's dropped.
+pub struct PendingResolution {
+    pending: Arc<PendingResolutions>,
+}
+
+impl PendingResolutions {
+    #[must_use]
+    pub fn new(max: Option<usize>, gauge: Gauge) -> Self {
+        Self {
+            max,
+            count: AtomicUsize::new(0),
+            gauge,
+        }
+    }
+
+    /// Adds a resolution to the count, unless the ceiling is reached.
+    #[must_use]
+    pub fn try_add(self: &Arc<Self>) -> Option<PendingResolution> {
+        let count = self
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                match self.max {
+                    Some(max) if count >= max => None,
+                    _ => Some(count + 1),
+                }
+            })
+            .ok()?;
+
+        self.gauge.set(gauge_value(count + 1));
+
+        Some(PendingResolution {
+            pending: self.clone(),
+        })
+    }
+
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for PendingResolution {
+    fn drop(&mut self) {
+        let count = self.pending.count.fetch_sub(1, Ordering::AcqRel);
+        self.pending.gauge.set(gauge_value(count - 1));
+    }
+}
+
+/// Closes the new connections while there are more resolutions in progress
+/// than the threshold. The other connections are accepted by the inner
+/// acceptor.
+#[derive(Clone)]
+pub struct OverloadAcceptor<A> {
+    inner: A,
+    pending: Arc<PendingResolutions>,
+    /// All the connections are accepted when it's `None`.
+    threshold: Option<usize>,
+}
+
+impl<A> OverloadAcceptor<A> {
+    #[must_use]
+    pub fn new(inner: A, pending: Arc<PendingResolutions>, threshold: Option<usize>) -> Self {
+        Self {
+            inner,
+            pending,
+            threshold,
+        }
+    }
+
+    fn is_overloaded(&self) -> bool {
+        self.threshold
+            .is_some_and(|threshold| self.pending.count() > threshold)
+    }
+}
+
+impl<A, I, S> Accept<I, S> for OverloadAcceptor<A>
+where
+    A: Accept<I, S>,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Either<Ready<io::Result<(A::Stream, A::Service)>>, A::Future>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        if self.is_overloaded() {
+            debug!("too many pending resolutions, closing the new connection");
+
+            // The stream is dropped, so the connection is closed.
+            return Either::Left(std::future::ready(Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "too many pending resolutions",
+            ))));
+        }
+
+        Either::Right(self.inner.accept(stream, service))
+    }
+}
+
+fn gauge_value(count: usize) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::sync::Arc;
+
+    use axum::routing::get;
+    use axum::Router;
+    use axum_server::accept::DefaultAcceptor;
+    use prometheus_client::metrics::gauge::Gauge;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::{OverloadAcceptor, PendingResolutions};
+
+    /// Sends a request and returns the response, which is empty if the
+    /// connection was closed.
+    async fn request(address: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+
+        // The server may have closed the connection already.
+        let _ = stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await;
+
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response).await;
+
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[test]
+    fn it_should_reject_new_resolutions_when_the_ceiling_is_reached() {
+        let gauge = Gauge::default();
+        let pending = Arc::new(PendingResolutions::new(Some(2), gauge.clone()));
+
+        let first = pending.try_add();
+        let second = pending.try_add();
+
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(pending.try_add().is_none());
+        assert_eq!(gauge.get(), 2);
+
+        drop(first);
+
+        assert_eq!(pending.count(), 1);
+        assert_eq!(gauge.get(), 1);
+        assert!(pending.try_add().is_some());
+    }
+
+    #[tokio::test]
+    async fn it_should_close_the_new_connections_while_the_threshold_is_exceeded() {
+        let pending = Arc::new(PendingResolutions::new(None, Gauge::default()));
+
+        let socket = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+        let acceptor = OverloadAcceptor::new(DefaultAcceptor::new(), pending.clone(), Some(1));
+
+        tokio::spawn(async move {
+            axum_server::from_tcp(socket)
+                .acceptor(acceptor)
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        let first = pending.try_add();
+
+        assert!(request(address).await.starts_with("HTTP/1.1 200 OK"));
+
+        let second = pending.try_add();
+
+        assert_eq!(request(address).await, "");
+
+        drop((first, second));
+
+        assert!(request(address).await.starts_with("HTTP/1.1 200 OK"));
+    }
+}
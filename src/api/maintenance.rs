@@ -0,0 +1,59 @@
+//! Maintenance mode.
+//!
+//! While it's enabled, the endpoints that resolve torrents return the
+//! configured message and status (usually `503 Service Unavailable`) without
+//! resolving the torrents. The health checks are not affected, so load
+//! balancers keep the instance in the pool.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use crate::api::error::ApiError;
+use crate::AppState;
+
+/// The maintenance mode state. It can be toggled at runtime with the admin
+/// endpoint.
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+}
+
+impl MaintenanceMode {
+    #[must_use]
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Middleware that rejects the requests while the maintenance mode is
+/// enabled.
+pub async fn reject_in_maintenance(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if app_state.maintenance.is_enabled() {
+        let maintenance = &app_state.config.api.maintenance;
+
+        return ApiError::Maintenance {
+            message: maintenance.message.clone(),
+            status: maintenance.status,
+        }
+        .into_response();
+    }
+
+    next.run(request).await
+}
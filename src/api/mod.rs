@@ -1,71 +1,435 @@
+pub mod access_list;
+pub mod auth;
 pub mod cache;
+pub mod connection_limit;
+pub mod disk_space;
+pub mod error;
+pub mod filename;
 pub mod handler;
+pub mod load_shedding;
+pub mod maintenance;
+pub mod negative_cache;
+pub mod placeholder;
+pub mod rate_limit;
+pub mod reload;
+pub mod server_timing;
+pub mod single_flight;
 pub mod slowloris;
+pub mod top_failures;
+pub mod warm_up;
 
-use axum::error_handling::HandleErrorLayer;
+use axum::routing::{delete, get, post};
+use axum::{middleware, Router};
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use axum_server::{Handle, Server};
 
-use axum::routing::get;
-use axum::{BoxError, Router};
-use axum_server::Server;
-
-use handler::{entrypoint_handler, get_metainfo_file_handler, health_check_handler};
-use hyper::StatusCode;
+use futures_util::future::join_all;
+use handler::{
+    batch_metadata_handler, clear_cache_handler, delete_torrent_handler, entrypoint_handler,
+    get_announce_list_handler, get_cache_keys_handler, get_cache_stats_handler,
+    get_file_tree_handler, get_magnet_link_handler, get_maintenance_handler, get_metadata_handler,
+    get_metainfo_file_handler, get_status_handler, get_top_failures_handler,
+    head_metainfo_file_handler, health_check_handler, info_hash_headers, liveness_handler,
+    metrics_handler, readiness_handler, reload_config_handler, set_maintenance_handler,
+};
+use hyper::header::{self, HeaderName, HeaderValue};
 use hyper_util::rt::TokioTimer;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
 use std::net::{SocketAddr, TcpListener};
+use std::pin::pin;
 
 use std::sync::Arc;
-use std::time::Duration;
 
-use tower::{timeout::TimeoutLayer, ServiceBuilder};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{error, info, info_span, warn};
 
+use crate::api::auth::{require_admin_token, require_api_token};
+use crate::api::connection_limit::{ConnectionLimitAcceptor, OpenConnections};
+use crate::api::error::{add_retry_after, negotiate_error_format};
+use crate::api::load_shedding::OverloadAcceptor;
+use crate::api::maintenance::reject_in_maintenance;
+use crate::api::rate_limit::rate_limit;
 use crate::api::slowloris::TimeoutAcceptor;
+use crate::config::{self, AllowedOrigins, HttpTimeouts};
 use crate::AppState;
 
-const TIMEOUT: Duration = Duration::from_secs(10);
+/// Responses smaller than this (in bytes) are not compressed, as the
+/// compression overhead would be bigger than the savings.
+const COMPRESSION_MIN_SIZE: u16 = 1024;
+
+/// Header used to correlate the log lines of a request.
+const REQUEST_ID_HEADER: &str = "x-request-id";
 
-/// It starts the web server.
+/// It starts the web server, listening on all the bind addresses.
+///
+/// If TLS is configured, the API is served over HTTPS. The slowloris
+/// protection is applied to the TCP stream, underneath the TLS layer, so it
+/// also covers the TLS handshake.
+///
+/// The server stops gracefully on `SIGINT` or `SIGTERM`: it stops accepting
+/// new connections and waits for the in-flight requests to finish, up to the
+/// configured shutdown grace period.
+///
+/// When a config reload changes the bind addresses, the connections are
+/// drained the same way, up to the configured rebind drain deadline, and then
+/// the server listens on the new addresses. If they can't be bound, it
+/// listens on the previous ones again.
 ///
 /// # Panics
 ///
-/// Will panic if it can get the local server address
-pub async fn start(bind_to: &SocketAddr, state: AppState) {
-    let socket =
-        std::net::TcpListener::bind(bind_to).expect("Could not bind tcp_listener to address.");
+/// Will panic if it can't bind to any of the addresses on startup, it can get
+/// the local server address or it can't load the TLS certificate and key.
+pub async fn start(state: Arc<AppState>) {
+    let mut bind_addresses = state.reloader.bind_addresses();
+
+    let tls = state.config.api.tls.clone();
+    let http_timeouts = state.config.api.http_timeouts.clone();
+    // The connection limit and overloaded checks come first, so the
+    // connections closed don't start the TLS handshake. All the listeners
+    // share the connection limit.
+    let acceptor = ConnectionLimitAcceptor::new(
+        OverloadAcceptor::new(
+            TimeoutAcceptor::new(
+                http_timeouts.slowloris_timeout,
+                http_timeouts.slowloris_jitter,
+            ),
+            state.pending_resolutions.clone(),
+            state.config.api.connection_shedding_threshold,
+        ),
+        Arc::new(OpenConnections::new(
+            state.config.api.max_connections,
+            state.metrics.open_connections(),
+        )),
+    );
+    let max_header_size = state.config.api.request_limits.max_header_size;
+    let grace_period = state.config.api.shutdown_grace_period;
+    let drain_deadline = state.config.api.rebind_drain_deadline;
+
+    let scheme = if tls.is_some() { "https" } else { "http" };
+
+    let app = router(state);
+
+    let make_service = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+
+    let rustls_config = match tls {
+        Some(tls) => Some(
+            RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+                .expect("Could not load TLS certificate and key."),
+        ),
+        None => None,
+    };
+
+    // Each listener has its own server, so the slowloris protection and the
+    // client address (`ConnectInfo`) apply to its own connections.
+    let serve = |socket: TcpListener, handle: Handle| {
+        let server = from_tcp_with_timeouts(socket, &http_timeouts, max_header_size).handle(handle);
+        let make_service = make_service.clone();
+        let rustls_config = rustls_config.clone();
+        let acceptor = acceptor.clone();
+
+        async move {
+            match rustls_config {
+                Some(rustls_config) => {
+                    server
+                        .acceptor(RustlsAcceptor::new(rustls_config).acceptor(acceptor))
+                        .serve(make_service)
+                        .await
+                }
+                None => server.acceptor(acceptor).serve(make_service).await,
+            }
+            .expect("Axum server crashed.");
+        }
+    };
+
+    let mut shutdown = pin!(shutdown_signal());
+
+    let mut addresses = bind_addresses.borrow_and_update().clone();
+    let mut sockets = bind_all(&addresses).expect("Could not bind tcp_listener to address.");
+
+    loop {
+        for socket in &sockets {
+            let server_address = socket
+                .local_addr()
+                .expect("Could not get local_addr from tcp_listener.");
+
+            info!("API bound to address: {scheme}://{server_address}");
+        }
+
+        // All the servers share the handle, so they are stopped together.
+        let handle = Handle::new();
+
+        let mut servers = pin!(join_all(
+            sockets
+                .into_iter()
+                .map(|socket| serve(socket, handle.clone()))
+        ));
+
+        tokio::select! {
+            _ = &mut servers => return,
+            () = &mut shutdown => {
+                info!(
+                    "shutting down the API, waiting up to {} seconds for in-flight requests ...",
+                    grace_period.as_secs()
+                );
+
+                handle.graceful_shutdown(Some(grace_period));
+                servers.await;
 
-    let server_address = socket
-        .local_addr()
-        .expect("Could not get local_addr from tcp_listener.");
+                return;
+            }
+            Ok(()) = bind_addresses.changed() => {}
+        }
 
-    info!("API bound to address: http://{server_address}"); // DevSkim: ignore DS137138
+        let new_addresses = bind_addresses.borrow_and_update().clone();
 
-    let server = from_tcp_with_timeouts(socket);
+        info!(
+            "draining the API connections to listen on {new_addresses:?}, waiting up to {} seconds for in-flight requests ...",
+            drain_deadline.as_secs()
+        );
+
+        handle.graceful_shutdown(None);
+
+        if tokio::time::timeout(drain_deadline, &mut servers)
+            .await
+            .is_err()
+        {
+            warn!(
+                "the rebind drain deadline elapsed with {} connections still handling requests, closing them",
+                handle.connection_count()
+            );
+
+            handle.shutdown();
+            servers.await;
+        }
+
+        sockets = match bind_all(&new_addresses) {
+            Ok(sockets) => {
+                addresses = new_addresses;
+                sockets
+            }
+            Err(err) => {
+                error!(
+                    "can't listen on the new bind addresses {new_addresses:?}: {err}, listening on {addresses:?} again"
+                );
+
+                bind_all(&addresses).expect("Could not bind tcp_listener to address.")
+            }
+        };
+    }
+}
+
+fn bind_all(addresses: &[SocketAddr]) -> io::Result<Vec<TcpListener>> {
+    addresses.iter().map(bind).collect()
+}
+
+/// Binds a TCP listener to the address.
+///
+/// IPv6 sockets only accept IPv6 connections, so the same port can also be
+/// bound for IPv4 to listen on both (dual-stack).
+fn bind(address: &SocketAddr) -> io::Result<TcpListener> {
+    let socket = Socket::new(
+        Domain::for_address(*address),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+
+    // Like the standard library, so the address can be bound again right
+    // after a restart.
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+
+    socket.bind(&(*address).into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
+
+/// Builds the router with all the endpoints and middlewares.
+pub fn router(state: Arc<AppState>) -> Router {
+    let cors = state.config.api.cors.clone();
+    let info_hash_header_prefix = state.config.api.info_hash_header_prefix.clone();
+    let request_timeouts = state.config.api.request_timeouts.clone();
+    let max_body_size = state.config.api.request_limits.max_body_size;
+
+    // Only the endpoints that resolve torrents are rate limited.
+    let torrents = Router::new()
+        .route("/torrents", post(batch_metadata_handler))
+        .route(
+            "/torrents/:info_hash",
+            get(get_metainfo_file_handler).head(head_metainfo_file_handler),
+        )
+        .route("/torrents/:info_hash/metadata", get(get_metadata_handler))
+        .route("/torrents/:info_hash/files", get(get_file_tree_handler))
+        .route(
+            "/torrents/:info_hash/announce-list",
+            get(get_announce_list_handler),
+        )
+        .route("/torrents/:info_hash/status", get(get_status_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            reject_in_maintenance,
+        ))
+        .layer(TimeoutLayer::new(request_timeouts.resolve));
 
-    let app = Router::new()
+    let admin = Router::new()
+        .route("/torrents/:info_hash", delete(delete_torrent_handler))
+        .route("/cache", delete(clear_cache_handler))
+        .route("/admin/cache/stats", get(get_cache_stats_handler))
+        .route("/admin/cache/keys", get(get_cache_keys_handler))
+        .route("/admin/top-failures", get(get_top_failures_handler))
+        .route(
+            "/maintenance",
+            get(get_maintenance_handler).put(set_maintenance_handler),
+        )
+        .route("/admin/reload", post(reload_config_handler))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_admin_token,
+        ))
+        .layer(TimeoutLayer::new(request_timeouts.resolve));
+
+    // The health checks are always open, so load balancers and orchestrators
+    // can use them without the token.
+    let protected = Router::new()
         .route("/", get(entrypoint_handler))
+        .route("/metrics", get(metrics_handler))
+        // It doesn't resolve the torrent, so it's not rate limited.
+        .route("/torrents/:info_hash/magnet", get(get_magnet_link_handler))
+        .layer(TimeoutLayer::new(request_timeouts.default))
+        .merge(torrents)
+        .merge(admin)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            require_api_token,
+        ));
+
+    let router = Router::new()
         .route("/health_check", get(health_check_handler))
-        .route("/torrents/:info_hash", get(get_metainfo_file_handler))
-        .layer(TraceLayer::new_for_http())
+        .route("/health/live", get(liveness_handler))
+        .route("/health/ready", get(readiness_handler))
+        .layer(TimeoutLayer::new(request_timeouts.default))
+        .merge(protected)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            add_retry_after,
+        ))
+        .layer(middleware::from_fn(negotiate_error_format))
+        // Larger bodies get a `413 Payload Too Large`.
+        .layer(RequestBodyLimitLayer::new(max_body_size))
         .layer(
-            ServiceBuilder::new()
-                // this middleware goes above `TimeoutLayer` because it will receive
-                // errors returned by `TimeoutLayer`
-                .layer(HandleErrorLayer::new(|_: BoxError| async {
-                    StatusCode::REQUEST_TIMEOUT
-                }))
-                .layer(TimeoutLayer::new(TIMEOUT)),
+            CompressionLayer::new()
+                .compress_when(DefaultPredicate::new().and(SizeAbove::new(COMPRESSION_MIN_SIZE))),
         )
-        .with_state(Arc::new(state));
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &axum::extract::Request| {
+                let request_id = request
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|request_id| request_id.to_str().ok())
+                    .unwrap_or_default();
 
-    server
-        .acceptor(TimeoutAcceptor)
-        .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
-        .await
-        .expect("Axum server crashed.");
+                info_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    request_id
+                )
+            }),
+        )
+        // The request ID is reused if the client (or a proxy) sends it, or
+        // generated otherwise, and it's always sent back in the response.
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid));
+
+    // It's the outermost layer, so preflight requests are answered before
+    // checking the token.
+    match cors {
+        Some(cors) => router.layer(cors_layer(&cors, &info_hash_header_prefix)),
+        None => router,
+    }
+    .with_state(state)
+}
+
+fn cors_layer(cors: &config::Cors, info_hash_header_prefix: &str) -> CorsLayer {
+    let allow_origin = match &cors.allowed_origins {
+        AllowedOrigins::Any => AllowOrigin::any(),
+        // The origins are validated with the configuration.
+        AllowedOrigins::List(origins) => AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        ),
+    };
+
+    let layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(cors.allowed_methods.clone())
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE]);
+
+    if cors.expose_info_hash_header {
+        let (info_hash_header, version_header) = info_hash_headers(info_hash_header_prefix);
+
+        // The prefix is validated with the configuration.
+        layer.expose_headers(
+            [info_hash_header, version_header]
+                .into_iter()
+                .filter_map(|name| HeaderName::try_from(name).ok())
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        layer
+    }
+}
+
+/// Resolves when the process receives `SIGINT` (Ctrl+C) or `SIGTERM`.
+///
+/// # Panics
+///
+/// Will panic if it can't install the signal handlers.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
 }
 
-fn from_tcp_with_timeouts(socket: TcpListener) -> Server {
+/// Builds the server with the connection timeouts and the max header size.
+/// Requests with larger headers get a `431 Request Header Fields Too Large`.
+fn from_tcp_with_timeouts(
+    socket: TcpListener,
+    timeouts: &HttpTimeouts,
+    max_header_size: usize,
+) -> Server {
     let mut server = axum_server::from_tcp(socket);
 
     server.http_builder().http1().timer(TokioTimer::new());
@@ -74,12 +438,94 @@ fn from_tcp_with_timeouts(socket: TcpListener) -> Server {
     server
         .http_builder()
         .http1()
-        .header_read_timeout(Duration::from_secs(1));
+        .header_read_timeout(timeouts.header_read_timeout);
+    server
+        .http_builder()
+        .http2()
+        .keep_alive_timeout(timeouts.keep_alive_timeout)
+        .keep_alive_interval(timeouts.keep_alive_interval);
+
+    // The size is validated with the configuration, as hyper panics with
+    // buffers smaller than the minimum.
+    server.http_builder().http1().max_buf_size(max_header_size);
     server
         .http_builder()
         .http2()
-        .keep_alive_timeout(Duration::from_secs(1))
-        .keep_alive_interval(Duration::from_secs(1));
+        .max_header_list_size(u32::try_from(max_header_size).unwrap_or(u32::MAX));
 
     server
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::{header, Method, Request};
+    use tower::ServiceExt;
+
+    use super::{bind, cors_layer};
+    use crate::config::{AllowedOrigins, Cors};
+
+    #[test]
+    fn it_should_bind_the_same_port_for_ipv4_and_ipv6() {
+        let ipv4 = bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))).unwrap();
+        let port = ipv4.local_addr().unwrap().port();
+
+        let ipv6 = bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))).unwrap();
+
+        assert_eq!(ipv6.local_addr().unwrap().port(), port);
+    }
+
+    #[tokio::test]
+    async fn it_should_answer_the_cors_preflight_requests_for_the_allowed_origins() {
+        let router = Router::new()
+            .route("/torrents", get(|| async { "torrents" }))
+            .layer(cors_layer(
+                &Cors {
+                    allowed_origins: AllowedOrigins::List(vec![
+                        "https://app.example.com".to_string()
+                    ]),
+                    allowed_methods: vec![Method::GET],
+                    expose_info_hash_header: true,
+                },
+                "x-torrust-torrent",
+            ));
+
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/torrents")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET"
+        );
+
+        let response = router
+            .oneshot(preflight("https://other.example.com"))
+            .await
+            .unwrap();
+
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
@@ -0,0 +1,147 @@
+//! Negative cache for the torrents that can't be resolved.
+//!
+//! Torrents without peers take the whole resolve timeout to fail. The recent
+//! failures are remembered for a short time, so repeated requests for them
+//! get a `404 Not Found` immediately, without looking for peers again. The
+//! entries expire, so torrents seeded later are eventually resolved.
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use lru::LruCache;
+
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::clock::{Clock, SystemClock};
+use crate::config::Cache as CacheConfig;
+
+pub struct NegativeCache {
+    /// `None` when the negative cache is disabled.
+    ttl: Option<Duration>,
+    /// When each torrent failed to be resolved.
+    failures: Mutex<LruCache<InfoHash, SystemTime>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl NegativeCache {
+    /// It keeps at most as many failures as torrents in the cache.
+    #[must_use]
+    pub fn new(config: &CacheConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// It uses the given clock to calculate the age of the failures.
+    #[must_use]
+    pub fn with_clock(config: &CacheConfig, clock: Arc<dyn Clock>) -> Self {
+        let capacity = NonZeroUsize::new(config.max_entries).unwrap_or(NonZeroUsize::MIN);
+
+        Self {
+            ttl: config.negative_ttl,
+            failures: Mutex::new(LruCache::new(capacity)),
+            clock,
+        }
+    }
+
+    /// Remembers that the torrent could not be resolved.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn add(&self, info_hash: &InfoHash) {
+        if self.ttl.is_none() {
+            return;
+        }
+
+        self.failures
+            .lock()
+            .expect("negative cache lock poisoned")
+            .put(*info_hash, self.clock.now());
+    }
+
+    /// Returns true if the torrent failed to be resolved recently. Expired
+    /// failures are forgotten.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn contains(&self, info_hash: &InfoHash) -> bool {
+        let Some(ttl) = self.ttl else {
+            return false;
+        };
+
+        let mut failures = self.failures.lock().expect("negative cache lock poisoned");
+
+        let Some(failed_at) = failures.get(info_hash) else {
+            return false;
+        };
+
+        // A time in the future (clock changes) is not considered expired.
+        if self
+            .clock
+            .now()
+            .duration_since(*failed_at)
+            .is_ok_and(|age| age > ttl)
+        {
+            failures.pop(info_hash);
+            return false;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::NegativeCache;
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::clock::StoppedClock;
+    use crate::config::Cache as CacheConfig;
+
+    fn negative_cache(negative_ttl: Option<Duration>) -> (NegativeCache, Arc<StoppedClock>) {
+        let clock = Arc::new(StoppedClock::new(SystemTime::now()));
+
+        let negative_cache = NegativeCache::with_clock(
+            &CacheConfig {
+                ttl: None,
+                max_entries: 10,
+                disk: false,
+                max_disk_size: None,
+                max_concurrent_disk_writes: 1,
+                negative_ttl,
+            },
+            clock.clone(),
+        );
+
+        (negative_cache, clock)
+    }
+
+    #[test]
+    fn it_should_remember_the_failures_until_they_expire() {
+        let (negative_cache, clock) = negative_cache(Some(Duration::from_secs(60)));
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        negative_cache.add(&info_hash);
+
+        clock.advance(Duration::from_secs(60));
+
+        assert!(negative_cache.contains(&info_hash));
+
+        clock.advance(Duration::from_secs(1));
+
+        assert!(!negative_cache.contains(&info_hash));
+    }
+
+    #[test]
+    fn it_should_not_remember_anything_when_it_is_disabled() {
+        let (negative_cache, _clock) = negative_cache(None);
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        negative_cache.add(&info_hash);
+
+        assert!(!negative_cache.contains(&info_hash));
+    }
+}
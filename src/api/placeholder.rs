@@ -0,0 +1,56 @@
+//! Placeholder torrent files for the torrents that can't be resolved.
+//!
+//! Some clients can't handle error responses. With the placeholder mode
+//! enabled, they get a `200 OK` with a valid torrent file with a single empty
+//! file named after the requested info hash. The response has the
+//! [`RESOLUTION_HEADER`] header, so the clients checking it can tell it apart
+//! from a resolved torrent.
+//!
+//! The placeholder has its own info hash, not the requested one.
+use bytes::Bytes;
+
+use crate::bit_torrent::info_hash::InfoHash;
+
+/// Header sent with the placeholders, with the [`RESOLUTION_FAILED`] value.
+pub const RESOLUTION_HEADER: &str = "x-torrust-resolution";
+
+pub const RESOLUTION_FAILED: &str = "failed";
+
+/// The piece length of the placeholders. They don't have pieces, but the
+/// field is required.
+const PIECE_LENGTH: u32 = 16384;
+
+/// Returns the bencoded placeholder torrent file for the info hash.
+#[must_use]
+pub fn torrent(info_hash: &InfoHash) -> Bytes {
+    let name = info_hash.to_hex_string();
+
+    Bytes::from(format!(
+        "d4:infod6:lengthi0e4:name{}:{name}12:piece lengthi{PIECE_LENGTH}e6:pieces0:ee",
+        name.len()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use librqbit::{torrent_from_bytes, ByteBuf};
+
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    #[test]
+    fn it_should_build_a_valid_torrent_with_an_empty_file_named_after_the_info_hash() {
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let bytes = super::torrent(&info_hash);
+        let torrent = torrent_from_bytes::<ByteBuf>(&bytes).unwrap();
+
+        assert_eq!(
+            torrent.info.name.map(|name| name.as_ref().to_vec()),
+            Some(b"443c7602b4fde83d1154d6d9da48808418b181b6".to_vec())
+        );
+        assert_eq!(torrent.info.length, Some(0));
+        assert!(torrent.info.files.is_none());
+    }
+}
@@ -0,0 +1,293 @@
+//! Per-IP rate limiting.
+//!
+//! Each client IP gets a [token bucket](https://en.wikipedia.org/wiki/Token_bucket)
+//! that holds up to `burst` tokens and is refilled at `requests_per_second`
+//! tokens per second. Every request takes one token. When the bucket is empty
+//! the request is rejected with `429 Too Many Requests` and a `Retry-After`
+//! header with the seconds until the next token is available.
+//!
+//! The number of buckets is capped, so clients sending requests from many
+//! IPs can't exhaust the memory. When the cap is reached, the least recently
+//! used bucket is removed, and its IP gets a full bucket on the next request.
+//!
+//! The limits can be changed (or rate limiting enabled and disabled) at
+//! runtime, when the config is reloaded.
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use lru::LruCache;
+
+use crate::api::error::ApiError;
+use crate::config::RateLimit as RateLimitConfig;
+use crate::AppState;
+
+/// Maximum number of buckets (client IPs) tracked at the same time.
+const MAX_BUCKETS: NonZeroUsize = match NonZeroUsize::new(10_000) {
+    Some(max) => max,
+    None => panic!("the maximum number of buckets must not be zero"),
+};
+
+pub struct RateLimiter {
+    state: Mutex<Buckets>,
+}
+
+/// The limits and the buckets, behind the same lock.
+struct Buckets {
+    /// `None` when rate limiting is disabled.
+    limits: Option<Limits>,
+    buckets: LruCache<IpAddr, Bucket>,
+}
+
+#[derive(Clone, Copy)]
+struct Limits {
+    requests_per_second: f64,
+    burst: f64,
+}
+
+impl From<&RateLimitConfig> for Limits {
+    fn from(config: &RateLimitConfig) -> Self {
+        Self {
+            requests_per_second: config.requests_per_second,
+            burst: f64::from(config.burst),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl RateLimiter {
+    /// It doesn't limit the requests when the `config` is `None`.
+    #[must_use]
+    pub fn new(config: Option<&RateLimitConfig>) -> Self {
+        Self::with_max_buckets(config, MAX_BUCKETS)
+    }
+
+    fn with_max_buckets(config: Option<&RateLimitConfig>, max_buckets: NonZeroUsize) -> Self {
+        Self {
+            state: Mutex::new(Buckets {
+                limits: config.map(Limits::from),
+                buckets: LruCache::new(max_buckets),
+            }),
+        }
+    }
+
+    /// Replaces the limits, or disables rate limiting when the `config` is
+    /// `None`. The buckets are kept, with their tokens capped to the new
+    /// burst on the next request.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn set_config(&self, config: Option<&RateLimitConfig>) {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+
+        state.limits = config.map(Limits::from);
+
+        if state.limits.is_none() {
+            state.buckets.clear();
+        }
+    }
+
+    /// Takes a token from the client bucket. It always succeeds when rate
+    /// limiting is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return the time until the next token is available if the bucket
+    /// is empty.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+
+        let Some(limits) = state.limits else {
+            return Ok(());
+        };
+
+        let bucket = state.buckets.get_or_insert_mut(ip, || Bucket {
+            tokens: limits.burst,
+            updated_at: now,
+        });
+
+        bucket.tokens = limits.refilled_tokens(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        // The limits are validated, but it must not panic if they are not.
+        Err(
+            Duration::try_from_secs_f64((1.0 - bucket.tokens) / limits.requests_per_second)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+impl Limits {
+    fn refilled_tokens(self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        (bucket.tokens + elapsed.as_secs_f64() * self.requests_per_second).min(self.burst)
+    }
+}
+
+/// Middleware that rejects the request if the client IP has exceeded the rate
+/// limit. It does nothing when rate limiting is disabled.
+pub async fn rate_limit(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Err(retry_after) = app_state.rate_limiter.check(addr.ip(), Instant::now()) {
+        // Round up so clients don't retry before the token is available.
+        let retry_after = retry_after
+            .as_secs()
+            .saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+
+        return ApiError::TooManyRequests { retry_after }.into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, Instant};
+
+    use std::num::NonZeroUsize;
+
+    use super::RateLimiter;
+    use crate::config::RateLimit;
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, n))
+    }
+
+    #[test]
+    fn it_should_allow_a_burst_of_requests() {
+        let rate_limiter = RateLimiter::new(Some(&RateLimit {
+            requests_per_second: 1.0,
+            burst: 3,
+        }));
+        let now = Instant::now();
+
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(1), now).is_err());
+    }
+
+    #[test]
+    fn it_should_return_the_time_until_the_next_token_is_available() {
+        let rate_limiter = RateLimiter::new(Some(&RateLimit {
+            requests_per_second: 2.0,
+            burst: 1,
+        }));
+        let now = Instant::now();
+
+        rate_limiter.check(ip(1), now).unwrap();
+
+        assert_eq!(
+            rate_limiter.check(ip(1), now),
+            Err(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn it_should_refill_the_bucket_over_time() {
+        let rate_limiter = RateLimiter::new(Some(&RateLimit {
+            requests_per_second: 1.0,
+            burst: 1,
+        }));
+        let now = Instant::now();
+
+        rate_limiter.check(ip(1), now).unwrap();
+        assert!(rate_limiter.check(ip(1), now).is_err());
+
+        assert!(rate_limiter
+            .check(ip(1), now + Duration::from_secs(1))
+            .is_ok());
+    }
+
+    #[test]
+    fn it_should_keep_a_separate_bucket_for_each_ip() {
+        let rate_limiter = RateLimiter::new(Some(&RateLimit {
+            requests_per_second: 1.0,
+            burst: 1,
+        }));
+        let now = Instant::now();
+
+        rate_limiter.check(ip(1), now).unwrap();
+
+        assert!(rate_limiter.check(ip(2), now).is_ok());
+    }
+
+    #[test]
+    fn it_should_remove_the_least_recently_used_bucket_when_the_maximum_is_reached() {
+        let rate_limiter = RateLimiter::with_max_buckets(
+            Some(&RateLimit {
+                requests_per_second: 1.0,
+                burst: 1,
+            }),
+            NonZeroUsize::new(2).unwrap(),
+        );
+        let now = Instant::now();
+
+        rate_limiter.check(ip(1), now).unwrap();
+        rate_limiter.check(ip(2), now).unwrap();
+        rate_limiter.check(ip(3), now).unwrap();
+
+        assert_eq!(rate_limiter.state.lock().unwrap().buckets.len(), 2);
+
+        // The bucket of the first IP was removed, so it's full again.
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(3), now).is_err());
+    }
+
+    #[test]
+    fn it_should_apply_the_new_limits_when_they_are_changed() {
+        let rate_limiter = RateLimiter::new(None);
+        let now = Instant::now();
+
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+
+        rate_limiter.set_config(Some(&RateLimit {
+            requests_per_second: 1.0,
+            burst: 1,
+        }));
+
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+        assert!(rate_limiter.check(ip(1), now).is_err());
+
+        rate_limiter.set_config(None);
+
+        assert!(rate_limiter.check(ip(1), now).is_ok());
+    }
+
+    #[test]
+    fn it_should_not_panic_when_the_limits_never_refill_the_buckets() {
+        let rate_limiter = RateLimiter::new(Some(&RateLimit {
+            requests_per_second: 0.0,
+            burst: 1,
+        }));
+        let now = Instant::now();
+
+        rate_limiter.check(ip(1), now).unwrap();
+
+        assert_eq!(rate_limiter.check(ip(1), now), Err(Duration::MAX));
+    }
+}
@@ -0,0 +1,402 @@
+//! Config reload without restarting the service.
+//!
+//! The settings that can change at runtime are read from an optional JSON
+//! config file. It's applied over the built-in config on startup, and read
+//! again by the `POST /admin/reload` admin endpoint, which applies the
+//! changes without tearing down the `BitTorrent` session. For example:
+//!
+//! ```json
+//! {
+//!   "trackers": ["udp://tracker.example.com:6969/announce"],
+//!   "resolve_timeout_ms": 8000,
+//!   "min_resolve_timeout_ms": 100,
+//!   "max_resolve_timeout_ms": 10000,
+//!   "cache_ttl_secs": 86400,
+//!   "rate_limit": { "requests_per_second": 5.0, "burst": 10 }
+//! }
+//! ```
+//!
+//! All the fields are optional. The missing ones keep the built-in value, and
+//! `null` disables the cache TTL or the rate limit. The `bind_addresses` can
+//! also be set: when a reload changes them, the API drains the connections on
+//! the current addresses and listens on the new ones (see
+//! [`start`](crate::api::start)). The `listen_ports` can be set too, but
+//! changing them requires a restart, so a reload only reports them.
+//!
+//! A reload also reads the allowlist and denylist files again, even when
+//! there is no config file.
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::config::{self, Config, ListenPorts, RateLimit};
+use crate::AppState;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Can't read the config file {path}: {source}")]
+    Read {
+        path: Utf8PathBuf,
+        source: io::Error,
+    },
+    #[error("Invalid config file {path}: {source}")]
+    Parse {
+        path: Utf8PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Invalid config: {0}")]
+    Invalid(#[from] config::Error),
+    #[error("Can't load the access lists: {0}")]
+    AccessLists(io::Error),
+}
+
+/// The contents of the config file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub trackers: Option<Vec<String>>,
+    pub resolve_timeout_ms: Option<u64>,
+    pub min_resolve_timeout_ms: Option<u64>,
+    pub max_resolve_timeout_ms: Option<u64>,
+    /// `Some(None)` (`null`) disables the TTL.
+    #[serde(default, deserialize_with = "nullable")]
+    pub cache_ttl_secs: Option<Option<u64>>,
+    /// `Some(None)` (`null`) disables rate limiting.
+    #[serde(default, deserialize_with = "nullable")]
+    pub rate_limit: Option<Option<RateLimitFile>>,
+    pub bind_addresses: Option<Vec<SocketAddr>>,
+    pub listen_ports: Option<ListenPortsFile>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitFile {
+    pub requests_per_second: f64,
+    pub burst: u32,
+}
+
+/// A list of ports, like `[51000, 51001]`, or a range, like
+/// `{"start": 51000, "end": 51010}` (the end is excluded).
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ListenPortsFile {
+    List(Vec<u16>),
+    Range(Range<u16>),
+}
+
+impl ConfigFile {
+    /// # Errors
+    ///
+    /// Will return an error if the file can't be read or it's not valid.
+    pub fn load(path: &Utf8Path) -> Result<Self, Error> {
+        let contents = fs::read(path).map_err(|source| Error::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        serde_json::from_slice(&contents).map_err(|source| Error::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// It overrides the config with the fields that are in the file.
+    pub fn apply(self, config: &mut Config) {
+        if let Some(trackers) = self.trackers {
+            config.client.trackers = trackers;
+        }
+        if let Some(timeout) = self.resolve_timeout_ms {
+            config.client.resolve_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(timeout) = self.min_resolve_timeout_ms {
+            config.api.min_resolve_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(timeout) = self.max_resolve_timeout_ms {
+            config.api.max_resolve_timeout = Duration::from_millis(timeout);
+        }
+        if let Some(ttl) = self.cache_ttl_secs {
+            config.cache.ttl = ttl.map(Duration::from_secs);
+        }
+        if let Some(rate_limit) = self.rate_limit {
+            config.api.rate_limit = rate_limit.map(|rate_limit| RateLimit {
+                requests_per_second: rate_limit.requests_per_second,
+                burst: rate_limit.burst,
+            });
+        }
+        if let Some(bind_addresses) = self.bind_addresses {
+            config.api.bind_addresses = bind_addresses;
+        }
+        if let Some(listen_ports) = self.listen_ports {
+            config.client.listen_ports = Some(match listen_ports {
+                ListenPortsFile::List(ports) => ListenPorts::List(ports),
+                ListenPortsFile::Range(range) => ListenPorts::Range(range),
+            });
+        }
+    }
+}
+
+/// Deserializes a field that can be missing (`None`) or `null` (`Some(None)`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Returns the config with the config file applied.
+///
+/// # Errors
+///
+/// Will return an error if the file can't be read or it's not valid.
+pub fn with_config_file(config: &Config, path: &Utf8Path) -> Result<Config, Error> {
+    let mut config = config.clone();
+
+    ConfigFile::load(path)?.apply(&mut config);
+
+    Ok(config)
+}
+
+/// The settings that can be changed at runtime.
+#[derive(Clone, Debug, PartialEq)]
+struct Settings {
+    trackers: Vec<String>,
+    resolve_timeout: Duration,
+    min_resolve_timeout: Duration,
+    max_resolve_timeout: Duration,
+    cache_ttl: Option<Duration>,
+    rate_limit: Option<RateLimit>,
+    bind_addresses: Vec<SocketAddr>,
+}
+
+impl From<&Config> for Settings {
+    fn from(config: &Config) -> Self {
+        Self {
+            trackers: config.client.trackers.clone(),
+            resolve_timeout: config.client.resolve_timeout,
+            min_resolve_timeout: config.api.min_resolve_timeout,
+            max_resolve_timeout: config.api.max_resolve_timeout,
+            cache_ttl: config.cache.ttl,
+            rate_limit: config.api.rate_limit.clone(),
+            bind_addresses: config.api.bind_addresses.clone(),
+        }
+    }
+}
+
+/// The result of a reload.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ReloadReport {
+    /// The settings that changed and were applied.
+    pub applied: Vec<String>,
+    /// The settings that changed but are only applied after a restart. The
+    /// `listen_ports` of the `BitTorrent` client always are.
+    pub requires_restart: Vec<String>,
+}
+
+/// It reloads the config file and keeps the settings that can be changed at
+/// runtime.
+pub struct ConfigReloader {
+    /// `None` when there is no config file.
+    path: Option<Utf8PathBuf>,
+    /// The built-in config, without the config file.
+    base: Config,
+    settings: RwLock<Settings>,
+    /// The API listens on the latest ones.
+    bind_addresses: watch::Sender<Vec<SocketAddr>>,
+}
+
+impl ConfigReloader {
+    /// It starts with the settings of the `config`, which is the `base`
+    /// config with the file at `path` applied.
+    #[must_use]
+    pub fn new(path: Option<Utf8PathBuf>, base: Config, config: &Config) -> Self {
+        Self {
+            path,
+            base,
+            settings: RwLock::new(Settings::from(config)),
+            bind_addresses: watch::channel(config.api.bind_addresses.clone()).0,
+        }
+    }
+
+    /// A reloader without a config file. Reloading only reads the access
+    /// list files again.
+    #[must_use]
+    pub fn without_config_file(config: &Config) -> Self {
+        Self::new(None, config.clone(), config)
+    }
+
+    /// Returns the current minimum and maximum `timeout_ms` of the requests.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn resolve_timeout_bounds(&self) -> (Duration, Duration) {
+        let settings = self.settings.read().expect("settings lock poisoned");
+
+        (settings.min_resolve_timeout, settings.max_resolve_timeout)
+    }
+
+    /// Returns the current configured trackers.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn trackers(&self) -> Vec<String> {
+        self.settings
+            .read()
+            .expect("settings lock poisoned")
+            .trackers
+            .clone()
+    }
+
+    /// Returns a receiver of the addresses the API listens on. It's notified
+    /// when a reload changes them.
+    #[must_use]
+    pub fn bind_addresses(&self) -> watch::Receiver<Vec<SocketAddr>> {
+        self.bind_addresses.subscribe()
+    }
+
+    /// Reads the config file again and applies the settings that changed to
+    /// the resolver, the cache, the rate limiter and the API listeners, and
+    /// reloads the access lists. The config is validated and the lists loaded first, so nothing
+    /// is applied if any of them is not valid.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the config file can't be read, the resulting
+    /// config is not valid, or the access list files can't be loaded.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn reload(&self, app_state: &AppState) -> Result<ReloadReport, Error> {
+        let config = match &self.path {
+            Some(path) => with_config_file(&self.base, path)?,
+            None => self.base.clone(),
+        };
+
+        config.validate()?;
+
+        let new = Settings::from(&config);
+
+        // Only one reload is applied at a time.
+        let mut settings = self.settings.write().expect("settings lock poisoned");
+
+        // The lists are not in the config file, only the list files change.
+        let access_lists_changed = app_state
+            .access_list
+            .reload(&app_state.config.api.access_lists)
+            .map_err(Error::AccessLists)?;
+
+        let mut report = ReloadReport::default();
+
+        let changes = [
+            ("trackers", settings.trackers != new.trackers),
+            (
+                "resolve_timeout",
+                settings.resolve_timeout != new.resolve_timeout,
+            ),
+            (
+                "min_resolve_timeout",
+                settings.min_resolve_timeout != new.min_resolve_timeout,
+            ),
+            (
+                "max_resolve_timeout",
+                settings.max_resolve_timeout != new.max_resolve_timeout,
+            ),
+            ("cache_ttl", settings.cache_ttl != new.cache_ttl),
+            ("rate_limit", settings.rate_limit != new.rate_limit),
+            (
+                "bind_addresses",
+                settings.bind_addresses != new.bind_addresses,
+            ),
+            ("access_lists", access_lists_changed),
+        ];
+
+        report.applied = changed(&changes);
+
+        // They are compared with the running config, as they are not applied.
+        let restart_changes = [(
+            "listen_ports",
+            config.client.listen_ports != app_state.config.client.listen_ports,
+        )];
+
+        report.requires_restart = changed(&restart_changes);
+
+        app_state
+            .resolver
+            .reconfigure(&new.trackers, new.resolve_timeout);
+        app_state.cache.set_ttl(new.cache_ttl);
+        app_state.rate_limiter.set_config(new.rate_limit.as_ref());
+
+        if settings.bind_addresses != new.bind_addresses {
+            // The API drains the connections and rebinds in the background,
+            // after this reload is answered.
+            self.bind_addresses.send_replace(new.bind_addresses.clone());
+        }
+
+        *settings = new;
+
+        info!(
+            "config reloaded, applied: {:?}, requires restart: {:?}",
+            report.applied, report.requires_restart
+        );
+
+        Ok(report)
+    }
+}
+
+fn changed(changes: &[(&str, bool)]) -> Vec<String> {
+    changes
+        .iter()
+        .filter(|(_, changed)| *changed)
+        .map(|(name, _)| (*name).to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigFile, ListenPortsFile};
+
+    fn parse(json: &str) -> ConfigFile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn it_should_tell_the_missing_fields_from_the_null_ones() {
+        let file = parse(r#"{"resolve_timeout_ms": 5000, "cache_ttl_secs": null}"#);
+
+        assert_eq!(file.resolve_timeout_ms, Some(5000));
+        assert_eq!(file.cache_ttl_secs, Some(None));
+        assert!(file.rate_limit.is_none());
+        assert!(file.trackers.is_none());
+    }
+
+    #[test]
+    fn it_should_parse_the_listen_ports_as_a_list_or_a_range() {
+        assert!(matches!(
+            parse(r#"{"listen_ports": [6881, 6882]}"#).listen_ports,
+            Some(ListenPortsFile::List(ports)) if ports == vec![6881, 6882]
+        ));
+        assert!(matches!(
+            parse(r#"{"listen_ports": {"start": 6881, "end": 6890}}"#).listen_ports,
+            Some(ListenPortsFile::Range(range)) if range == (6881..6890)
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_fields() {
+        assert!(serde_json::from_str::<ConfigFile>(r#"{"tracker": []}"#).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+//! `Server-Timing` header of the torrent file responses.
+//!
+//! Browsers show it in the dev tools, so integrators can see where the time
+//! of a slow response goes: the cache lookup, the resolution of the magnet
+//! link (DHT and peers) and the whole request.
+//!
+//! For example: `cache_lookup;dur=0.05, dht_resolve;dur=2345.67, total;dur=2346.12`.
+use std::fmt::Write;
+use std::time::Duration;
+
+use hyper::header::{HeaderName, HeaderValue};
+
+pub const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Name of the cache lookup duration.
+pub const CACHE_LOOKUP: &str = "cache_lookup";
+
+/// Name of the magnet link resolution duration. It's only there for cache
+/// misses.
+pub const DHT_RESOLVE: &str = "dht_resolve";
+
+/// Name of the whole request duration.
+pub const TOTAL: &str = "total";
+
+/// The durations of the stages of a request, in the order they are recorded.
+#[derive(Debug, Default)]
+pub struct ServerTiming {
+    metrics: Vec<(&'static str, Duration)>,
+}
+
+impl ServerTiming {
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        self.metrics.push((name, duration));
+    }
+
+    /// Returns the header value, with the durations in milliseconds.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if a metric name is not a valid header value.
+    #[must_use]
+    pub fn header_value(&self) -> HeaderValue {
+        let mut value = String::new();
+
+        for (name, duration) in &self.metrics {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+
+            let _ = write!(value, "{name};dur={:.2}", duration.as_secs_f64() * 1000.0);
+        }
+
+        HeaderValue::from_str(&value).expect("metric names and durations are valid header chars")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ServerTiming, CACHE_LOOKUP, DHT_RESOLVE, TOTAL};
+
+    #[test]
+    fn it_should_list_the_durations_in_milliseconds_in_the_recorded_order() {
+        let mut timing = ServerTiming::default();
+
+        timing.record(CACHE_LOOKUP, Duration::from_micros(50));
+        timing.record(DHT_RESOLVE, Duration::from_millis(2345));
+        timing.record(TOTAL, Duration::from_micros(2_345_120));
+
+        assert_eq!(
+            timing.header_value(),
+            "cache_lookup;dur=0.05, dht_resolve;dur=2345.00, total;dur=2345.12"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Deduplication of concurrent work for the same key.
+//!
+//! When many requests for the same info-hash arrive at the same time, only the
+//! first one starts resolving the magnet link. The rest of them wait for the
+//! same future and get a clone of its output.
+//!
+//! The in-flight entry is removed as soon as the shared future completes (or
+//! when all the awaiters are dropped), so a failed resolution does not affect
+//! subsequent requests.
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+
+pub struct SingleFlight<K, T> {
+    in_flight: Mutex<InFlight<K, T>>,
+}
+
+struct InFlight<K, T> {
+    next_id: u64,
+    calls: HashMap<K, Call<T>>,
+}
+
+struct Call<T> {
+    id: u64,
+    future: Shared<BoxFuture<'static, T>>,
+}
+
+impl<K, T> Default for SingleFlight<K, T> {
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(InFlight {
+                next_id: 0,
+                calls: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<K, T> SingleFlight<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Clone,
+{
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the future returned by `make_future` unless there is already one
+    /// in flight for the same key. In that case it waits for the in-flight
+    /// one and returns a clone of its output.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub async fn run<F, Fut>(&self, key: K, make_future: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T> + Send + 'static,
+    {
+        let (id, future) = {
+            let mut in_flight = self.in_flight.lock().expect("single flight lock poisoned");
+
+            if let Some(call) = in_flight.calls.get(&key) {
+                (call.id, call.future.clone())
+            } else {
+                let id = in_flight.next_id;
+                in_flight.next_id = in_flight.next_id.wrapping_add(1);
+
+                let future = make_future().boxed().shared();
+
+                in_flight.calls.insert(
+                    key.clone(),
+                    Call {
+                        id,
+                        future: future.clone(),
+                    },
+                );
+
+                (id, future)
+            }
+        };
+
+        // The guard removes the entry even if this future is dropped before
+        // completion (for example, when the HTTP client disconnects).
+        let _guard = CleanupGuard {
+            single_flight: self,
+            key,
+            id,
+        };
+
+        future.await
+    }
+
+    /// Returns the number of keys with in-flight work.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+            .lock()
+            .expect("single flight lock poisoned")
+            .calls
+            .len()
+    }
+}
+
+struct CleanupGuard<'a, K, T>
+where
+    K: Eq + Hash,
+{
+    single_flight: &'a SingleFlight<K, T>,
+    key: K,
+    id: u64,
+}
+
+impl<K, T> Drop for CleanupGuard<'_, K, T>
+where
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        let Ok(mut in_flight) = self.single_flight.in_flight.lock() else {
+            return;
+        };
+
+        let Some(call) = in_flight.calls.get(&self.key) else {
+            return;
+        };
+
+        // Another call for the same key could have been started after ours.
+        if call.id != self.id {
+            return;
+        }
+
+        // `None` means the future has completed. `Some(1)` means the map holds
+        // the only remaining reference, so nobody is waiting for it anymore.
+        if matches!(call.future.strong_count(), None | Some(1)) {
+            in_flight.calls.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::SingleFlight;
+
+    #[tokio::test]
+    async fn it_should_run_the_future_only_once_for_concurrent_calls_with_the_same_key() {
+        let single_flight = Arc::new(SingleFlight::<u32, u32>::new());
+        let executions = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = vec![];
+
+        for _ in 0..10 {
+            let single_flight = single_flight.clone();
+            let executions = executions.clone();
+
+            handles.push(tokio::spawn(async move {
+                single_flight
+                    .run(1, || async move {
+                        executions.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        42
+                    })
+                    .await
+            }));
+        }
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), 42);
+        }
+
+        assert_eq!(executions.load(Ordering::SeqCst), 1);
+        assert_eq!(single_flight.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_should_not_reuse_a_failed_result_for_subsequent_calls() {
+        let single_flight = SingleFlight::<u32, Result<u32, ()>>::new();
+
+        let first = single_flight.run(1, || async { Err(()) }).await;
+        let second = single_flight.run(1, || async { Ok(42) }).await;
+
+        assert_eq!(first, Err(()));
+        assert_eq!(second, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn it_should_clean_up_the_in_flight_entry_when_all_the_callers_are_dropped() {
+        let single_flight = SingleFlight::<u32, u32>::new();
+
+        let call = single_flight.run(1, || async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            42
+        });
+
+        let result = tokio::time::timeout(Duration::from_millis(10), call).await;
+
+        assert!(result.is_err());
+        assert_eq!(single_flight.in_flight(), 0);
+    }
+}
@@ -3,6 +3,7 @@ use futures_util::{ready, Future};
 use http_body::{Body, Frame};
 use hyper::Response;
 use pin_project_lite::pin_project;
+use rand::Rng;
 use std::time::Duration;
 use std::{
     future::Ready,
@@ -17,10 +18,35 @@ use tokio::{
 };
 use tower::Service;
 
-const TIMEOUT: Duration = Duration::from_secs(10);
+/// The default timeout. See [`HttpTimeouts`](crate::config::HttpTimeouts).
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Closes the connections that don't send a request within the timeout.
+///
+/// A random jitter (up to the configured one) is added to the timeout of
+/// each connection, so slow connections opened at the same time are not
+/// closed at the same instant.
 #[derive(Clone)]
-pub struct TimeoutAcceptor;
+pub struct TimeoutAcceptor {
+    timeout: Duration,
+    jitter: Duration,
+}
+
+impl TimeoutAcceptor {
+    #[must_use]
+    pub fn new(timeout: Duration, jitter: Duration) -> Self {
+        Self { timeout, jitter }
+    }
+
+    /// Returns the timeout of a new connection.
+    fn timeout(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return self.timeout;
+        }
+
+        self.timeout + rand::thread_rng().gen_range(Duration::ZERO..=self.jitter)
+    }
+}
 
 impl<I, S> Accept<I, S> for TimeoutAcceptor {
     type Stream = TimeoutStream<I>;
@@ -30,7 +56,7 @@ impl<I, S> Accept<I, S> for TimeoutAcceptor {
     fn accept(&self, stream: I, service: S) -> Self::Future {
         let (tx, rx) = mpsc::unbounded_channel();
 
-        let stream = TimeoutStream::new(stream, TIMEOUT, rx);
+        let stream = TimeoutStream::new(stream, self.timeout(), rx);
         let service = TimeoutService::new(service, tx);
 
         std::future::ready(Ok((stream, service)))
@@ -244,3 +270,83 @@ impl<IO: AsyncWrite + Unpin> AsyncWrite for TimeoutStream<IO> {
         self.inner.is_write_vectored()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use axum::Router;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use super::TimeoutAcceptor;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Serves a router with the acceptor on a free local port.
+    fn serve(acceptor: TimeoutAcceptor) -> SocketAddr {
+        let socket = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let router = Router::new().route("/", get(|| async { "ok" }));
+
+        tokio::spawn(async move {
+            axum_server::from_tcp(socket)
+                .acceptor(acceptor)
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        address
+    }
+
+    /// Connects to the server, waits for the delay and sends a request. It
+    /// returns the response, which is empty if the connection was closed.
+    async fn request_after(address: SocketAddr, delay: Duration) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+
+        tokio::time::sleep(delay).await;
+
+        // The server may have closed the connection already.
+        let _ = stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await;
+
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response).await;
+
+        String::from_utf8_lossy(&response).to_string()
+    }
+
+    #[test]
+    fn it_should_add_a_bounded_random_jitter_to_the_timeout() {
+        assert_eq!(
+            TimeoutAcceptor::new(TIMEOUT, Duration::ZERO).timeout(),
+            TIMEOUT
+        );
+
+        let acceptor = TimeoutAcceptor::new(TIMEOUT, Duration::from_secs(2));
+
+        for _ in 0..100 {
+            let timeout = acceptor.timeout();
+
+            assert!(timeout >= TIMEOUT && timeout <= TIMEOUT + Duration::from_secs(2));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_drop_the_connections_slower_than_the_timeout() {
+        let address = serve(TimeoutAcceptor::new(
+            Duration::from_millis(200),
+            Duration::ZERO,
+        ));
+
+        assert!(request_after(address, Duration::ZERO)
+            .await
+            .starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(request_after(address, Duration::from_millis(500)).await, "");
+    }
+}
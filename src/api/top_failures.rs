@@ -0,0 +1,190 @@
+//! Most frequently failing info hashes.
+//!
+//! Info hashes can't be metric labels, as there are unbounded of them. The
+//! failures are counted here instead, for a bounded number of info hashes,
+//! and the top ones are returned by an admin endpoint. When the maximum is
+//! reached, the info hash that failed least recently is forgotten.
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::config::TopFailures as TopFailuresConfig;
+use crate::metrics::Outcome;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct TopFailure {
+    pub info_hash: InfoHash,
+    /// Number of failed resolutions since it's tracked.
+    pub failures: u64,
+    /// The outcome of the last failed resolution, like in the metrics. For
+    /// example, `not_found`.
+    pub last_outcome: String,
+}
+
+struct Failures {
+    count: u64,
+    last_outcome: Outcome,
+}
+
+pub struct TopFailures {
+    /// Number of info hashes returned.
+    size: usize,
+    /// `None` when the tracking is disabled.
+    failures: Option<Mutex<LruCache<InfoHash, Failures>>>,
+}
+
+impl TopFailures {
+    /// Nothing is tracked when there is no config.
+    #[must_use]
+    pub fn new(config: Option<&TopFailuresConfig>) -> Self {
+        Self {
+            size: config.map_or(0, |config| config.size),
+            failures: config.map(|config| {
+                let capacity = NonZeroUsize::new(config.max_tracked).unwrap_or(NonZeroUsize::MIN);
+                Mutex::new(LruCache::new(capacity))
+            }),
+        }
+    }
+
+    /// Counts a failed resolution of the torrent.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn add(&self, info_hash: &InfoHash, outcome: Outcome) {
+        let Some(failures) = &self.failures else {
+            return;
+        };
+
+        let mut failures = failures.lock().expect("top failures lock poisoned");
+
+        match failures.get_mut(info_hash) {
+            Some(entry) => {
+                entry.count += 1;
+                entry.last_outcome = outcome;
+            }
+            None => {
+                failures.put(
+                    *info_hash,
+                    Failures {
+                        count: 1,
+                        last_outcome: outcome,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the info hashes with the most failures, from most to least.
+    /// Ties are sorted by info hash.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn top(&self) -> Vec<TopFailure> {
+        let Some(failures) = &self.failures else {
+            return vec![];
+        };
+
+        let mut top: Vec<TopFailure> = failures
+            .lock()
+            .expect("top failures lock poisoned")
+            .iter()
+            .map(|(info_hash, failures)| TopFailure {
+                info_hash: *info_hash,
+                failures: failures.count,
+                last_outcome: failures.last_outcome.as_str().to_string(),
+            })
+            .collect();
+
+        top.sort_by(|a, b| {
+            b.failures
+                .cmp(&a.failures)
+                .then_with(|| a.info_hash.0.cmp(&b.info_hash.0))
+        });
+        top.truncate(self.size);
+
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::TopFailures;
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::config::TopFailures as TopFailuresConfig;
+    use crate::metrics::Outcome;
+
+    fn info_hash(hex: &str) -> InfoHash {
+        InfoHash::from_str(hex).unwrap()
+    }
+
+    #[test]
+    fn it_should_return_the_info_hashes_with_the_most_failures() {
+        let top_failures = TopFailures::new(Some(&TopFailuresConfig {
+            size: 2,
+            max_tracked: 10,
+        }));
+
+        let first = info_hash("443c7602b4fde83d1154d6d9da48808418b181b6");
+        let second = info_hash("0000000000000000000000000000000000000001");
+        let third = info_hash("0000000000000000000000000000000000000002");
+
+        top_failures.add(&first, Outcome::NotFound);
+        top_failures.add(&first, Outcome::InvalidMetadata);
+        top_failures.add(&first, Outcome::NotFound);
+        top_failures.add(&second, Outcome::NotFound);
+        top_failures.add(&second, Outcome::NotFound);
+        top_failures.add(&third, Outcome::NotFound);
+
+        let top = top_failures.top();
+
+        assert_eq!(top.len(), 2);
+        assert_eq!((top[0].info_hash, top[0].failures), (first, 3));
+        assert_eq!(top[0].last_outcome, "not_found");
+        assert_eq!((top[1].info_hash, top[1].failures), (second, 2));
+    }
+
+    #[test]
+    fn it_should_forget_the_least_recently_failed_info_hash_when_the_maximum_is_reached() {
+        let top_failures = TopFailures::new(Some(&TopFailuresConfig {
+            size: 10,
+            max_tracked: 1,
+        }));
+
+        top_failures.add(
+            &info_hash("443c7602b4fde83d1154d6d9da48808418b181b6"),
+            Outcome::NotFound,
+        );
+        top_failures.add(
+            &info_hash("0000000000000000000000000000000000000001"),
+            Outcome::NotFound,
+        );
+
+        let top = top_failures.top();
+
+        assert_eq!(top.len(), 1);
+        assert_eq!(
+            top[0].info_hash,
+            info_hash("0000000000000000000000000000000000000001")
+        );
+    }
+
+    #[test]
+    fn it_should_not_track_anything_when_it_is_disabled() {
+        let top_failures = TopFailures::new(None);
+
+        top_failures.add(
+            &info_hash("443c7602b4fde83d1154d6d9da48808418b181b6"),
+            Outcome::NotFound,
+        );
+
+        assert!(top_failures.top().is_empty());
+    }
+}
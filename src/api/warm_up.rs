@@ -0,0 +1,90 @@
+//! Cache warm-up.
+//!
+//! A known set of popular torrents is resolved on startup, in the
+//! background, so their first requests are served from the cache. They are
+//! resolved like the requests, sharing the resolutions in progress and
+//! respecting the access lists.
+use std::num::NonZeroUsize;
+use std::time::Instant;
+
+use futures_util::stream::{self, StreamExt};
+use tracing::{debug, info};
+
+use crate::api::handler::get_torrent;
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::config::WarmUp;
+use crate::AppState;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct WarmUpReport {
+    /// Torrents resolved or already cached.
+    pub warmed: usize,
+    /// Torrents that could not be resolved or are not allowed.
+    pub failed: usize,
+    /// Torrents not resolved before the time budget was exhausted.
+    pub skipped: usize,
+}
+
+enum Outcome {
+    Warmed,
+    Failed,
+    Skipped,
+}
+
+/// Resolves the torrents, respecting the maximum concurrency and time
+/// budget, and logs how many were warmed.
+pub async fn warm_up(
+    app_state: &AppState,
+    info_hashes: Vec<InfoHash>,
+    config: &WarmUp,
+) -> WarmUpReport {
+    info!(
+        "cache warm-up: resolving {} torrents ...",
+        info_hashes.len()
+    );
+
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + config.budget;
+
+    let concurrency = NonZeroUsize::new(config.max_concurrency)
+        .unwrap_or(NonZeroUsize::MIN)
+        .get();
+
+    let report = stream::iter(info_hashes.into_iter().map(|info_hash| async move {
+        if let Err(err) = app_state.access_list.check(&info_hash) {
+            debug!("cache warm-up: torrent {info_hash} skipped: {err}");
+            return Outcome::Failed;
+        }
+
+        match tokio::time::timeout_at(deadline, get_torrent(app_state, &info_hash, None, false))
+            .await
+        {
+            Ok(Ok(_)) => Outcome::Warmed,
+            Ok(Err(err)) => {
+                debug!("cache warm-up: torrent {info_hash} not resolved: {err}");
+                Outcome::Failed
+            }
+            Err(_elapsed) => Outcome::Skipped,
+        }
+    }))
+    .buffer_unordered(concurrency)
+    .fold(WarmUpReport::default(), |mut report, outcome| async move {
+        match outcome {
+            Outcome::Warmed => report.warmed += 1,
+            Outcome::Failed => report.failed += 1,
+            Outcome::Skipped => report.skipped += 1,
+        }
+        report
+    })
+    .await;
+
+    info!(
+        "cache warm-up: {} torrents warmed, {} failed and {} skipped in {} ms",
+        report.warmed,
+        report.failed,
+        report.skipped,
+        start.elapsed().as_millis()
+    );
+
+    report
+}
@@ -1,62 +1,293 @@
 use anyhow::Context;
 use camino::Utf8PathBuf;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use std::{fs, io};
 use thiserror::Error;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
 
 use bytes::Bytes;
+use futures_util::StreamExt;
+use librqbit::dht::Id20;
 use librqbit::{
-    AddTorrent, AddTorrentOptions, AddTorrentResponse, ByteBufOwned, ListOnlyResponse, Session,
-    TorrentMetaV1Info,
+    torrent_from_bytes, AddTorrent, AddTorrentOptions, AddTorrentResponse, ByteBuf, ByteBufOwned,
+    ListOnlyResponse, ManagedTorrent, PeerConnectionOptions, Session, TorrentMetaV1Info,
 };
 
-use crate::config::Client as ClientConfig;
+use super::info_hash::InfoHash;
+use super::magnet::MagnetLink;
+use super::tracker_list::RemoteTrackers;
+use super::verify_storage::VerifyStorageFactory;
+use super::web_cache::WebCaches;
+use crate::config::{Client as ClientConfig, ListenPorts, PeerConnection, SessionCleanup};
+use librqbit::storage::StorageFactoryExt;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, Clone)]
 pub enum ResolveMagnetError {
     #[error("BitTorrent client session not started")]
     NoSession,
-    #[error("Torrent was added to the BitTorrent client for downloading instead of only listing")]
+    #[error(
+        "Torrent was added to the BitTorrent client for downloading instead of only listing, \
+         it was removed"
+    )]
     AddedForDownloading,
     #[error("Torrent could not been added to the BitTorrent client")]
     NotAdded,
+    #[error("Too many magnet links are being resolved at the same time")]
+    Busy,
+    #[error("Torrent metadata not found before the resolve timeout")]
+    NotFound,
+    #[error("Torrent metadata is too large: {size} bytes, the maximum is {max}")]
+    MetadataTooLarge { size: usize, max: usize },
+    #[error(
+        "Only {found} peers have the torrent before the resolve timeout, the minimum is {min}"
+    )]
+    NotEnoughPeers { found: usize, min: usize },
+    #[error("The torrent metadata does not match the info hash, it's the metadata of {computed}")]
+    InfoHashMismatch { computed: InfoHash },
+    #[error("The torrent metadata could not be parsed")]
+    InvalidMetadata,
+    #[error("No peer served a valid piece of the torrent content before the resolve timeout")]
+    ContentNotVerified,
+    #[error("The torrent content could not be verified because of a BitTorrent client error")]
+    VerificationFailed,
 }
 
+/// A torrent resolved from its magnet link.
+#[derive(Clone)]
+pub struct ResolvedTorrent {
+    pub info: TorrentMetaV1Info<ByteBufOwned>,
+    /// The metainfo (torrent binary data).
+    pub torrent_bytes: Bytes,
+    /// Number of peers the client found while resolving it, or `None` if the
+    /// torrent was already managed by the client.
+    pub seen_peers: Option<usize>,
+}
+
+/// The resolved torrent or the reason why the magnet link could not be
+/// resolved.
+pub type ResolveMagnetResult = Result<ResolvedTorrent, ResolveMagnetError>;
+
 pub struct Client {
     pub opt_session: Option<Arc<Session>>,
     pub output_dir: Utf8PathBuf,
-    pub listen_port_range: Option<std::ops::Range<u16>>,
+    pub listen_ports: Option<ListenPorts>,
+    /// Bounds the number of concurrent resolutions, as each one of them does
+    /// DHT lookups and opens connections to peers.
+    resolutions: Semaphore,
+    resolution_queue_timeout: Option<Duration>,
+    /// It can be changed at runtime with [`Client::reconfigure`].
+    resolve_timeout: RwLock<Duration>,
+    resolve_retries: u32,
+    resolve_retry_base_delay: Duration,
+    max_metadata_size: usize,
+    min_peers: Option<usize>,
+    session_cleanup: Option<SessionCleanup>,
+    disable_dht: bool,
+    /// It can be changed at runtime with [`Client::reconfigure`].
+    trackers: RwLock<Vec<String>>,
+    /// `None` when there is no remote list of trackers.
+    remote_trackers: Option<Arc<RemoteTrackers>>,
+    peers: Vec<SocketAddr>,
+    /// `None` when the web caches fallback is disabled.
+    web_caches: Option<WebCaches>,
+    peer_connection: PeerConnection,
+    verify_content: bool,
 }
 
 impl Client {
     #[must_use]
     pub fn new(config: ClientConfig) -> Self {
+        // At least one resolution is always allowed.
+        let max_concurrent_resolutions =
+            NonZeroUsize::new(config.max_concurrent_resolutions).unwrap_or(NonZeroUsize::MIN);
+
         Self {
             opt_session: None,
             output_dir: config.session_output_dir,
-            listen_port_range: config.listen_port_range,
+            listen_ports: config.listen_ports,
+            resolutions: Semaphore::new(max_concurrent_resolutions.get()),
+            resolution_queue_timeout: config.resolution_queue_timeout,
+            resolve_timeout: RwLock::new(config.resolve_timeout),
+            resolve_retries: config.resolve_retries,
+            resolve_retry_base_delay: config.resolve_retry_base_delay,
+            max_metadata_size: config.max_metadata_size,
+            min_peers: config.min_peers,
+            session_cleanup: config.session_cleanup,
+            disable_dht: config.disable_dht,
+            trackers: RwLock::new(config.trackers),
+            remote_trackers: config
+                .remote_trackers
+                .as_ref()
+                .map(|remote_trackers| Arc::new(RemoteTrackers::new(remote_trackers))),
+            peers: config.peers,
+            web_caches: config.web_caches.as_ref().map(WebCaches::new),
+            peer_connection: config.peer_connection,
+            verify_content: config.verify_content,
         }
     }
 
+    /// It removes the stale files in the output dir first, if the cleanup is
+    /// configured. There are no resolutions in progress yet.
+    ///
     /// # Errors
     ///
-    /// Will return an error if the session can't be created.
+    /// Will return an error if the session can't be created or none of the
+    /// listen ports is free.
     pub async fn start_session(&mut self) -> Result<(), anyhow::Error> {
+        if let Some(session_cleanup) = &self.session_cleanup {
+            self.remove_stale_files(session_cleanup.max_age);
+        }
+
+        // The client only takes a range, so the first free port in the list
+        // is passed as a range with only that port.
+        let listen_port_range = match &self.listen_ports {
+            Some(ListenPorts::Range(range)) => Some(range.clone()),
+            Some(ListenPorts::List(ports)) => {
+                let port = first_free_port(ports)
+                    .with_context(|| format!("no free TCP ports in the list {ports:?}"))?;
+                Some(port..port + 1)
+            }
+            None => None,
+        };
+
+        // The DHT bootstrap nodes can't be configured: the session always
+        // creates its DHT with the default ones and librqbit does not expose
+        // `DhtConfig::bootstrap_addrs` through the `SessionOptions`. Use the
+        // `trackers` and `peers` options when they are not reachable.
         let opts = librqbit::SessionOptions {
-            disable_dht: false, // DHT is needed to get the list of peers having the torrent.
-            listen_port_range: self.listen_port_range.clone(),
+            // The DHT is needed to get the list of peers having the torrent,
+            // unless all of them can be found using the configured trackers.
+            disable_dht: self.disable_dht,
+            listen_port_range,
+            peer_opts: peer_connection_options(&self.peer_connection),
             ..Default::default()
         };
 
-        self.opt_session = Some(
-            Session::new_with_opts(self.output_dir.clone().into(), opts)
-                .await
-                .context("error creating session")?,
-        );
+        let session = Session::new_with_opts(self.output_dir.clone().into(), opts)
+            .await
+            .context("error creating session")?;
+
+        if let Some(port) = session.tcp_listen_port() {
+            info!("listening on port {port} for incoming peer connections");
+        }
+
+        self.opt_session = Some(session);
 
         Ok(())
     }
 
-    /// Return the torrent info and metainfo (torrent binary data) from the magnet link.
+    /// Returns true if the session was started, so torrents can be resolved.
+    #[must_use]
+    pub fn is_session_started(&self) -> bool {
+        self.opt_session.is_some()
+    }
+
+    #[must_use]
+    pub fn is_dht_enabled(&self) -> bool {
+        !self.disable_dht
+    }
+
+    /// Returns the number of nodes in the DHT routing table, or `None` if the
+    /// session is not started or the DHT is disabled.
+    ///
+    /// The routing table is populated as nodes reply, starting with the
+    /// bootstrap nodes, so a non-zero value means the DHT is reachable.
+    #[must_use]
+    pub fn dht_nodes(&self) -> Option<usize> {
+        self.opt_session
+            .as_ref()
+            .and_then(|session| session.get_dht())
+            .map(|dht| dht.stats().routing_table_size)
+    }
+
+    /// Stops the session, if it was started, giving it a chance to flush its
+    /// state before exiting.
+    ///
+    /// The stale files in the output dir are removed after stopping it, if
+    /// it's configured, so no resolution is using them.
+    pub async fn stop_session(&self) {
+        if let Some(session) = &self.opt_session {
+            session.stop().await;
+        }
+
+        if let Some(session_cleanup) = &self.session_cleanup {
+            if session_cleanup.on_shutdown {
+                self.remove_stale_files(session_cleanup.max_age);
+            }
+        }
+    }
+
+    fn remove_stale_files(&self, max_age: Duration) {
+        match remove_files_older_than(self.output_dir.as_std_path(), max_age) {
+            Ok(removed) => info!(
+                "removed {removed} stale files from the session output dir {}",
+                self.output_dir
+            ),
+            Err(err) => warn!(
+                "error removing stale files from the session output dir {}: {err}",
+                self.output_dir
+            ),
+        }
+    }
+
+    /// Returns the remote list of trackers, so it can be refreshed in the
+    /// background, or `None` if it's not configured.
+    #[must_use]
+    pub fn remote_trackers(&self) -> Option<Arc<RemoteTrackers>> {
+        self.remote_trackers.clone()
+    }
+
+    /// Replaces the trackers and the resolve timeout, for example when the
+    /// config is reloaded. The resolutions in progress are not affected.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal locks are poisoned.
+    pub fn reconfigure(&self, trackers: &[String], resolve_timeout: Duration) {
+        trackers.clone_into(&mut self.trackers.write().expect("trackers lock poisoned"));
+        *self
+            .resolve_timeout
+            .write()
+            .expect("resolve timeout lock poisoned") = resolve_timeout;
+    }
+
+    /// Returns the magnet link for the info-hash with the configured trackers,
+    /// followed by the ones in the remote list that are not configured, and
+    /// peers.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn magnet_link(&self, info_hash: InfoHash) -> MagnetLink {
+        let trackers = self.trackers.read().expect("trackers lock poisoned");
+
+        let remote_trackers: Vec<String> = self
+            .remote_trackers
+            .iter()
+            .flat_map(|remote_trackers| remote_trackers.trackers())
+            .filter(|tracker| !trackers.contains(tracker))
+            .collect();
+
+        MagnetLink::new(info_hash)
+            .with_trackers(&trackers)
+            .with_trackers(&remote_trackers)
+            .with_peers(&self.peers)
+    }
+
+    /// Return the torrent info and metainfo (torrent binary data) for the
+    /// info-hash, resolving its magnet link.
+    ///
+    /// If the torrent can't be added to the `BitTorrent` client, it's retried
+    /// up to the configured number of retries, doubling the delay between
+    /// them, as long as the next attempt starts before the resolve timeout.
     ///
     /// # Errors
     ///
@@ -64,44 +295,901 @@ impl Client {
     ///
     /// - Can't be added in list-only mode to the `BitTorrent` client.
     /// - Was added for downloading. It shouldn't, it should be added in list-only mode.
-    pub async fn resolve_magnet(
+    /// - Could not start resolving because the concurrency limit was reached.
+    /// - Metadata was not found before the resolve timeout.
+    /// - Metadata is larger than the configured maximum size.
+    /// - Is not advertised by the configured minimum number of DHT peers
+    ///   before the resolve timeout.
+    ///
+    /// If the web caches are configured, they are tried when the torrent can't
+    /// be added or its metadata is not found in time.
+    ///
+    /// It's cancellation-safe: torrents are added in list-only mode, so they
+    /// are never tracked by the session, and dropping the future (for
+    /// example, when all the HTTP clients waiting for it disconnect) stops
+    /// looking for peers and frees the resolution slot.
+    pub async fn resolve_magnet(&self, info_hash: InfoHash) -> ResolveMagnetResult {
+        let _permit = self.acquire_resolution_permit().await?;
+
+        let Some(session) = &self.opt_session else {
+            return Err(ResolveMagnetError::NoSession);
+        };
+
+        let result = self.resolve_from_network(session, info_hash).await;
+
+        match (&self.web_caches, &result) {
+            (
+                Some(web_caches),
+                Err(ResolveMagnetError::NotFound | ResolveMagnetError::NotAdded),
+            ) => {
+                debug!("torrent {info_hash} not found in the network, trying the web caches");
+
+                // The web caches verify the info hash.
+                match web_caches.fetch(info_hash, self.max_metadata_size).await {
+                    Some(resolved) => Ok(resolved),
+                    None => result,
+                }
+            }
+            _ => result,
+        }
+    }
+
+    /// Resolves the magnet link with the `BitTorrent` client, retrying the
+    /// torrents that can't be added.
+    async fn resolve_from_network(
         &self,
-        magnet_link: String,
-    ) -> Result<(TorrentMetaV1Info<ByteBufOwned>, Bytes), ResolveMagnetError> {
-        match &self.opt_session {
-            Some(session) => {
-                let added = match session
-                    .add_torrent(
-                        AddTorrent::from_url(&magnet_link),
-                        Some(AddTorrentOptions {
-                            list_only: true,
-                            ..Default::default()
-                        }),
-                    )
-                    .await
-                {
-                    Ok(add_torrent_response) => add_torrent_response,
-                    Err(_err) => return Err(ResolveMagnetError::NotAdded),
-                };
+        session: &Arc<Session>,
+        info_hash: InfoHash,
+    ) -> ResolveMagnetResult {
+        let magnet_link = self.magnet_link(info_hash);
+
+        let deadline = Instant::now()
+            + *self
+                .resolve_timeout
+                .read()
+                .expect("resolve timeout lock poisoned");
+
+        let mut retries = 0;
+
+        loop {
+            let result = match Self::add_torrent(session, &magnet_link, deadline)
+                .await
+                .and_then(|resolved| self.check_metadata_size(resolved))
+                .and_then(|resolved| check_info_hash(info_hash, resolved))
+            {
+                Ok(resolved) => match self.check_peers(session, info_hash, deadline).await {
+                    Ok(()) if self.verify_content => self
+                        .verify_content(session, &magnet_link, &resolved, deadline)
+                        .await
+                        .map(|()| resolved),
+                    Ok(()) => Ok(resolved),
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(err),
+            };
+
+            // Other errors are not transient, retrying would give the same
+            // result.
+            if !matches!(result, Err(ResolveMagnetError::NotAdded))
+                || retries >= self.resolve_retries
+            {
+                return result;
+            }
+
+            let delay = self
+                .resolve_retry_base_delay
+                .saturating_mul(2u32.saturating_pow(retries));
+
+            if Instant::now() + delay >= deadline {
+                return result;
+            }
 
-                let (info, content) = match added {
-                    AddTorrentResponse::AlreadyManaged(_, handle) => (
-                        handle.shared().info.clone(),
-                        handle.shared().torrent_bytes.clone(),
-                    ),
-                    AddTorrentResponse::ListOnly(ListOnlyResponse {
-                        info,
-                        torrent_bytes,
-                        ..
-                    }) => (info, torrent_bytes),
-                    AddTorrentResponse::Added(_, _) => {
-                        return Err(ResolveMagnetError::AddedForDownloading)
-                    }
+            tokio::time::sleep(delay).await;
+
+            retries += 1;
+        }
+    }
+
+    /// Rejects torrents with a metadata larger than the maximum, so they are
+    /// never cached nor served.
+    fn check_metadata_size(&self, resolved: ResolvedTorrent) -> ResolveMagnetResult {
+        if resolved.torrent_bytes.len() > self.max_metadata_size {
+            return Err(ResolveMagnetError::MetadataTooLarge {
+                size: resolved.torrent_bytes.len(),
+                max: self.max_metadata_size,
+            });
+        }
+
+        Ok(resolved)
+    }
+
+    /// Waits until the configured minimum number of distinct DHT peers
+    /// advertise the info hash, or the deadline.
+    async fn check_peers(
+        &self,
+        session: &Arc<Session>,
+        info_hash: InfoHash,
+        deadline: Instant,
+    ) -> Result<(), ResolveMagnetError> {
+        let Some(min) = self.min_peers else {
+            return Ok(());
+        };
+
+        let Some(Ok(mut peers_stream)) = session
+            .get_dht()
+            .map(|dht| dht.get_peers(Id20::new(info_hash.0), None))
+        else {
+            return Err(ResolveMagnetError::NotEnoughPeers { found: 0, min });
+        };
+
+        let mut peers = HashSet::new();
+
+        while peers.len() < min {
+            match tokio::time::timeout_at(deadline, peers_stream.next()).await {
+                Ok(Some(peer)) => {
+                    peers.insert(peer);
+                }
+                Ok(None) | Err(_) => {
+                    return Err(ResolveMagnetError::NotEnoughPeers {
+                        found: peers.len(),
+                        min,
+                    })
+                }
+            }
+        }
+
+        debug!("{} peers have the torrent {}", peers.len(), info_hash);
+
+        Ok(())
+    }
+
+    /// Downloads the first file with content until one of its pieces is
+    /// verified against its hash in the metadata, so the peers can actually
+    /// serve the content, not only the metadata. The pieces are kept in
+    /// memory, up to [`MAX_VERIFY_BYTES`], and the torrent is removed as soon
+    /// as one of them is verified, even if the resolution is cancelled.
+    async fn verify_content(
+        &self,
+        session: &Arc<Session>,
+        magnet_link: &MagnetLink,
+        resolved: &ResolvedTorrent,
+        deadline: Instant,
+    ) -> Result<(), ResolveMagnetError> {
+        let Some(file) = first_file_with_content(&resolved.info) else {
+            // There is nothing to download.
+            return Ok(());
+        };
+
+        // The pieces are downloaded to memory, nothing is written there.
+        let output_dir = self
+            .output_dir
+            .join(VERIFY_DIR)
+            .join(magnet_link.info_hash.to_hex_string());
+
+        // The torrent bytes don't have the trackers, so the magnet link is
+        // added again.
+        let add_torrent = session.add_torrent(
+            AddTorrent::from_url(magnet_link.to_string()),
+            Some(AddTorrentOptions {
+                only_files: Some(vec![file]),
+                overwrite: true,
+                output_folder: Some(output_dir.to_string()),
+                initial_peers: Some(magnet_link.peers.clone()),
+                storage_factory: Some(VerifyStorageFactory::new(MAX_VERIFY_BYTES).boxed()),
+                ..Default::default()
+            }),
+        );
+
+        let handle = match tokio::time::timeout_at(deadline, add_torrent).await {
+            Ok(Ok(AddTorrentResponse::Added(id, handle))) => {
+                let _verifying_torrent = VerifyingTorrent {
+                    session: session.clone(),
+                    id,
                 };
 
-                Ok((info, content))
+                return wait_for_verified_piece(&handle, deadline).await;
+            }
+            // It's being verified by another resolution, so it's not removed
+            // here.
+            Ok(Ok(AddTorrentResponse::AlreadyManaged(_, handle))) => handle,
+            // The metadata was already resolved, so it's not about the
+            // torrent, it must not be remembered as not found.
+            Ok(Ok(AddTorrentResponse::ListOnly(_))) => {
+                return Err(ResolveMagnetError::VerificationFailed)
+            }
+            Ok(Err(err)) => {
+                warn!(
+                    "error adding torrent {} to verify its content: {err:#}",
+                    magnet_link.info_hash
+                );
+                return Err(ResolveMagnetError::VerificationFailed);
+            }
+            Err(_elapsed) => return Err(ResolveMagnetError::ContentNotVerified),
+        };
+
+        wait_for_verified_piece(&handle, deadline).await
+    }
+
+    /// Adds the torrent in list-only mode, waiting for its metadata until the
+    /// deadline.
+    async fn add_torrent(
+        session: &Arc<Session>,
+        magnet_link: &MagnetLink,
+        deadline: Instant,
+    ) -> ResolveMagnetResult {
+        let add_torrent = session.add_torrent(
+            AddTorrent::from_url(magnet_link.to_string()),
+            Some(AddTorrentOptions {
+                list_only: true,
+                // The client ignores the `x.pe` magnet link params.
+                initial_peers: Some(magnet_link.peers.clone()),
+                ..Default::default()
+            }),
+        );
+
+        // The client keeps looking for peers with the metadata until it finds
+        // them, so not finding them in time means there are no (reachable)
+        // peers for the torrent.
+        let added = match tokio::time::timeout_at(deadline, add_torrent).await {
+            Ok(Ok(add_torrent_response)) => add_torrent_response,
+            Ok(Err(_err)) => return Err(ResolveMagnetError::NotAdded),
+            Err(_elapsed) => return Err(ResolveMagnetError::NotFound),
+        };
+
+        match added {
+            AddTorrentResponse::AlreadyManaged(_, handle) => Ok(ResolvedTorrent {
+                info: handle.shared().info.clone(),
+                torrent_bytes: handle.shared().torrent_bytes.clone(),
+                seen_peers: None,
+            }),
+            AddTorrentResponse::ListOnly(ListOnlyResponse {
+                info,
+                torrent_bytes,
+                seen_peers,
+                ..
+            }) => Ok(ResolvedTorrent {
+                info,
+                torrent_bytes,
+                seen_peers: Some(seen_peers.len()),
+            }),
+            AddTorrentResponse::Added(id, _) => {
+                // The removal runs in its own task, so it's completed even if
+                // the resolution is cancelled meanwhile.
+                let session = session.clone();
+                let _ = tokio::spawn(async move { Self::forget_torrent(&session, id).await }).await;
+                Err(ResolveMagnetError::AddedForDownloading)
+            }
+        }
+    }
+
+    /// Removes a torrent that was added for downloading instead of only
+    /// listing, so it doesn't keep downloading. The files it could have
+    /// already written to the output dir are also removed.
+    async fn forget_torrent(session: &Arc<Session>, id: usize) {
+        match session.delete(id.into(), true).await {
+            Ok(()) => warn!("removed torrent {id} added for downloading by mistake"),
+            Err(err) => warn!("error removing torrent {id} added for downloading: {err}"),
+        }
+    }
+
+    /// Waits for a free resolution slot, if the queue timeout is configured,
+    /// or fails immediately when all of them are taken.
+    async fn acquire_resolution_permit(&self) -> Result<SemaphorePermit<'_>, ResolveMagnetError> {
+        match self.resolution_queue_timeout {
+            Some(queue_timeout) => tokio::time::timeout(queue_timeout, self.resolutions.acquire())
+                .await
+                .map_err(|_| ResolveMagnetError::Busy)?
+                .map_err(|_| ResolveMagnetError::Busy),
+            None => self
+                .resolutions
+                .try_acquire()
+                .map_err(|_| ResolveMagnetError::Busy),
+        }
+    }
+}
+
+/// Subdir of the session output dir of the torrents added to verify their
+/// content.
+const VERIFY_DIR: &str = "verify";
+
+/// Maximum number of bytes downloaded to verify the content of a torrent. It
+/// bounds the memory used when peers send many pieces before one of them is
+/// verified. At least one piece is always downloaded.
+const MAX_VERIFY_BYTES: u64 = 32 * 1024 * 1024;
+
+/// How often the download progress is checked while verifying the content.
+const VERIFY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A torrent added to verify its content. It's removed, with its pieces in
+/// memory, when it's dropped.
+struct VerifyingTorrent {
+    session: Arc<Session>,
+    id: usize,
+}
+
+impl Drop for VerifyingTorrent {
+    fn drop(&mut self) {
+        let session = self.session.clone();
+        let id = self.id;
+
+        // The removal runs in its own task, so it's completed even if the
+        // resolution is cancelled.
+        tokio::spawn(async move {
+            if let Err(err) = session.delete(id.into(), true).await {
+                warn!("error removing torrent {id} added to verify its content: {err}");
+            }
+        });
+    }
+}
+
+/// Waits until at least one piece is downloaded. The client only counts the
+/// pieces matching their hash in the metadata.
+///
+/// It fails as soon as the torrent is stopped with an error, for example
+/// when [`MAX_VERIFY_BYTES`] are downloaded without a valid piece.
+async fn wait_for_verified_piece(
+    handle: &ManagedTorrent,
+    deadline: Instant,
+) -> Result<(), ResolveMagnetError> {
+    let verified = async {
+        loop {
+            let stats = handle.stats();
+
+            if stats.progress_bytes > 0 {
+                return Ok(());
+            }
+
+            if let Some(err) = stats.error {
+                debug!("the torrent added to verify its content failed: {err}");
+                return Err(ResolveMagnetError::ContentNotVerified);
+            }
+
+            tokio::time::sleep(VERIFY_POLL_INTERVAL).await;
+        }
+    };
+
+    tokio::time::timeout_at(deadline, verified)
+        .await
+        .map_err(|_| ResolveMagnetError::ContentNotVerified)?
+}
+
+/// Returns the index of the first file that is not empty, or `None` if all
+/// of them are. Single-file torrents only have the file `0`.
+fn first_file_with_content<B>(info: &TorrentMetaV1Info<B>) -> Option<usize> {
+    match &info.files {
+        Some(files) => files.iter().position(|file| file.length > 0),
+        None => info.length.is_some_and(|length| length > 0).then_some(0),
+    }
+}
+
+/// Rejects the metadata if the SHA-1 of its `info` dictionary is not the
+/// requested info hash, so metadata from a lying peer is never cached nor
+/// served. Only v1 info hashes are supported.
+fn check_info_hash(info_hash: InfoHash, resolved: ResolvedTorrent) -> ResolveMagnetResult {
+    let torrent = torrent_from_bytes::<ByteBuf>(&resolved.torrent_bytes)
+        .map_err(|_| ResolveMagnetError::InvalidMetadata)?;
+
+    let computed = InfoHash(torrent.info_hash.0);
+
+    if computed != info_hash {
+        warn!("the torrent metadata for {info_hash} has the info hash {computed}");
+        return Err(ResolveMagnetError::InfoHashMismatch { computed });
+    }
+
+    Ok(resolved)
+}
+
+/// Removes the files in the dir and its subdirs not modified for longer than
+/// `max_age`, and the subdirs left empty. It returns the number of removed
+/// files.
+fn remove_files_older_than(dir: &Path, max_age: Duration) -> io::Result<usize> {
+    let mut removed = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+
+        if metadata.is_dir() {
+            removed += remove_files_older_than(&path, max_age)?;
+
+            if fs::read_dir(&path)?.next().is_none() {
+                fs::remove_dir(&path)?;
             }
-            None => Err(ResolveMagnetError::NoSession),
+        } else if SystemTime::now()
+            .duration_since(metadata.modified()?)
+            .is_ok_and(|age| age > max_age)
+        {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Returns `None` when no timeout is configured, so the client uses its own
+/// defaults.
+fn peer_connection_options(peer_connection: &PeerConnection) -> Option<PeerConnectionOptions> {
+    if peer_connection.connect_timeout.is_none() && peer_connection.read_write_timeout.is_none() {
+        return None;
+    }
+
+    Some(PeerConnectionOptions {
+        connect_timeout: peer_connection.connect_timeout,
+        read_write_timeout: peer_connection.read_write_timeout,
+        ..Default::default()
+    })
+}
+
+/// Returns the first port in the list that can be bound.
+///
+/// `u16::MAX` is skipped, as it can't be included in the range passed to the
+/// client.
+fn first_free_port(ports: &[u16]) -> Option<u16> {
+    ports
+        .iter()
+        .copied()
+        .filter(|port| *port != u16::MAX)
+        .find(
+            |port| match TcpListener::bind((Ipv4Addr::UNSPECIFIED, *port)) {
+                Ok(_listener) => true,
+                Err(err) => {
+                    debug!("error listening on port {port}: {err}");
+                    false
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use std::fs;
+    use std::str::FromStr;
+
+    use bytes::Bytes;
+    use camino::Utf8PathBuf;
+    use librqbit::dht::Id20;
+    use librqbit::{
+        torrent_from_bytes, AddTorrent, AddTorrentOptions, AddTorrentResponse, ByteBufOwned,
+        TorrentMetaV1Info,
+    };
+    use tempfile::TempDir;
+    use tokio::time::Instant;
+
+    use super::{
+        check_info_hash, first_file_with_content, peer_connection_options, remove_files_older_than,
+        Client, ResolveMagnetError, ResolvedTorrent,
+    };
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::config::{Client as ClientConfig, ListenPorts, PeerConnection};
+
+    fn client_config() -> ClientConfig {
+        ClientConfig {
+            listen_ports: None,
+            session_output_dir: "session".into(),
+            max_concurrent_resolutions: 1,
+            resolution_queue_timeout: None,
+            resolve_timeout: Duration::from_secs(10),
+            resolve_retry_base_delay: Duration::from_millis(100),
+            session_cleanup: None,
+            free_disk_space: None,
+            ..ClientConfig::default()
         }
     }
+
+    fn client(
+        max_concurrent_resolutions: usize,
+        resolution_queue_timeout: Option<Duration>,
+    ) -> Client {
+        Client::new(ClientConfig {
+            max_concurrent_resolutions,
+            resolution_queue_timeout,
+            ..client_config()
+        })
+    }
+
+    #[tokio::test]
+    async fn it_should_never_exceed_the_max_number_of_concurrent_resolutions() {
+        let client = Arc::new(client(3, Some(Duration::from_secs(30))));
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = vec![];
+
+        for _ in 0..20 {
+            let client = client.clone();
+            let running = running.clone();
+            let max_running = max_running.clone();
+
+            handles.push(tokio::spawn(async move {
+                let _permit = client.acquire_resolution_permit().await.unwrap();
+
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+
+                tokio::time::sleep(Duration::from_millis(10)).await;
+
+                running.fetch_sub(1, Ordering::SeqCst);
+            }));
+        }
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_resolution_immediately_when_the_limit_is_reached_and_queueing_is_disabled(
+    ) {
+        let client = client(1, None);
+
+        let _permit = client.acquire_resolution_permit().await.unwrap();
+
+        assert!(matches!(
+            client.acquire_resolution_permit().await,
+            Err(ResolveMagnetError::Busy)
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_a_resolution_when_it_can_not_start_before_the_queue_timeout() {
+        let client = client(1, Some(Duration::from_millis(10)));
+
+        let _permit = client.acquire_resolution_permit().await.unwrap();
+
+        assert!(matches!(
+            client.acquire_resolution_permit().await,
+            Err(ResolveMagnetError::Busy)
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_should_report_the_torrent_as_not_found_when_the_metadata_is_not_found_before_the_resolve_timeout(
+    ) {
+        let dir = TempDir::new().unwrap();
+
+        // The only source of peers is a tracker that is not running.
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            resolve_timeout: Duration::from_millis(100),
+            disable_dht: true,
+            trackers: vec!["http://127.0.0.1:1/announce".to_string()],
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        assert!(matches!(
+            client.resolve_magnet(info_hash).await,
+            Err(ResolveMagnetError::NotFound)
+        ));
+
+        client.stop_session().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_free_the_resources_when_the_resolution_is_cancelled() {
+        let dir = TempDir::new().unwrap();
+
+        // The only source of peers is a tracker that is not running, so the
+        // resolution only ends with the resolve timeout.
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            resolve_timeout: Duration::from_secs(60),
+            disable_dht: true,
+            trackers: vec!["http://127.0.0.1:1/announce".to_string()],
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        // The resolution future is dropped when the timeout elapses.
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), client.resolve_magnet(info_hash))
+                .await
+                .is_err()
+        );
+
+        let session = client.opt_session.clone().unwrap();
+
+        assert!(session.get(Id20::new(info_hash.0).into()).is_none());
+        assert_eq!(session.with_torrents(|torrents| torrents.count()), 0);
+        assert!(client.acquire_resolution_permit().await.is_ok());
+
+        client.stop_session().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_retry_the_torrents_that_can_not_be_added_with_exponential_backoff() {
+        let dir = TempDir::new().unwrap();
+
+        // Without the DHT and trackers the torrent can't be added.
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            resolve_retries: 2,
+            resolve_retry_base_delay: Duration::from_millis(50),
+            disable_dht: true,
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let started_at = Instant::now();
+
+        assert!(matches!(
+            client.resolve_magnet(info_hash).await,
+            Err(ResolveMagnetError::NotAdded)
+        ));
+
+        // 50ms before the first retry and 100ms before the second one.
+        assert!(started_at.elapsed() >= Duration::from_millis(150));
+
+        client.stop_session().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_not_retry_when_the_next_attempt_would_start_after_the_resolve_timeout() {
+        let dir = TempDir::new().unwrap();
+
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            resolve_timeout: Duration::from_millis(100),
+            resolve_retries: 2,
+            resolve_retry_base_delay: Duration::from_secs(10),
+            disable_dht: true,
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        let started_at = Instant::now();
+
+        assert!(matches!(
+            client.resolve_magnet(info_hash).await,
+            Err(ResolveMagnetError::NotAdded)
+        ));
+
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+
+        client.stop_session().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_listen_on_the_first_free_port_in_the_list() {
+        let dir = TempDir::new().unwrap();
+
+        let busy = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
+        let busy_port = busy.local_addr().unwrap().port();
+        let free_port = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut client = Client::new(ClientConfig {
+            listen_ports: Some(ListenPorts::List(vec![busy_port, free_port])),
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            disable_dht: true,
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        assert_eq!(
+            client.opt_session.as_ref().unwrap().tcp_listen_port(),
+            Some(free_port)
+        );
+
+        client.stop_session().await;
+    }
+
+    #[test]
+    fn it_should_only_override_the_peer_connection_timeouts_that_are_configured() {
+        assert!(peer_connection_options(&PeerConnection::default()).is_none());
+
+        let options = peer_connection_options(&PeerConnection {
+            connect_timeout: Some(Duration::from_secs(2)),
+            read_write_timeout: None,
+        })
+        .unwrap();
+
+        assert_eq!(options.connect_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(options.read_write_timeout, None);
+        assert_eq!(options.keep_alive_interval, None);
+    }
+
+    #[test]
+    fn it_should_only_remove_the_stale_files_and_the_dirs_left_empty() {
+        let dir = TempDir::new().unwrap();
+
+        let write = |path: &str, age: Duration| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::File::create(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - age)
+                .unwrap();
+        };
+
+        write("stale", Duration::from_secs(7200));
+        write("old/stale", Duration::from_secs(7200));
+        write("recent", Duration::ZERO);
+
+        let removed = remove_files_older_than(dir.path(), Duration::from_secs(3600)).unwrap();
+
+        assert_eq!(removed, 2);
+        assert!(!dir.path().join("stale").exists());
+        assert!(!dir.path().join("old").exists());
+        assert!(dir.path().join("recent").exists());
+    }
+
+    #[test]
+    fn it_should_reject_torrents_with_a_metadata_larger_than_the_maximum() {
+        let client = Client::new(ClientConfig {
+            max_metadata_size: 10,
+            ..client_config()
+        });
+
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBufOwned::from(b"sample.txt".as_slice())),
+            pieces: ByteBufOwned::from([0u8; 20].as_slice()),
+            piece_length: 16384,
+            length: Some(11),
+            md5sum: None,
+            files: None,
+        };
+
+        let resolved = |size: usize| ResolvedTorrent {
+            info: info.clone(),
+            torrent_bytes: Bytes::from(vec![0u8; size]),
+            seen_peers: Some(1),
+        };
+
+        assert!(client.check_metadata_size(resolved(10)).is_ok());
+
+        assert!(matches!(
+            client.check_metadata_size(resolved(11)),
+            Err(ResolveMagnetError::MetadataTooLarge { size: 11, max: 10 })
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_the_metadata_of_another_info_hash() {
+        let mut torrent_bytes =
+            b"d4:infod6:lengthi11e4:name10:sample.txt12:piece lengthi16384e6:pieces20:".to_vec();
+        torrent_bytes.extend_from_slice(&[0u8; 20]);
+        torrent_bytes.extend_from_slice(b"ee");
+        let torrent_bytes = Bytes::from(torrent_bytes);
+
+        let torrent = torrent_from_bytes::<ByteBufOwned>(&torrent_bytes).unwrap();
+        let info_hash = InfoHash(torrent.info_hash.0);
+
+        let resolved = ResolvedTorrent {
+            info: torrent.info,
+            torrent_bytes,
+            seen_peers: Some(1),
+        };
+
+        assert!(check_info_hash(info_hash, resolved.clone()).is_ok());
+
+        let other = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        assert!(matches!(
+            check_info_hash(other, resolved),
+            Err(ResolveMagnetError::InfoHashMismatch { computed }) if computed == info_hash
+        ));
+    }
+
+    #[test]
+    fn it_should_verify_the_first_file_with_content() {
+        let torrent_bytes = b"d4:infod5:filesld6:lengthi0e4:pathl5:emptyeed6:lengthi11e4:pathl\
+            10:sample.txteee4:name6:sample12:piece lengthi16384e6:pieces0:ee";
+
+        let torrent = torrent_from_bytes::<ByteBufOwned>(torrent_bytes).unwrap();
+
+        assert_eq!(first_file_with_content(&torrent.info), Some(1));
+    }
+
+    #[tokio::test]
+    async fn it_should_fail_and_remove_the_torrent_when_no_peer_serves_the_content() {
+        let dir = TempDir::new().unwrap();
+
+        // The only source of peers is a tracker that is not running.
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            disable_dht: true,
+            trackers: vec!["http://127.0.0.1:1/announce".to_string()],
+            verify_content: true,
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let session = client.opt_session.clone().unwrap();
+
+        let mut torrent_bytes =
+            b"d4:infod6:lengthi11e4:name10:sample.txt12:piece lengthi16384e6:pieces20:".to_vec();
+        torrent_bytes.extend_from_slice(&[0u8; 20]);
+        torrent_bytes.extend_from_slice(b"ee");
+        let torrent_bytes = Bytes::from(torrent_bytes);
+
+        let torrent = torrent_from_bytes::<ByteBufOwned>(&torrent_bytes).unwrap();
+        let magnet_link = client.magnet_link(InfoHash(torrent.info_hash.0));
+
+        let resolved = ResolvedTorrent {
+            info: torrent.info,
+            torrent_bytes,
+            seen_peers: Some(1),
+        };
+
+        assert!(matches!(
+            client
+                .verify_content(
+                    &session,
+                    &magnet_link,
+                    &resolved,
+                    Instant::now() + Duration::from_millis(200)
+                )
+                .await,
+            Err(ResolveMagnetError::ContentNotVerified)
+        ));
+
+        // The torrent is removed in another task.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(session.with_torrents(|torrents| torrents.count()), 0);
+
+        client.stop_session().await;
+    }
+
+    #[tokio::test]
+    async fn it_should_remove_the_torrents_added_for_downloading() {
+        let dir = TempDir::new().unwrap();
+
+        let mut client = Client::new(ClientConfig {
+            session_output_dir: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            disable_dht: true,
+            ..client_config()
+        });
+        client.start_session().await.unwrap();
+
+        let session = client.opt_session.clone().unwrap();
+
+        let mut torrent_bytes =
+            b"d4:infod6:lengthi11e4:name10:sample.txt12:piece lengthi16384e6:pieces20:".to_vec();
+        torrent_bytes.extend_from_slice(&[0u8; 20]);
+        torrent_bytes.extend_from_slice(b"ee");
+
+        let added = session
+            .add_torrent(
+                AddTorrent::from_bytes(torrent_bytes),
+                Some(AddTorrentOptions {
+                    paused: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+
+        let AddTorrentResponse::Added(id, _) = added else {
+            panic!("the torrent should be added for downloading");
+        };
+
+        Client::forget_torrent(&session, id).await;
+
+        assert!(session.get(id.into()).is_none());
+        assert!(!dir.path().join("sample.txt").exists());
+
+        client.stop_session().await;
+    }
 }
@@ -0,0 +1,193 @@
+//! The files of a torrent as a directory tree.
+//!
+//! Multi-file torrents list each file with its path components (see
+//! [`TorrentMetaV1Info`]). The tree rebuilds the directory hierarchy from
+//! them, so it's easy to render by file browsers. Directories include the
+//! total length of the files inside them.
+use librqbit::TorrentMetaV1Info;
+use serde::{Deserialize, Serialize};
+
+use super::info_hash::InfoHash;
+use super::metadata::torrent_name;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Node {
+    File {
+        name: String,
+        length: u64,
+    },
+    Directory {
+        name: String,
+        length: u64,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    /// Builds the file tree from the torrent `info` dictionary.
+    ///
+    /// The root is a directory named after the torrent with all its files,
+    /// keeping their order in the torrent. For single-file torrents the root
+    /// is the only file. Non UTF-8 names are lossy-decoded.
+    #[must_use]
+    pub fn from_info<B: AsRef<[u8]>>(info_hash: &InfoHash, info: &TorrentMetaV1Info<B>) -> Self {
+        let name = torrent_name(info_hash, info);
+
+        let Some(files) = &info.files else {
+            return Node::File {
+                name,
+                length: info.length.unwrap_or_default(),
+            };
+        };
+
+        let mut children = vec![];
+
+        for file in files {
+            let path: Vec<String> = file
+                .path
+                .iter()
+                .map(|component| String::from_utf8_lossy(component.as_ref()).to_string())
+                .collect();
+
+            insert(&mut children, &path, file.length);
+        }
+
+        Node::Directory {
+            name,
+            length: files.iter().map(|file| file.length).sum(),
+            children,
+        }
+    }
+}
+
+/// Inserts the file into the directory children, creating its parent
+/// directories if they don't exist yet.
+fn insert(children: &mut Vec<Node>, path: &[String], length: u64) {
+    let Some((name, rest)) = path.split_first() else {
+        return;
+    };
+
+    if rest.is_empty() {
+        children.push(Node::File {
+            name: name.clone(),
+            length,
+        });
+        return;
+    }
+
+    let index = children
+        .iter()
+        .position(
+            |child| matches!(child, Node::Directory { name: dir_name, .. } if dir_name == name),
+        )
+        .unwrap_or_else(|| {
+            children.push(Node::Directory {
+                name: name.clone(),
+                length: 0,
+                children: vec![],
+            });
+            children.len() - 1
+        });
+
+    if let Node::Directory {
+        length: dir_length,
+        children,
+        ..
+    } = &mut children[index]
+    {
+        *dir_length += length;
+        insert(children, rest, length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use librqbit::{ByteBuf, TorrentMetaV1File, TorrentMetaV1Info};
+
+    use super::Node;
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    fn info_hash() -> InfoHash {
+        InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap()
+    }
+
+    #[test]
+    fn it_should_return_a_single_node_for_single_file_torrents() {
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample.txt")),
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: Some(11),
+            md5sum: None,
+            files: None,
+        };
+
+        assert_eq!(
+            Node::from_info(&info_hash(), &info),
+            Node::File {
+                name: "sample.txt".to_string(),
+                length: 11
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_nest_the_files_in_their_directories_with_the_total_length() {
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample")),
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: None,
+            md5sum: None,
+            files: Some(vec![
+                TorrentMetaV1File {
+                    length: 11,
+                    path: vec![ByteBuf(b"sample.txt")],
+                },
+                TorrentMetaV1File {
+                    length: 5,
+                    path: vec![ByteBuf(b"docs"), ByteBuf(b"readme.txt")],
+                },
+                TorrentMetaV1File {
+                    length: 3,
+                    path: vec![ByteBuf(b"docs"), ByteBuf(b"en"), ByteBuf(b"license.txt")],
+                },
+            ]),
+        };
+
+        assert_eq!(
+            Node::from_info(&info_hash(), &info),
+            Node::Directory {
+                name: "sample".to_string(),
+                length: 19,
+                children: vec![
+                    Node::File {
+                        name: "sample.txt".to_string(),
+                        length: 11
+                    },
+                    Node::Directory {
+                        name: "docs".to_string(),
+                        length: 8,
+                        children: vec![
+                            Node::File {
+                                name: "readme.txt".to_string(),
+                                length: 5
+                            },
+                            Node::Directory {
+                                name: "en".to_string(),
+                                length: 3,
+                                children: vec![Node::File {
+                                    name: "license.txt".to_string(),
+                                    length: 3
+                                }],
+                            },
+                        ],
+                    },
+                ],
+            }
+        );
+    }
+}
@@ -11,7 +11,9 @@
 //! > **NOTICE**: It only supports Info Hash v1.
 //!
 //! Typically info-hashes are represented as hex strings, but internally they are
-//! a 20-byte array.
+//! a 20-byte array. Some magnet links use the 32-char base32 encoding instead
+//! (`xt=urn:btih:<base32>`). Both forms can be parsed, but the canonical
+//! representation is always the 40-char hex string.
 //!
 //! # Calculating the info-hash of a torrent file
 //!
@@ -205,6 +207,12 @@ pub struct InfoHash(pub [u8; 20]);
 
 const INFO_HASH_BYTES_LEN: usize = 20;
 
+/// Length of the info-hash encoded as an hexadecimal string.
+const INFO_HASH_HEX_LEN: usize = 40;
+
+/// Length of the info-hash encoded as a base32 string (without padding).
+const INFO_HASH_BASE32_LEN: usize = 32;
+
 impl InfoHash {
     /// Create a new `InfoHash` from a byte slice.
     ///
@@ -230,6 +238,33 @@ impl InfoHash {
     pub fn to_hex_string(&self) -> String {
         self.to_string()
     }
+
+    fn from_hex(s: &str) -> Result<Self, binascii::ConvertError> {
+        let mut info_hash = Self([0u8; INFO_HASH_BYTES_LEN]);
+        binascii::hex2bin(s.as_bytes(), &mut info_hash.0)?;
+        Ok(info_hash)
+    }
+
+    fn from_base32(s: &str) -> Result<Self, binascii::ConvertError> {
+        // `binascii` is lenient and maps `0` and `1` to `O` and `I`, and
+        // accepts padding chars. We only accept the strict RFC 4648 alphabet.
+        if !s
+            .bytes()
+            .all(|c| c.is_ascii_alphabetic() || (b'2'..=b'7').contains(&c))
+        {
+            return Err(binascii::ConvertError::InvalidInput);
+        }
+
+        // The decoder needs room for a whole trailing 5-byte block.
+        let mut buffer = [0u8; INFO_HASH_BYTES_LEN + 5];
+        let decoded = binascii::b32decode(s.as_bytes(), &mut buffer)?;
+
+        if decoded.len() != INFO_HASH_BYTES_LEN {
+            return Err(binascii::ConvertError::InvalidInputLength);
+        }
+
+        Ok(Self::from_bytes(decoded))
+    }
 }
 
 impl std::fmt::Display for InfoHash {
@@ -243,13 +278,18 @@ impl std::fmt::Display for InfoHash {
 impl std::str::FromStr for InfoHash {
     type Err = binascii::ConvertError;
 
+    /// Parses an info-hash from its hex (40 chars) or base32 (32 chars)
+    /// representation. Both are case-insensitive.
+    ///
+    /// The length is checked in bytes, so strings with multibyte characters
+    /// are rejected by the decoders. It never panics, which is checked by the
+    /// `info_hash` fuzz target (see `fuzz/`).
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut i = Self([0u8; 20]);
-        if s.len() != 40 {
-            return Err(binascii::ConvertError::InvalidInputLength);
+        match s.len() {
+            INFO_HASH_HEX_LEN => Self::from_hex(s),
+            INFO_HASH_BASE32_LEN => Self::from_base32(s),
+            _ => Err(binascii::ConvertError::InvalidInputLength),
         }
-        binascii::hex2bin(s.as_bytes(), &mut i.0)?;
-        Ok(i)
     }
 }
 
@@ -399,6 +439,49 @@ mod tests {
         assert!(info_hash.is_err());
     }
 
+    #[test]
+    fn an_info_hash_can_be_created_from_a_valid_32_char_base32_string() {
+        let info_hash = InfoHash::from_str("IQ6HMAVU7XUD2EKU23M5USEAQQMLDANW").unwrap();
+
+        assert_eq!(
+            info_hash,
+            InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap()
+        );
+    }
+
+    #[test]
+    fn an_info_hash_can_be_created_from_a_lowercased_base32_string() {
+        let info_hash = InfoHash::from_str("iq6hmavu7xud2eku23m5useaqqmldanw").unwrap();
+
+        assert_eq!(
+            info_hash,
+            InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap()
+        );
+    }
+
+    #[test]
+    fn an_info_hash_can_not_be_created_from_a_32_char_string_with_chars_outside_the_base32_alphabet(
+    ) {
+        assert!(InfoHash::from_str("IQ6HMAVU7XUD2EKU23M5USEAQQMLDAN0").is_err());
+        assert!(InfoHash::from_str("IQ6HMAVU7XUD2EKU23M5USEAQQMLDAN=").is_err());
+    }
+
+    #[test]
+    fn an_info_hash_can_not_be_created_from_strings_with_the_right_length_in_bytes_but_not_in_chars(
+    ) {
+        // 38 ASCII chars and one 2-byte char.
+        assert!(InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181é").is_err());
+        // 30 ASCII chars and one 2-byte char.
+        assert!(InfoHash::from_str("IQ6HMAVU7XUD2EKU23M5USEAQQMLDAé").is_err());
+    }
+
+    #[test]
+    fn an_info_hash_can_not_be_created_from_prefixed_or_padded_hex_strings() {
+        assert!(InfoHash::from_str("0x3c7602b4fde83d1154d6d9da48808418b181b6").is_err());
+        assert!(InfoHash::from_str(" 443c7602b4fde83d1154d6d9da48808418b181b").is_err());
+        assert!(InfoHash::from_str("+43c7602b4fde83d1154d6d9da48808418b181b6").is_err());
+    }
+
     #[test]
     fn an_info_hash_should_by_displayed_like_a_40_utf8_lowercased_char_hex_string() {
         let info_hash = InfoHash::from_str("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF").unwrap();
@@ -0,0 +1,143 @@
+//! Magnet links.
+//!
+//! See [BEP 9. Extension for Peers to Send Metadata Files](https://www.bittorrent.org/beps/bep_0009.html#magnet-uri-format)
+//! for the magnet URI format.
+//!
+//! Besides the info-hash (`xt`), a magnet link can include tracker URLs (`tr`)
+//! and peer addresses (`x.pe`) the client can use to find peers without
+//! depending only on the DHT.
+use std::fmt;
+use std::net::SocketAddr;
+
+use url::form_urlencoded;
+
+use super::info_hash::InfoHash;
+
+/// The tracker URL schemes supported by the `BitTorrent` client.
+const TRACKER_SCHEMES: [&str; 3] = ["http", "https", "udp"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagnetLink {
+    pub info_hash: InfoHash,
+    pub trackers: Vec<String>,
+    pub peers: Vec<SocketAddr>,
+}
+
+impl MagnetLink {
+    #[must_use]
+    pub fn new(info_hash: InfoHash) -> Self {
+        Self {
+            info_hash,
+            trackers: vec![],
+            peers: vec![],
+        }
+    }
+
+    #[must_use]
+    pub fn with_trackers(mut self, trackers: &[String]) -> Self {
+        self.trackers.extend_from_slice(trackers);
+        self
+    }
+
+    #[must_use]
+    pub fn with_peers(mut self, peers: &[SocketAddr]) -> Self {
+        self.peers.extend_from_slice(peers);
+        self
+    }
+}
+
+impl fmt::Display for MagnetLink {
+    /// The `tr` and `x.pe` values are percent-encoded, so they don't clash
+    /// with the query string separators.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "magnet:?xt=urn:btih:{}", self.info_hash.to_hex_string())?;
+
+        for tracker in &self.trackers {
+            write!(f, "&tr={}", percent_encode(tracker))?;
+        }
+
+        for peer in &self.peers {
+            write!(f, "&x.pe={}", percent_encode(&peer.to_string()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that the tracker URL is valid and uses a supported scheme.
+///
+/// # Errors
+///
+/// Will return an error message if the URL can't be parsed or its scheme is
+/// not supported.
+pub fn validate_tracker_url(tracker: &str) -> Result<(), String> {
+    let url = url::Url::parse(tracker).map_err(|err| err.to_string())?;
+
+    if !TRACKER_SCHEMES.contains(&url.scheme()) {
+        return Err(format!(
+            "unsupported scheme {}, expected one of: {}",
+            url.scheme(),
+            TRACKER_SCHEMES.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+fn percent_encode(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{validate_tracker_url, MagnetLink};
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    fn info_hash() -> InfoHash {
+        InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap()
+    }
+
+    #[test]
+    fn it_should_only_include_the_info_hash_by_default() {
+        assert_eq!(
+            MagnetLink::new(info_hash()).to_string(),
+            "magnet:?xt=urn:btih:443c7602b4fde83d1154d6d9da48808418b181b6"
+        );
+    }
+
+    #[test]
+    fn it_should_include_the_percent_encoded_trackers_and_peers() {
+        let magnet_link = MagnetLink::new(info_hash())
+            .with_trackers(&["udp://tracker.example.com:6969/announce?key=a&b".to_string()])
+            .with_peers(&["[::1]:6881".parse().unwrap()]);
+
+        assert_eq!(
+            magnet_link.to_string(),
+            "magnet:?xt=urn:btih:443c7602b4fde83d1154d6d9da48808418b181b6\
+             &tr=udp%3A%2F%2Ftracker.example.com%3A6969%2Fannounce%3Fkey%3Da%26b\
+             &x.pe=%5B%3A%3A1%5D%3A6881"
+        );
+    }
+
+    #[test]
+    fn it_should_be_parsed_back_by_the_bit_torrent_client() {
+        let tracker = "http://tracker.example.com/announce?key=a&b".to_string();
+
+        let magnet_link =
+            MagnetLink::new(info_hash()).with_trackers(std::slice::from_ref(&tracker));
+
+        let magnet = librqbit::Magnet::parse(&magnet_link.to_string()).unwrap();
+
+        assert_eq!(magnet.trackers, vec![tracker]);
+    }
+
+    #[test]
+    fn it_should_validate_the_tracker_urls() {
+        assert!(validate_tracker_url("udp://tracker.example.com:6969/announce").is_ok());
+        assert!(validate_tracker_url("https://tracker.example.com/announce").is_ok());
+        assert!(validate_tracker_url("tracker.example.com").is_err());
+        assert!(validate_tracker_url("ftp://tracker.example.com").is_err());
+    }
+}
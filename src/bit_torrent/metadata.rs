@@ -0,0 +1,260 @@
+//! Torrent metadata in a format that is easy to consume by API clients.
+//!
+//! It's a simplified view of the torrent `info` dictionary (see
+//! [`TorrentMetaV1Info`]) with the file paths already reconstructed and the
+//! total size of the torrent.
+use std::fmt::Write;
+
+use librqbit::TorrentMetaV1Info;
+use serde::{Deserialize, Serialize};
+
+use super::info_hash::InfoHash;
+
+/// The separator used to join the file path components.
+const PATH_SEPARATOR: &str = "/";
+
+/// The length of the SHA-1 piece hashes.
+const PIECE_HASH_LENGTH: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub info_hash: InfoHash,
+    pub name: String,
+    /// False when the torrent name is not valid UTF-8. The `name` is
+    /// lossy-decoded then, with the invalid bytes replaced by `U+FFFD`, so
+    /// clients can decide how to display it.
+    pub name_valid_utf8: bool,
+    pub total_size: u64,
+    pub piece_length: u32,
+    pub files: Vec<File>,
+    /// The hex-encoded SHA-1 hashes of the pieces. Only included on request,
+    /// as there can be thousands of them.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pieces: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    /// The full path of the file inside the torrent. Components are joined
+    /// with `/`.
+    pub path: String,
+    pub length: u64,
+}
+
+impl Metadata {
+    /// Builds the metadata from the torrent `info` dictionary.
+    ///
+    /// For single-file torrents the only file is named after the torrent
+    /// `name`. If the torrent does not have a name, the info-hash is used
+    /// instead. Non UTF-8 names are lossy-decoded.
+    #[must_use]
+    pub fn from_info<B: AsRef<[u8]>>(info_hash: &InfoHash, info: &TorrentMetaV1Info<B>) -> Self {
+        let name = torrent_name(info_hash, info);
+
+        let files: Vec<File> = match &info.files {
+            Some(files) => files
+                .iter()
+                .map(|file| File {
+                    path: file
+                        .path
+                        .iter()
+                        .map(|component| String::from_utf8_lossy(component.as_ref()))
+                        .collect::<Vec<_>>()
+                        .join(PATH_SEPARATOR),
+                    length: file.length,
+                })
+                .collect(),
+            None => vec![File {
+                path: name.clone(),
+                length: info.length.unwrap_or_default(),
+            }],
+        };
+
+        Self {
+            info_hash: *info_hash,
+            name,
+            name_valid_utf8: info
+                .name
+                .as_ref()
+                .is_none_or(|name| std::str::from_utf8(name.as_ref()).is_ok()),
+            total_size: files.iter().map(|file| file.length).sum(),
+            piece_length: info.piece_length,
+            files,
+            pieces: None,
+        }
+    }
+
+    /// Adds the piece hashes from the torrent `info` dictionary.
+    #[must_use]
+    pub fn with_pieces<B: AsRef<[u8]>>(mut self, info: &TorrentMetaV1Info<B>) -> Self {
+        self.pieces = Some(piece_hashes(info));
+        self
+    }
+}
+
+/// Returns the hex-encoded piece hashes. The `pieces` field is the
+/// concatenation of the 20-byte SHA-1 hashes of all the pieces.
+fn piece_hashes<B: AsRef<[u8]>>(info: &TorrentMetaV1Info<B>) -> Vec<String> {
+    info.pieces
+        .as_ref()
+        .chunks(PIECE_HASH_LENGTH)
+        .map(|hash| {
+            hash.iter().fold(String::new(), |mut hex, byte| {
+                let _ = write!(hex, "{byte:02x}");
+                hex
+            })
+        })
+        .collect()
+}
+
+/// Returns the torrent name, or the info-hash if it does not have one.
+pub(crate) fn torrent_name<B: AsRef<[u8]>>(
+    info_hash: &InfoHash,
+    info: &TorrentMetaV1Info<B>,
+) -> String {
+    info.name.as_ref().map_or_else(
+        || info_hash.to_hex_string(),
+        |name| String::from_utf8_lossy(name.as_ref()).to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use librqbit::{ByteBuf, TorrentMetaV1File, TorrentMetaV1Info};
+
+    use super::{File, Metadata};
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    fn info_hash() -> InfoHash {
+        InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap()
+    }
+
+    #[test]
+    fn it_should_be_built_from_a_single_file_torrent_info_dictionary() {
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample.txt")),
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: Some(11),
+            md5sum: None,
+            files: None,
+        };
+
+        let metadata = Metadata::from_info(&info_hash(), &info);
+
+        assert_eq!(
+            metadata,
+            Metadata {
+                info_hash: info_hash(),
+                name: "sample.txt".to_string(),
+                name_valid_utf8: true,
+                total_size: 11,
+                piece_length: 16384,
+                files: vec![File {
+                    path: "sample.txt".to_string(),
+                    length: 11
+                }],
+                pieces: None,
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_be_built_from_a_multi_file_torrent_info_dictionary() {
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample")),
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: None,
+            md5sum: None,
+            files: Some(vec![
+                TorrentMetaV1File {
+                    length: 11,
+                    path: vec![ByteBuf(b"sample.txt")],
+                },
+                TorrentMetaV1File {
+                    length: 5,
+                    path: vec![ByteBuf(b"docs"), ByteBuf(b"readme.txt")],
+                },
+            ]),
+        };
+
+        let metadata = Metadata::from_info(&info_hash(), &info);
+
+        assert_eq!(metadata.total_size, 16);
+        assert_eq!(
+            metadata.files,
+            vec![
+                File {
+                    path: "sample.txt".to_string(),
+                    length: 11
+                },
+                File {
+                    path: "docs/readme.txt".to_string(),
+                    length: 5
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_include_the_hex_encoded_piece_hashes_on_request() {
+        let mut pieces = [0u8; 40];
+        pieces[19] = 0xab;
+        pieces[20] = 0x01;
+
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample.txt")),
+            pieces: ByteBuf(&pieces),
+            piece_length: 16384,
+            length: Some(20000),
+            md5sum: None,
+            files: None,
+        };
+
+        let metadata = Metadata::from_info(&info_hash(), &info).with_pieces(&info);
+
+        assert_eq!(
+            metadata.pieces,
+            Some(vec![
+                "00000000000000000000000000000000000000ab".to_string(),
+                "0100000000000000000000000000000000000000".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn it_should_use_the_info_hash_as_name_when_the_torrent_has_no_name() {
+        let info = TorrentMetaV1Info {
+            name: None,
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: Some(11),
+            md5sum: None,
+            files: None,
+        };
+
+        let metadata = Metadata::from_info(&info_hash(), &info);
+
+        assert_eq!(metadata.name, info_hash().to_hex_string());
+    }
+
+    #[test]
+    fn it_should_lossy_decode_the_names_that_are_not_valid_utf8() {
+        let info = TorrentMetaV1Info {
+            name: Some(ByteBuf(b"sample\xff.txt")),
+            pieces: ByteBuf(&[0u8; 20]),
+            piece_length: 16384,
+            length: Some(11),
+            md5sum: None,
+            files: None,
+        };
+
+        let metadata = Metadata::from_info(&info_hash(), &info);
+
+        assert_eq!(metadata.name, "sample\u{fffd}.txt");
+        assert!(!metadata.name_valid_utf8);
+    }
+}
@@ -1,2 +1,10 @@
 pub mod client;
+pub mod file_tree;
 pub mod info_hash;
+pub mod magnet;
+pub mod metadata;
+pub mod resolver;
+pub mod torrent_file;
+pub mod tracker_list;
+pub mod verify_storage;
+pub mod web_cache;
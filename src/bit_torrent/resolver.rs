@@ -0,0 +1,67 @@
+//! Abstraction over the `BitTorrent` client.
+//!
+//! The API depends on the [`MagnetResolver`] trait instead of the
+//! librqbit-backed [`Client`], so the handlers can be tested with a fake
+//! resolver, without a real DHT, and other backends can be added later.
+use std::time::Duration;
+
+use futures_util::future::BoxFuture;
+
+use super::client::{Client, ResolveMagnetResult};
+use super::info_hash::InfoHash;
+use super::magnet::MagnetLink;
+
+/// It returns the torrent for an info hash, or the reason why it couldn't
+/// be resolved, and the state of the backend for the health checks.
+pub trait MagnetResolver: Send + Sync {
+    fn resolve_magnet(&self, info_hash: InfoHash) -> BoxFuture<'_, ResolveMagnetResult>;
+
+    /// Returns true if torrents can be resolved.
+    fn is_session_started(&self) -> bool;
+
+    fn is_dht_enabled(&self) -> bool {
+        false
+    }
+
+    /// Returns the number of nodes in the DHT routing table, or `None` if
+    /// the DHT is not used.
+    fn dht_nodes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the magnet link used to resolve the info hash. By default, it
+    /// only contains the info hash.
+    fn magnet_link(&self, info_hash: InfoHash) -> MagnetLink {
+        MagnetLink::new(info_hash)
+    }
+
+    /// Applies the trackers and resolve timeout of a reloaded config. By
+    /// default, it does nothing.
+    fn reconfigure(&self, _trackers: &[String], _resolve_timeout: Duration) {}
+}
+
+impl MagnetResolver for Client {
+    fn resolve_magnet(&self, info_hash: InfoHash) -> BoxFuture<'_, ResolveMagnetResult> {
+        Box::pin(Client::resolve_magnet(self, info_hash))
+    }
+
+    fn is_session_started(&self) -> bool {
+        Client::is_session_started(self)
+    }
+
+    fn is_dht_enabled(&self) -> bool {
+        Client::is_dht_enabled(self)
+    }
+
+    fn dht_nodes(&self) -> Option<usize> {
+        Client::dht_nodes(self)
+    }
+
+    fn magnet_link(&self, info_hash: InfoHash) -> MagnetLink {
+        Client::magnet_link(self, info_hash)
+    }
+
+    fn reconfigure(&self, trackers: &[String], resolve_timeout: Duration) {
+        Client::reconfigure(self, trackers, resolve_timeout);
+    }
+}
@@ -0,0 +1,334 @@
+//! Re-encoding of torrent files with more trackers.
+//!
+//! Torrents resolved from trackerless magnet links (or found in web caches)
+//! may not have trackers. When they are re-hosted, the configured trackers
+//! can be added to their `announce` and `announce-list`, so clients don't
+//! depend on the DHT to find peers.
+//!
+//! Only the `announce` and `announce-list` are replaced. The other top-level
+//! keys (the `info` dictionary, but also `url-list`, `httpseeds`, `nodes`,
+//! etc.) are copied byte by byte, so the info hash (the SHA-1 of the `info`
+//! dictionary) doesn't change.
+use bytes::Bytes;
+use librqbit::{torrent_from_bytes, ByteBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid torrent file: {0}")]
+    InvalidTorrent(String),
+}
+
+/// Returns the torrent file with the trackers added to its `announce-list`.
+///
+/// The tiers of the torrent are kept, and the trackers it doesn't have are
+/// added in a new tier, in order. The `announce` is the first tracker of the
+/// torrent, or the first added one if it doesn't have any.
+///
+/// # Errors
+///
+/// Will return an error if the torrent file can't be parsed.
+pub fn with_trackers(torrent_bytes: &[u8], trackers: &[String]) -> Result<Bytes, Error> {
+    let torrent = torrent_from_bytes::<ByteBuf>(torrent_bytes)
+        .map_err(|err| Error::InvalidTorrent(err.to_string()))?;
+
+    let mut tiers: Vec<Vec<&[u8]>> = torrent
+        .announce_list
+        .iter()
+        .map(|tier| tier.iter().map(AsRef::as_ref).collect::<Vec<&[u8]>>())
+        .filter(|tier| !tier.is_empty())
+        .collect();
+
+    // Clients ignore the `announce` when there is an `announce-list`, so it
+    // has to be in the list.
+    if let Some(announce) = &torrent.announce {
+        let announce: &[u8] = announce.as_ref();
+
+        if !announce.is_empty() && !tiers.iter().flatten().any(|tracker| *tracker == announce) {
+            tiers.insert(0, vec![announce]);
+        }
+    }
+
+    let mut new_tier: Vec<&[u8]> = vec![];
+
+    for tracker in trackers.iter().map(String::as_bytes) {
+        if !tiers
+            .iter()
+            .flatten()
+            .chain(&new_tier)
+            .any(|added| *added == tracker)
+        {
+            new_tier.push(tracker);
+        }
+    }
+
+    if !new_tier.is_empty() {
+        tiers.push(new_tier);
+    }
+
+    let announce = torrent
+        .announce
+        .as_ref()
+        .map(AsRef::as_ref)
+        .filter(|announce: &&[u8]| !announce.is_empty())
+        .or_else(|| tiers.first().and_then(|tier| tier.first().copied()));
+
+    let mut entries: Vec<(&[u8], Vec<u8>)> = dict_entries(torrent_bytes)?
+        .into_iter()
+        .filter(|(key, _)| *key != b"announce" && *key != b"announce-list")
+        .map(|(key, value)| (key, value.to_vec()))
+        .collect();
+
+    if let Some(announce) = announce {
+        let mut value = vec![];
+        write_bytes(&mut value, announce);
+        entries.push((b"announce", value));
+    }
+
+    if !tiers.is_empty() {
+        let mut value = vec![b'l'];
+        for tier in &tiers {
+            value.push(b'l');
+            for tracker in tier {
+                write_bytes(&mut value, tracker);
+            }
+            value.push(b'e');
+        }
+        value.push(b'e');
+        entries.push((b"announce-list", value));
+    }
+
+    // The keys of bencoded dictionaries must be sorted.
+    entries.sort_by_key(|(key, _)| *key);
+
+    let mut out = vec![b'd'];
+
+    for (key, value) in entries {
+        write_bytes(&mut out, key);
+        out.extend_from_slice(&value);
+    }
+
+    out.push(b'e');
+
+    Ok(Bytes::from(out))
+}
+
+/// Writes a bencoded byte string.
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+/// A key of a dictionary and its raw (bencoded) value.
+type Entry<'a> = (&'a [u8], &'a [u8]);
+
+/// Returns the keys and the raw (bencoded) values of the top-level
+/// dictionary, in order.
+fn dict_entries(bytes: &[u8]) -> Result<Vec<Entry<'_>>, Error> {
+    if bytes.first() != Some(&b'd') {
+        return Err(invalid("not a dictionary"));
+    }
+
+    let mut entries = vec![];
+    let mut pos = 1;
+
+    while bytes.get(pos) != Some(&b'e') {
+        let (key, value_start) = read_bytes(bytes, pos)?;
+        let value_end = skip_value(bytes, value_start)?;
+
+        entries.push((key, &bytes[value_start..value_end]));
+        pos = value_end;
+    }
+
+    if pos + 1 != bytes.len() {
+        return Err(invalid("trailing bytes after the dictionary"));
+    }
+
+    Ok(entries)
+}
+
+/// Returns the byte string starting at `pos` and the position after it.
+fn read_bytes(bytes: &[u8], pos: usize) -> Result<(&[u8], usize), Error> {
+    let colon = bytes[pos..]
+        .iter()
+        .position(|byte| *byte == b':')
+        .map(|offset| pos + offset)
+        .ok_or_else(|| invalid("unterminated byte string length"))?;
+
+    let len: usize = std::str::from_utf8(&bytes[pos..colon])
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .ok_or_else(|| invalid("invalid byte string length"))?;
+
+    let end = colon
+        .checked_add(1 + len)
+        .filter(|end| *end <= bytes.len())
+        .ok_or_else(|| invalid("truncated byte string"))?;
+
+    Ok((&bytes[colon + 1..end], end))
+}
+
+/// Returns the position after the value starting at `pos`.
+fn skip_value(bytes: &[u8], pos: usize) -> Result<usize, Error> {
+    match bytes.get(pos) {
+        Some(b'i') => bytes[pos..]
+            .iter()
+            .position(|byte| *byte == b'e')
+            .map(|offset| pos + offset + 1)
+            .ok_or_else(|| invalid("unterminated integer")),
+        Some(b'l' | b'd') => {
+            let mut pos = pos + 1;
+
+            while bytes.get(pos) != Some(&b'e') {
+                if pos >= bytes.len() {
+                    return Err(invalid("unterminated list or dictionary"));
+                }
+                pos = skip_value(bytes, pos)?;
+            }
+
+            Ok(pos + 1)
+        }
+        Some(b'0'..=b'9') => read_bytes(bytes, pos).map(|(_, end)| end),
+        _ => Err(invalid("invalid value")),
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidTorrent(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit::{torrent_from_bytes, ByteBuf};
+
+    use super::with_trackers;
+
+    const INFO: &[u8] =
+        b"d6:lengthi1e4:name4:test12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+
+    fn torrent(before_info: &[u8]) -> Vec<u8> {
+        [b"d".as_slice(), before_info, b"4:info", INFO, b"e"].concat()
+    }
+
+    fn trackers(torrent_bytes: &[u8]) -> (Option<String>, Vec<Vec<String>>) {
+        let torrent = torrent_from_bytes::<ByteBuf>(torrent_bytes).unwrap();
+        let to_string = |tracker: &ByteBuf| String::from_utf8(tracker.as_ref().to_vec()).unwrap();
+
+        (
+            torrent.announce.as_ref().map(to_string),
+            torrent
+                .announce_list
+                .iter()
+                .map(|tier| tier.iter().map(to_string).collect())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn it_should_keep_the_info_hash_when_adding_the_trackers() {
+        let original = torrent(b"");
+
+        let injected = with_trackers(&original, &["udp://a.example:1".to_string()]).unwrap();
+
+        assert_eq!(
+            torrent_from_bytes::<ByteBuf>(&injected).unwrap().info_hash,
+            torrent_from_bytes::<ByteBuf>(&original).unwrap().info_hash
+        );
+    }
+
+    #[test]
+    fn it_should_add_the_trackers_to_a_trackerless_torrent() {
+        let injected = with_trackers(
+            &torrent(b""),
+            &[
+                "udp://a.example:1".to_string(),
+                "udp://b.example:1".to_string(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            trackers(&injected),
+            (
+                Some("udp://a.example:1".to_string()),
+                vec![vec![
+                    "udp://a.example:1".to_string(),
+                    "udp://b.example:1".to_string()
+                ]]
+            )
+        );
+    }
+
+    #[test]
+    fn it_should_add_the_missing_trackers_in_a_new_tier_keeping_the_other_keys() {
+        let original = torrent(
+            b"8:announce17:udp://a.example:113:announce-listll17:udp://b.example:1ee\
+              7:comment4:test",
+        );
+
+        let injected = with_trackers(
+            &original,
+            &[
+                "udp://b.example:1".to_string(),
+                "udp://c.example:1".to_string(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            trackers(&injected),
+            (
+                Some("udp://a.example:1".to_string()),
+                vec![
+                    vec!["udp://a.example:1".to_string()],
+                    vec!["udp://b.example:1".to_string()],
+                    vec!["udp://c.example:1".to_string()]
+                ]
+            )
+        );
+        assert_eq!(
+            torrent_from_bytes::<ByteBuf>(&injected)
+                .unwrap()
+                .comment
+                .map(|comment| comment.as_ref().to_vec()),
+            Some(b"test".to_vec())
+        );
+    }
+
+    #[test]
+    fn it_should_keep_the_other_top_level_keys_byte_by_byte() {
+        // The keys must be sorted, and `url-list` goes after `info`.
+        let original = [
+            b"d7:comment4:test".as_slice(),
+            b"4:info",
+            INFO,
+            b"5:nodesll9:127.0.0.1i6881eee",
+            b"8:url-listl23:https://seed.example/a/e",
+            b"e",
+        ]
+        .concat();
+
+        let injected = with_trackers(&original, &["udp://a.example:1".to_string()]).unwrap();
+
+        assert_eq!(
+            injected.as_ref(),
+            [
+                b"d8:announce17:udp://a.example:113:announce-listll17:udp://a.example:1ee"
+                    .as_slice(),
+                b"7:comment4:test",
+                b"4:info",
+                INFO,
+                b"5:nodesll9:127.0.0.1i6881eee",
+                b"8:url-listl23:https://seed.example/a/e",
+                b"e",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn it_should_fail_when_the_torrent_file_is_not_valid() {
+        assert!(with_trackers(b"not a torrent", &[]).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Default trackers fetched from a remote list.
+//!
+//! Community-maintained lists of working trackers are published at stable
+//! URLs, with a tracker URL per line. The list is fetched on startup and
+//! refreshed periodically, and its trackers are added to the magnet links
+//! after the configured ones. The last fetched list is saved to disk, so it's
+//! used when the remote is not reachable on the next startup.
+use std::io;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use tracing::{debug, info, warn};
+
+use super::magnet::validate_tracker_url;
+use crate::config::RemoteTrackers as RemoteTrackersConfig;
+
+/// Maximum size in bytes of the remote list. Lists are a few KiB.
+const MAX_LIST_SIZE: usize = 1_048_576;
+
+pub struct RemoteTrackers {
+    http_client: reqwest::Client,
+    url: String,
+    refresh_interval: Duration,
+    cache_path: Utf8PathBuf,
+    trackers: RwLock<Vec<String>>,
+}
+
+impl RemoteTrackers {
+    /// It starts with the list saved to disk by the last fetch, if there is
+    /// one.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the HTTP client can't be built, for example, when the
+    /// TLS backend can't be initialized.
+    #[must_use]
+    pub fn new(config: &RemoteTrackersConfig) -> Self {
+        let trackers = match std::fs::read_to_string(&config.cache_path) {
+            Ok(list) => parse_trackers(&list),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                warn!(
+                    "error reading the cached tracker list {}: {err}",
+                    config.cache_path
+                );
+                vec![]
+            }
+        };
+
+        Self {
+            http_client: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .expect("the remote trackers HTTP client should be built"),
+            url: config.url.clone(),
+            refresh_interval: config.refresh_interval,
+            cache_path: config.cache_path.clone(),
+            trackers: RwLock::new(trackers),
+        }
+    }
+
+    /// Returns the trackers of the last list fetched.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn trackers(&self) -> Vec<String> {
+        self.trackers
+            .read()
+            .expect("remote trackers lock poisoned")
+            .clone()
+    }
+
+    /// Fetches the list now and then every refresh interval. The current
+    /// list is kept when a fetch fails.
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.refresh_interval);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.refresh().await {
+                warn!("error fetching the tracker list {}: {err}", self.url);
+            }
+        }
+    }
+
+    /// Fetches the list and saves it to disk.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the list can't be fetched, it's too large or
+    /// it can't be saved to disk.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub async fn refresh(&self) -> io::Result<()> {
+        let list = self.fetch().await?;
+
+        let trackers = parse_trackers(&list);
+
+        info!(
+            "{} trackers fetched from the tracker list {}",
+            trackers.len(),
+            self.url
+        );
+
+        *self
+            .trackers
+            .write()
+            .expect("remote trackers lock poisoned") = trackers;
+
+        let cache_path = self.cache_path.clone();
+
+        tokio::task::spawn_blocking(move || write_file(&cache_path, &list))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    async fn fetch(&self) -> io::Result<String> {
+        let response = self
+            .http_client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(io::Error::other)?;
+
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_LIST_SIZE as u64)
+        {
+            return Err(io::Error::other(format!(
+                "the list is larger than the maximum of {MAX_LIST_SIZE} bytes"
+            )));
+        }
+
+        let list = response.text().await.map_err(io::Error::other)?;
+
+        if list.len() > MAX_LIST_SIZE {
+            return Err(io::Error::other(format!(
+                "the list is larger than the maximum of {MAX_LIST_SIZE} bytes"
+            )));
+        }
+
+        Ok(list)
+    }
+}
+
+/// Returns the tracker URLs in the list, one per line. Blank lines and the
+/// ones starting with `#` are ignored, and the invalid URLs are skipped.
+fn parse_trackers(list: &str) -> Vec<String> {
+    let mut trackers: Vec<String> = vec![];
+
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Err(reason) = validate_tracker_url(line) {
+            debug!("invalid tracker URL {line} in the tracker list skipped: {reason}");
+            continue;
+        }
+
+        if !trackers.iter().any(|tracker| tracker == line) {
+            trackers.push(line.to_string());
+        }
+    }
+
+    trackers
+}
+
+/// Writes the file atomically, so a partially written list is never read.
+fn write_file(path: &Utf8PathBuf, contents: &str) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+
+    std::fs::write(&tmp_path, contents)?;
+
+    std::fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use axum::Router;
+    use camino::Utf8PathBuf;
+    use tempfile::TempDir;
+
+    use super::{parse_trackers, RemoteTrackers};
+    use crate::config::RemoteTrackers as RemoteTrackersConfig;
+
+    fn config(url: String, dir: &TempDir) -> RemoteTrackersConfig {
+        RemoteTrackersConfig {
+            url,
+            refresh_interval: Duration::from_secs(3600),
+            timeout: Duration::from_secs(5),
+            cache_path: Utf8PathBuf::from_path_buf(dir.path().join("trackers.txt")).unwrap(),
+        }
+    }
+
+    #[test]
+    fn it_should_only_return_the_valid_tracker_urls_without_duplicates() {
+        let list = "udp://a.example:1/announce\n\
+                    \n\
+                    # Comment\n\
+                    ftp://b.example/announce\n\
+                    http://c.example/announce\n\
+                    udp://a.example:1/announce\n";
+
+        assert_eq!(
+            parse_trackers(list),
+            vec!["udp://a.example:1/announce", "http://c.example/announce"]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_use_the_list_saved_to_disk_when_the_remote_is_not_reachable() {
+        let dir = TempDir::new().unwrap();
+
+        let socket = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let router = Router::new().route(
+            "/trackers.txt",
+            get(|| async { "udp://tracker.example.com:6969/announce\n" }),
+        );
+
+        tokio::spawn(async move {
+            axum_server::from_tcp(socket)
+                .serve(router.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        let remote_trackers =
+            RemoteTrackers::new(&config(format!("http://{address}/trackers.txt"), &dir));
+
+        assert!(remote_trackers.trackers().is_empty());
+
+        remote_trackers.refresh().await.unwrap();
+
+        assert_eq!(
+            remote_trackers.trackers(),
+            vec!["udp://tracker.example.com:6969/announce"]
+        );
+
+        let remote_trackers =
+            RemoteTrackers::new(&config("http://127.0.0.1:1/trackers.txt".to_string(), &dir));
+
+        assert!(remote_trackers.refresh().await.is_err());
+        assert_eq!(
+            remote_trackers.trackers(),
+            vec!["udp://tracker.example.com:6969/announce"]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! In-memory storage for the torrents added to verify their content.
+//!
+//! The downloaded pieces are kept in memory instead of being written to the
+//! session output dir, so verifying the content does not depend on the disk
+//! and never downloads a whole file. At most `max_bytes` (and at least one
+//! piece) are kept: writing a piece beyond that fails, and the client stops
+//! the torrent.
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::bail;
+use librqbit::storage::{BoxStorageFactory, StorageFactory, StorageFactoryExt, TorrentStorage};
+use librqbit::ManagedTorrentShared;
+
+#[derive(Clone)]
+pub struct VerifyStorageFactory {
+    max_bytes: u64,
+}
+
+impl VerifyStorageFactory {
+    #[must_use]
+    pub fn new(max_bytes: u64) -> Self {
+        Self { max_bytes }
+    }
+}
+
+impl StorageFactory for VerifyStorageFactory {
+    type Storage = VerifyStorage;
+
+    fn create(&self, shared: &ManagedTorrentShared) -> anyhow::Result<VerifyStorage> {
+        Ok(VerifyStorage::new(
+            u64::from(shared.info.piece_length),
+            shared
+                .file_infos
+                .iter()
+                .map(|file| file.offset_in_torrent)
+                .collect(),
+            self.max_bytes,
+        ))
+    }
+
+    fn clone_box(&self) -> BoxStorageFactory {
+        self.clone().boxed()
+    }
+}
+
+pub struct VerifyStorage {
+    piece_length: u64,
+    /// The offset of each file in the torrent.
+    file_offsets: Vec<u64>,
+    max_pieces: usize,
+    pieces: Mutex<HashMap<u64, Box<[u8]>>>,
+}
+
+impl VerifyStorage {
+    fn new(piece_length: u64, file_offsets: Vec<u64>, max_bytes: u64) -> Self {
+        let piece_length = piece_length.max(1);
+        let max_pieces = usize::try_from(max_bytes / piece_length)
+            .unwrap_or(usize::MAX)
+            .max(1);
+
+        Self {
+            piece_length,
+            file_offsets,
+            max_pieces,
+            pieces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the index of the piece and the range of the bytes in it.
+    fn locate(
+        &self,
+        file_id: usize,
+        offset: u64,
+        len: usize,
+    ) -> anyhow::Result<(u64, usize, usize)> {
+        let Some(file_offset) = self.file_offsets.get(file_id) else {
+            bail!("unknown file {file_id}");
+        };
+
+        let offset = file_offset + offset;
+        let piece = offset / self.piece_length;
+        let start = usize::try_from(offset % self.piece_length)?;
+        let end = start + len;
+
+        if end > usize::try_from(self.piece_length)? {
+            bail!("the bytes at {offset} are not in a single piece");
+        }
+
+        Ok((piece, start, end))
+    }
+}
+
+impl TorrentStorage for VerifyStorage {
+    fn init(&mut self, _shared: &ManagedTorrentShared) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn pread_exact(&self, file_id: usize, offset: u64, buf: &mut [u8]) -> anyhow::Result<()> {
+        let (piece, start, end) = self.locate(file_id, offset, buf.len())?;
+
+        let pieces = self.pieces.lock().expect("verify storage lock poisoned");
+
+        let Some(bytes) = pieces.get(&piece) else {
+            bail!("piece {piece} not downloaded");
+        };
+
+        buf.copy_from_slice(&bytes[start..end]);
+
+        Ok(())
+    }
+
+    fn pwrite_all(&self, file_id: usize, offset: u64, buf: &[u8]) -> anyhow::Result<()> {
+        let (piece, start, end) = self.locate(file_id, offset, buf.len())?;
+
+        let mut pieces = self.pieces.lock().expect("verify storage lock poisoned");
+
+        if !pieces.contains_key(&piece) && pieces.len() >= self.max_pieces {
+            bail!("the maximum of {} pieces is reached", self.max_pieces);
+        }
+
+        let bytes = pieces.entry(piece).or_insert_with(|| {
+            vec![0; usize::try_from(self.piece_length).unwrap_or(usize::MAX)].into_boxed_slice()
+        });
+
+        bytes[start..end].copy_from_slice(buf);
+
+        Ok(())
+    }
+
+    fn remove_file(&self, _file_id: usize, _filename: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn remove_directory_if_empty(&self, _path: &Path) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn ensure_file_length(&self, _file_id: usize, _length: u64) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn take(&self) -> anyhow::Result<Box<dyn TorrentStorage>> {
+        let pieces =
+            std::mem::take(&mut *self.pieces.lock().expect("verify storage lock poisoned"));
+
+        Ok(Box::new(Self {
+            piece_length: self.piece_length,
+            file_offsets: self.file_offsets.clone(),
+            max_pieces: self.max_pieces,
+            pieces: Mutex::new(pieces),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use librqbit::storage::TorrentStorage;
+
+    use super::VerifyStorage;
+
+    #[test]
+    fn it_should_read_the_written_pieces_across_files() {
+        // Two files of 6 bytes, with pieces of 4 bytes.
+        let storage = VerifyStorage::new(4, vec![0, 6], 1024);
+
+        storage.pwrite_all(0, 4, b"ab").unwrap();
+        storage.pwrite_all(1, 0, b"cd").unwrap();
+
+        let mut buf = [0; 4];
+        storage.pread_exact(0, 4, &mut buf[..2]).unwrap();
+        storage.pread_exact(1, 0, &mut buf[2..]).unwrap();
+
+        assert_eq!(&buf, b"abcd");
+        assert!(storage.pread_exact(0, 0, &mut buf).is_err());
+    }
+
+    #[test]
+    fn it_should_keep_at_most_the_maximum_bytes_and_at_least_one_piece() {
+        let storage = VerifyStorage::new(4, vec![0], 8);
+
+        storage.pwrite_all(0, 0, b"a").unwrap();
+        storage.pwrite_all(0, 4, b"b").unwrap();
+        assert!(storage.pwrite_all(0, 8, b"c").is_err());
+
+        // The pieces already stored can still be written.
+        storage.pwrite_all(0, 1, b"d").unwrap();
+
+        let storage = VerifyStorage::new(16, vec![0], 8);
+
+        storage.pwrite_all(0, 0, b"a").unwrap();
+        assert!(storage.pwrite_all(0, 16, b"b").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+//! Web torrent caches, used as a fallback when the torrent metadata is not
+//! found in the `BitTorrent` network.
+//!
+//! They are HTTP servers hosting the torrent files at
+//! `<base_url>/<info_hash>.torrent`, with the 40 hex characters info hash.
+//! The torrent files are only returned if their info hash matches the
+//! requested one, so a compromised cache can't serve other torrents.
+use bytes::{Bytes, BytesMut};
+use librqbit::{torrent_from_bytes, ByteBuf, ByteBufOwned};
+use tracing::{debug, warn};
+
+use super::client::ResolvedTorrent;
+use super::info_hash::InfoHash;
+use crate::config::WebCaches as WebCachesConfig;
+
+pub struct WebCaches {
+    http_client: reqwest::Client,
+    base_urls: Vec<String>,
+}
+
+impl WebCaches {
+    /// # Panics
+    ///
+    /// Will panic if the HTTP client can't be built, for example, when the
+    /// TLS backend can't be initialized.
+    #[must_use]
+    pub fn new(config: &WebCachesConfig) -> Self {
+        Self {
+            http_client: reqwest::Client::builder()
+                .timeout(config.timeout)
+                .build()
+                .expect("the web caches HTTP client should be built"),
+            base_urls: config.base_urls.clone(),
+        }
+    }
+
+    /// Returns the torrent from the first cache that has it, trying them in
+    /// order. Torrent files larger than `max_size` are ignored.
+    pub async fn fetch(&self, info_hash: InfoHash, max_size: usize) -> Option<ResolvedTorrent> {
+        for base_url in &self.base_urls {
+            let url = torrent_url(base_url, &info_hash);
+
+            let Some(bytes) = self.download(&url, max_size).await else {
+                continue;
+            };
+
+            match parse_torrent_for(&bytes, &info_hash) {
+                Some(resolved) => {
+                    debug!("torrent {info_hash} fetched from {url}");
+                    return Some(resolved);
+                }
+                None => warn!("the torrent in {url} is not a valid torrent for {info_hash}"),
+            }
+        }
+
+        None
+    }
+
+    /// Downloads the torrent file, if it's found and not larger than
+    /// `max_size`.
+    async fn download(&self, url: &str, max_size: usize) -> Option<Bytes> {
+        let mut response = match self.http_client.get(url).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                debug!("torrent not found in {url}: {}", response.status());
+                return None;
+            }
+            Err(err) => {
+                warn!("error fetching {url}: {err}");
+                return None;
+            }
+        };
+
+        if response
+            .content_length()
+            .is_some_and(|length| length > max_size as u64)
+        {
+            warn!("the torrent in {url} is larger than the maximum of {max_size} bytes");
+            return None;
+        }
+
+        // The length is not always known in advance, so it's also checked
+        // while reading the body.
+        let mut bytes = BytesMut::new();
+
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    if bytes.len() + chunk.len() > max_size {
+                        warn!(
+                            "the torrent in {url} is larger than the maximum of {max_size} bytes"
+                        );
+                        return None;
+                    }
+
+                    bytes.extend_from_slice(&chunk);
+                }
+                Ok(None) => return Some(bytes.freeze()),
+                Err(err) => {
+                    warn!("error fetching {url}: {err}");
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+fn torrent_url(base_url: &str, info_hash: &InfoHash) -> String {
+    format!(
+        "{}/{}.torrent",
+        base_url.trim_end_matches('/'),
+        info_hash.to_hex_string()
+    )
+}
+
+/// Returns the torrent if the bytes are a valid torrent file for the info
+/// hash.
+fn parse_torrent_for(bytes: &Bytes, info_hash: &InfoHash) -> Option<ResolvedTorrent> {
+    let computed = InfoHash(torrent_from_bytes::<ByteBuf>(bytes).ok()?.info_hash.0);
+
+    if computed != *info_hash {
+        return None;
+    }
+
+    let torrent = torrent_from_bytes::<ByteBufOwned>(bytes).ok()?;
+
+    Some(ResolvedTorrent {
+        info: torrent.info,
+        torrent_bytes: bytes.clone(),
+        seen_peers: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use axum::routing::get;
+    use axum::Router;
+    use bytes::Bytes;
+    use librqbit::{torrent_from_bytes, ByteBuf};
+
+    use super::{torrent_url, WebCaches};
+    use crate::bit_torrent::info_hash::InfoHash;
+    use crate::config::WebCaches as WebCachesConfig;
+
+    /// Returns a valid torrent file with the given name and its info-hash.
+    fn torrent(name: &str) -> (InfoHash, Bytes) {
+        let mut bytes = format!(
+            "d4:infod6:lengthi11e4:name{}:{name}12:piece lengthi16384e6:pieces20:",
+            name.len()
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&[0u8; 20]);
+        bytes.extend_from_slice(b"ee");
+
+        let info_hash = InfoHash(torrent_from_bytes::<ByteBuf>(&bytes).unwrap().info_hash.0);
+
+        (info_hash, Bytes::from(bytes))
+    }
+
+    /// Serves the router on a free local port and returns its base URL.
+    async fn serve(router: Router) -> String {
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        format!("http://{address}")
+    }
+
+    fn web_caches(base_urls: Vec<String>) -> WebCaches {
+        WebCaches::new(&WebCachesConfig {
+            base_urls,
+            timeout: Duration::from_secs(5),
+        })
+    }
+
+    #[test]
+    fn it_should_build_the_torrent_url_from_the_base_url() {
+        let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+
+        assert_eq!(
+            torrent_url("https://cache.example.com/torrents/", &info_hash),
+            "https://cache.example.com/torrents/443c7602b4fde83d1154d6d9da48808418b181b6.torrent"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_the_torrent_from_the_first_cache_that_has_it() {
+        let (info_hash, bytes) = torrent("sample.txt");
+        let path = format!("/{}.torrent", info_hash.to_hex_string());
+
+        let empty_cache = serve(Router::new()).await;
+        let cache = serve(Router::new().route(&path, get(move || async move { bytes }))).await;
+
+        let resolved = web_caches(vec![empty_cache, cache])
+            .fetch(info_hash, 10_485_760)
+            .await
+            .unwrap();
+
+        assert_eq!(resolved.torrent_bytes, torrent("sample.txt").1);
+        assert_eq!(resolved.info.name.unwrap().as_ref(), b"sample.txt");
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_the_torrents_of_another_info_hash_or_too_large() {
+        let (info_hash, bytes) = torrent("sample.txt");
+        let (_, other_bytes) = torrent("other.txt");
+        let path = format!("/{}.torrent", info_hash.to_hex_string());
+
+        let lying_cache =
+            serve(Router::new().route(&path, get(move || async move { other_bytes }))).await;
+        let cache = serve(Router::new().route(&path, get(move || async move { bytes }))).await;
+
+        assert!(web_caches(vec![lying_cache])
+            .fetch(info_hash, 10_485_760)
+            .await
+            .is_none());
+        assert!(web_caches(vec![cache]).fetch(info_hash, 10).await.is_none());
+    }
+}
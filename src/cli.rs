@@ -0,0 +1,173 @@
+//! Command line arguments.
+//!
+//! Without arguments, the API is started. The `resolve` command resolves a
+//! single info hash with the `BitTorrent` client, without starting the API,
+//! and writes the torrent file to stdout (or the `--out` path). It's meant
+//! for scripting and debugging:
+//!
+//! ```text
+//! torrust-hash2torrent resolve <info_hash> [--out <path>] [--timeout <seconds>]
+//! ```
+use std::str::FromStr;
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use thiserror::Error;
+
+use crate::bit_torrent::info_hash::InfoHash;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("Unknown command: {0}, the only command is `resolve`")]
+    UnknownCommand(String),
+    #[error("Missing the info hash to resolve")]
+    MissingInfoHash,
+    #[error("Invalid info hash: {0}")]
+    InvalidInfoHash(String),
+    #[error("Missing the value of the {0} option")]
+    MissingValue(String),
+    #[error("Invalid timeout: {0}, it must be a positive number of seconds")]
+    InvalidTimeout(String),
+    #[error("Unexpected argument: {0}")]
+    UnexpectedArgument(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Starts the API.
+    Serve,
+    Resolve(Resolve),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Resolve {
+    pub info_hash: InfoHash,
+    /// The torrent file is written to stdout when it's `None`.
+    pub out: Option<Utf8PathBuf>,
+    /// Overrides the configured resolve timeout.
+    pub timeout: Option<Duration>,
+}
+
+/// Parses the arguments, without the program name.
+///
+/// # Errors
+///
+/// Will return an error if the command or any of its arguments is not
+/// valid.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, Error> {
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None => Ok(Command::Serve),
+        Some("resolve") => parse_resolve(args).map(Command::Resolve),
+        Some(command) => Err(Error::UnknownCommand(command.to_string())),
+    }
+}
+
+fn parse_resolve<I: Iterator<Item = String>>(mut args: I) -> Result<Resolve, Error> {
+    let mut info_hash = None;
+    let mut out = None;
+    let mut timeout = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--out" => {
+                out = Some(Utf8PathBuf::from(option_value(&mut args, &arg)?));
+            }
+            "--timeout" => {
+                let value = option_value(&mut args, &arg)?;
+
+                timeout = match value.parse::<u64>() {
+                    Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+                    _ => return Err(Error::InvalidTimeout(value)),
+                };
+            }
+            _ if info_hash.is_none() && !arg.starts_with("--") => {
+                info_hash = Some(
+                    InfoHash::from_str(&arg).map_err(|_| Error::InvalidInfoHash(arg.clone()))?,
+                );
+            }
+            _ => return Err(Error::UnexpectedArgument(arg)),
+        }
+    }
+
+    Ok(Resolve {
+        info_hash: info_hash.ok_or(Error::MissingInfoHash)?,
+        out,
+        timeout,
+    })
+}
+
+fn option_value<I: Iterator<Item = String>>(args: &mut I, option: &str) -> Result<String, Error> {
+    args.next()
+        .ok_or_else(|| Error::MissingValue(option.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    use camino::Utf8PathBuf;
+
+    use super::{parse, Command, Error, Resolve};
+    use crate::bit_torrent::info_hash::InfoHash;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn it_should_start_the_api_without_arguments() {
+        assert_eq!(parse(args(&[])), Ok(Command::Serve));
+    }
+
+    #[test]
+    fn it_should_parse_the_resolve_command() {
+        assert_eq!(
+            parse(args(&[
+                "resolve",
+                "443c7602b4fde83d1154d6d9da48808418b181b6",
+                "--out",
+                "out.torrent",
+                "--timeout",
+                "30",
+            ])),
+            Ok(Command::Resolve(Resolve {
+                info_hash: InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap(),
+                out: Some(Utf8PathBuf::from("out.torrent")),
+                timeout: Some(Duration::from_secs(30)),
+            }))
+        );
+    }
+
+    #[test]
+    fn it_should_reject_invalid_resolve_arguments() {
+        assert_eq!(parse(args(&["resolve"])), Err(Error::MissingInfoHash));
+        assert_eq!(
+            parse(args(&["resolve", "not-an-info-hash"])),
+            Err(Error::InvalidInfoHash("not-an-info-hash".to_string()))
+        );
+        assert_eq!(
+            parse(args(&[
+                "resolve",
+                "443c7602b4fde83d1154d6d9da48808418b181b6",
+                "--out"
+            ])),
+            Err(Error::MissingValue("--out".to_string()))
+        );
+        assert_eq!(
+            parse(args(&[
+                "resolve",
+                "443c7602b4fde83d1154d6d9da48808418b181b6",
+                "--timeout",
+                "0"
+            ])),
+            Err(Error::InvalidTimeout("0".to_string()))
+        );
+        assert_eq!(
+            parse(args(&["serve"])),
+            Err(Error::UnknownCommand("serve".to_string()))
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Typed client for the API, so other Rust projects don't need to build the
+//! requests themselves.
+//!
+//! It's enabled with the `client` feature.
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), torrust_hash2torrent::client::Error> {
+//! use std::str::FromStr;
+//!
+//! use torrust_hash2torrent::bit_torrent::info_hash::InfoHash;
+//! use torrust_hash2torrent::client::Client;
+//!
+//! let client = Client::new("http://127.0.0.1:3000");
+//! let info_hash = InfoHash::from_str("443c7602b4fde83d1154d6d9da48808418b181b6").unwrap();
+//!
+//! let torrent_file = client.resolve(&info_hash).await?;
+//! let metadata = client.metadata(&info_hash).await?;
+//! # Ok(())
+//! # }
+//! ```
+use bytes::Bytes;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use thiserror::Error;
+
+use crate::api::error::ErrorBody;
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::bit_torrent::metadata::Metadata;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("The request to the API failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The API returned an error. The body contains the stable error code
+    /// (see [`ApiError`](crate::api::error::ApiError)).
+    #[error("The API returned a {status} error: {}", body.message)]
+    Api { status: StatusCode, body: ErrorBody },
+}
+
+pub struct Client {
+    http_client: reqwest::Client,
+    base_url: String,
+    auth_token: Option<String>,
+}
+
+impl Client {
+    /// The base URL is the API root, for example `http://127.0.0.1:3000`.
+    #[must_use]
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Uses the given HTTP client, for example, to set timeouts or proxies.
+    #[must_use]
+    pub fn with_http_client(base_url: &str, http_client: reqwest::Client) -> Self {
+        Self {
+            http_client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth_token: None,
+        }
+    }
+
+    /// Sends the token in the `Authorization` header, for APIs with an auth
+    /// token.
+    #[must_use]
+    pub fn with_auth_token(mut self, auth_token: &str) -> Self {
+        self.auth_token = Some(auth_token.to_string());
+        self
+    }
+
+    /// Returns the torrent file (the bencoded metainfo) for the info hash.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the request fails or the API returns an error,
+    /// for example, when the torrent is not found.
+    pub async fn resolve(&self, info_hash: &InfoHash) -> Result<Bytes, Error> {
+        let response = self
+            .send(self.get(&format!("/torrents/{}", info_hash.to_hex_string())))
+            .await?;
+
+        Ok(response.bytes().await?)
+    }
+
+    /// Returns the torrent metadata (name, sizes and file list).
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the request fails or the API returns an error,
+    /// for example, when the torrent is not found.
+    pub async fn metadata(&self, info_hash: &InfoHash) -> Result<Metadata, Error> {
+        let response = self
+            .send(self.get(&format!("/torrents/{}/metadata", info_hash.to_hex_string())))
+            .await?;
+
+        Ok(response.json().await?)
+    }
+
+    fn get(&self, path: &str) -> RequestBuilder {
+        let request = self.http_client.get(format!("{}{path}", self.base_url));
+
+        match &self.auth_token {
+            Some(auth_token) => request.bearer_auth(auth_token),
+            None => request,
+        }
+    }
+
+    /// Sends the request and returns the response if it's successful.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let response = request.send().await?;
+
+        let status = response.status();
+
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        Err(Error::Api {
+            status,
+            body: response.json().await?,
+        })
+    }
+}
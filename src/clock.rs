@@ -0,0 +1,50 @@
+//! Time source abstraction.
+//!
+//! Components that depend on the current time (like the cache TTL) get the
+//! time from a [`Clock`] so tests can control it.
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The real system clock.
+#[derive(Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves forward when told to. Used for testing.
+pub struct StoppedClock {
+    now: Mutex<SystemTime>,
+}
+
+impl StoppedClock {
+    #[must_use]
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Moves the clock forward.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("clock lock poisoned");
+        *now += duration;
+    }
+}
+
+impl Clock for StoppedClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().expect("clock lock poisoned")
+    }
+}
@@ -1,21 +1,1112 @@
-use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::Duration;
 
 use camino::Utf8PathBuf;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Method, StatusCode};
+use thiserror::Error;
+use tracing_subscriber::filter::LevelFilter;
 
-#[derive(Clone)]
+use crate::api::filename::validate_template;
+use crate::api::handler::info_hash_headers;
+use crate::api::slowloris;
+use crate::bit_torrent::info_hash::InfoHash;
+use crate::bit_torrent::magnet::validate_tracker_url;
+
+/// The minimum `RequestLimits::max_header_size`, the smallest buffer size
+/// accepted by hyper.
+pub const MIN_MAX_HEADER_SIZE: usize = 8192;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid tracker URL {url}: {reason}")]
+    InvalidTrackerUrl { url: String, reason: String },
+    #[error("Invalid web cache URL {url}: {reason}")]
+    InvalidWebCacheUrl { url: String, reason: String },
+    #[error("Invalid remote trackers list URL {url}: {reason}")]
+    InvalidRemoteTrackersUrl { url: String, reason: String },
+    #[error("At least one tracker is required when the DHT is disabled")]
+    NoTrackersWithoutDht,
+    #[error("At least one listen port is required")]
+    NoListenPorts,
+    #[error("The DHT is required to count the peers with the torrent")]
+    MinPeersWithoutDht,
+    #[error("Invalid filename template {template}: {reason}")]
+    InvalidFilenameTemplate { template: String, reason: String },
+    #[error("Invalid CORS allowed origin: {origin}")]
+    InvalidCorsOrigin { origin: String },
+    #[error("Invalid info hash header prefix {prefix}: it must be a valid header name")]
+    InvalidHeaderPrefix { prefix: String },
+    #[error("Invalid max header size {size}: the minimum is {MIN_MAX_HEADER_SIZE} bytes")]
+    InvalidMaxHeaderSize { size: usize },
+    #[error(
+        "Invalid slowloris jitter {}ms: the maximum is the timeout, {}ms",
+        jitter.as_millis(),
+        timeout.as_millis()
+    )]
+    InvalidSlowlorisJitter { jitter: Duration, timeout: Duration },
+    #[error(
+        "Invalid max resolve timeout {}ms: it must be shorter than the resolve request timeout, {}ms",
+        max.as_millis(),
+        request_timeout.as_millis()
+    )]
+    InvalidMaxResolveTimeout {
+        max: Duration,
+        request_timeout: Duration,
+    },
+    #[error(
+        "Invalid web caches timeout: the resolve timeout, {}ms, plus the timeout of each web cache, {}ms in total, must be shorter than the resolve request timeout, {}ms",
+        resolve_timeout.as_millis(),
+        total.as_millis(),
+        request_timeout.as_millis()
+    )]
+    InvalidWebCachesTimeout {
+        resolve_timeout: Duration,
+        total: Duration,
+        request_timeout: Duration,
+    },
+    #[error(
+        "Invalid rate limit of {requests_per_second} requests per second and a burst of {burst}: both must be positive"
+    )]
+    InvalidRateLimit {
+        requests_per_second: f64,
+        burst: u32,
+    },
+    #[error("Invalid maintenance mode status {status}: it must be an error status")]
+    InvalidMaintenanceStatus { status: StatusCode },
+    #[error("Invalid log format {format}: it must be full, pretty or json")]
+    InvalidLogFormat { format: String },
+}
+
+#[derive(Clone, Default)]
 pub struct Config {
     pub api: Api,
     pub client: Client,
+    pub cache: Cache,
+    /// Torrent resolved on startup to check the `BitTorrent` network is
+    /// reachable. The check is skipped when it's `None`.
+    pub canary: Option<Canary>,
+    /// Torrents resolved on startup to populate the cache. It's disabled
+    /// when it's `None`.
+    pub warm_up: Option<WarmUp>,
+    pub logging: Logging,
 }
 
 #[derive(Clone)]
 pub struct Client {
-    pub listen_port_range: Option<std::ops::Range<u16>>,
+    /// Ports the `BitTorrent` client listens on for incoming peer
+    /// connections. `None` means it does not accept incoming connections.
+    pub listen_ports: Option<ListenPorts>,
     pub session_output_dir: Utf8PathBuf,
+    /// Maximum number of magnet links resolved at the same time.
+    pub max_concurrent_resolutions: usize,
+    /// How long a resolution waits for a free slot when the limit is reached.
+    /// `None` means it's rejected immediately.
+    pub resolution_queue_timeout: Option<Duration>,
+    /// Maximum time to find the torrent metadata. When it's exceeded the
+    /// torrent is considered not found.
+    pub resolve_timeout: Duration,
+    /// How many times a torrent that can't be added to the `BitTorrent`
+    /// client is retried. `0` disables retries.
+    pub resolve_retries: u32,
+    /// Delay before the first retry. It's doubled for each following one.
+    pub resolve_retry_base_delay: Duration,
+    /// Maximum size in bytes of the torrent metadata. Larger torrents are
+    /// rejected, so they are never cached nor served. It's checked once the
+    /// metadata is downloaded from the peers, so it doesn't bound the memory
+    /// used to download it: librqbit buffers up to 1 MiB per peer, a limit
+    /// that can't be configured. The torrent files of the web caches are not
+    /// downloaded beyond it.
+    pub max_metadata_size: usize,
+    /// Minimum number of distinct DHT peers advertising the info hash before
+    /// the metadata is trusted, so a single lying peer can't serve a fake
+    /// torrent. The metadata is accepted right away when it's `None`.
+    pub min_peers: Option<usize>,
+    /// Removes the stale files the client leaves in the session output dir,
+    /// so the disk usage is bounded on long-running instances. Disabled when
+    /// it's `None`.
+    pub session_cleanup: Option<SessionCleanup>,
+    /// Minimum free space in the disk of the session output dir. Below it,
+    /// only cached torrents are served. Disabled when it's `None`.
+    pub free_disk_space: Option<FreeDiskSpace>,
+    /// Disables the DHT, for networks where it's not reachable. Torrents are
+    /// only resolved using the configured trackers.
+    pub disable_dht: bool,
+    /// Minimum number of nodes in the DHT routing table for the service to
+    /// be ready, so it doesn't accept traffic before the DHT is usable. It's
+    /// ignored when the DHT is disabled.
+    pub min_dht_nodes: usize,
+    /// Tracker URLs added to the magnet links, so torrents that are not well
+    /// seeded in the DHT can also be resolved.
+    pub trackers: Vec<String>,
+    /// A remote list of trackers added to the magnet links after the
+    /// configured ones. Disabled when it's `None`.
+    pub remote_trackers: Option<RemoteTrackers>,
+    /// Known peers the client always asks for the torrent metadata.
+    pub peers: Vec<SocketAddr>,
+    /// Web torrent caches tried when the metadata is not found in the
+    /// `BitTorrent` network. Disabled when it's `None`.
+    pub web_caches: Option<WebCaches>,
+    /// Timeouts of the connections to the peers asked for the metadata.
+    pub peer_connection: PeerConnection,
+    /// Downloads a piece of the content after resolving the metadata, and
+    /// fails the resolution if no peer serves a piece matching its hash
+    /// before the resolve timeout. It rejects torrents whose peers only have
+    /// the metadata, at the cost of slower resolutions.
+    pub verify_content: bool,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self {
+            listen_ports: Some((51000..51010).into()),
+            session_output_dir: "/var/lib/torrust/hash2torrent/session".into(),
+            max_concurrent_resolutions: 50,
+            resolution_queue_timeout: Some(Duration::from_secs(1)),
+            // Less than the API request timeout, so it's reported as not found.
+            resolve_timeout: Duration::from_secs(8),
+            resolve_retries: 0,
+            resolve_retry_base_delay: Duration::from_millis(500),
+            // 10 MiB
+            max_metadata_size: 10_485_760,
+            min_peers: None,
+            session_cleanup: Some(SessionCleanup {
+                // One day
+                max_age: Duration::from_secs(86_400),
+                on_shutdown: false,
+            }),
+            free_disk_space: Some(FreeDiskSpace {
+                // 1 GiB
+                min_free_bytes: 1_073_741_824,
+                check_interval: Duration::from_secs(60),
+            }),
+            disable_dht: false,
+            // Ready as soon as the DHT has reached a node.
+            min_dht_nodes: 1,
+            trackers: vec![],
+            // For example, `Some(RemoteTrackers { url, refresh_interval, timeout, cache_path })`.
+            remote_trackers: None,
+            peers: vec![],
+            // For example, `Some(WebCaches { base_urls, timeout })`.
+            web_caches: None,
+            // The `BitTorrent` client defaults.
+            peer_connection: PeerConnection::default(),
+            verify_content: false,
+        }
+    }
+}
+
+/// A list with a tracker URL per line, see
+/// [`tracker_list`](crate::bit_torrent::tracker_list).
+#[derive(Clone)]
+pub struct RemoteTrackers {
+    /// For example, `https://example.com/trackers_best.txt`.
+    pub url: String,
+    /// How often the list is fetched again.
+    pub refresh_interval: Duration,
+    /// Maximum time to fetch the list.
+    pub timeout: Duration,
+    /// Where the last fetched list is saved. It's used until the list is
+    /// fetched, for example, when the remote is not reachable on startup.
+    pub cache_path: Utf8PathBuf,
+}
+
+/// HTTP servers hosting torrent files, see
+/// [`web_cache`](crate::bit_torrent::web_cache).
+#[derive(Clone)]
+pub struct WebCaches {
+    /// For example, `https://cache.example.com/torrents`. They are tried in
+    /// order.
+    pub base_urls: Vec<String>,
+    /// Maximum time to fetch a torrent file from each of them. It's on top
+    /// of the resolve timeout, and the total must be shorter than the resolve
+    /// request timeout (see `RequestTimeouts::resolve`).
+    pub timeout: Duration,
+}
+
+/// The `BitTorrent` client asks many peers for the metadata at the same
+/// time, and takes it from the first one sending it. Lower timeouts drop the
+/// unresponsive peers sooner, so the slots are used for other peers. The
+/// client defaults are used for the timeouts that are `None`.
+///
+/// The number of peers can't be configured: librqbit connects to up to 128
+/// peers per resolution and does not expose the limit in the
+/// `SessionOptions`. Lower [`Client::max_concurrent_resolutions`] to bound
+/// the peer connections of the whole client.
+#[derive(Clone, Debug, Default)]
+pub struct PeerConnection {
+    pub connect_timeout: Option<Duration>,
+    pub read_write_timeout: Option<Duration>,
+}
+
+impl Config {
+    /// # Errors
+    ///
+    /// Will return an error if any of the configured values is not valid, or
+    /// the web caches can't be tried before the resolve request timeout.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.api.validate()?;
+        self.client.validate()?;
+
+        // The web caches are tried after the resolve timeout, one after the
+        // other. Otherwise, a slow cache makes the request time out with a
+        // `408` instead of returning the torrent or a `404`.
+        if let Some(web_caches) = &self.client.web_caches {
+            let resolve_timeout = self.client.resolve_timeout;
+            let count = u32::try_from(web_caches.base_urls.len()).unwrap_or(u32::MAX);
+            let total = resolve_timeout.saturating_add(web_caches.timeout.saturating_mul(count));
+            let request_timeout = self.api.request_timeouts.resolve;
+
+            if total >= request_timeout {
+                return Err(Error::InvalidWebCachesTimeout {
+                    resolve_timeout,
+                    total,
+                    request_timeout,
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Api {
+    /// # Errors
+    ///
+    /// Will return an error if the filename template, the info hash header
+    /// prefix, any of the CORS allowed origins, the maintenance mode status,
+    /// the max header size, the slowloris jitter or the rate limit is not
+    /// valid, or the max resolve timeout is not shorter than the resolve
+    /// request timeout.
+    pub fn validate(&self) -> Result<(), Error> {
+        validate_template(&self.filename_template).map_err(|reason| {
+            Error::InvalidFilenameTemplate {
+                template: self.filename_template.clone(),
+                reason,
+            }
+        })?;
+
+        let (info_hash_header, version_header) = info_hash_headers(&self.info_hash_header_prefix);
+
+        if HeaderName::try_from(info_hash_header).is_err()
+            || HeaderName::try_from(version_header).is_err()
+        {
+            return Err(Error::InvalidHeaderPrefix {
+                prefix: self.info_hash_header_prefix.clone(),
+            });
+        }
+
+        if let Some(Cors {
+            allowed_origins: AllowedOrigins::List(origins),
+            ..
+        }) = &self.cors
+        {
+            for origin in origins {
+                if HeaderValue::from_str(origin).is_err() {
+                    return Err(Error::InvalidCorsOrigin {
+                        origin: origin.clone(),
+                    });
+                }
+            }
+        }
+
+        let status = self.maintenance.status;
+
+        if !status.is_client_error() && !status.is_server_error() {
+            return Err(Error::InvalidMaintenanceStatus { status });
+        }
+
+        let size = self.request_limits.max_header_size;
+
+        if size < MIN_MAX_HEADER_SIZE {
+            return Err(Error::InvalidMaxHeaderSize { size });
+        }
+
+        let jitter = self.http_timeouts.slowloris_jitter;
+        let timeout = self.http_timeouts.slowloris_timeout;
+
+        if jitter > timeout {
+            return Err(Error::InvalidSlowlorisJitter { jitter, timeout });
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            rate_limit.validate()?;
+        }
+
+        // Otherwise, the resolutions with the max `timeout_ms` race the
+        // request timeout, and get a `408` instead of a `504`.
+        let max = self.max_resolve_timeout;
+        let request_timeout = self.request_timeouts.resolve;
+
+        if max >= request_timeout {
+            return Err(Error::InvalidMaxResolveTimeout {
+                max,
+                request_timeout,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Client {
+    /// # Errors
+    ///
+    /// Will return an error if any of the tracker, remote tracker list or web
+    /// cache URLs is not valid, the DHT is disabled and there are no trackers or a minimum
+    /// number of peers, or there are no listen ports.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self
+            .listen_ports
+            .as_ref()
+            .is_some_and(ListenPorts::is_empty)
+        {
+            return Err(Error::NoListenPorts);
+        }
+
+        if self.disable_dht && self.trackers.is_empty() && self.remote_trackers.is_none() {
+            return Err(Error::NoTrackersWithoutDht);
+        }
+
+        if self.disable_dht && self.min_peers.is_some() {
+            return Err(Error::MinPeersWithoutDht);
+        }
+
+        for tracker in &self.trackers {
+            validate_tracker_url(tracker).map_err(|reason| Error::InvalidTrackerUrl {
+                url: tracker.clone(),
+                reason,
+            })?;
+        }
+
+        for url in self
+            .web_caches
+            .iter()
+            .flat_map(|web_caches| &web_caches.base_urls)
+        {
+            validate_web_cache_url(url).map_err(|reason| Error::InvalidWebCacheUrl {
+                url: url.clone(),
+                reason,
+            })?;
+        }
+
+        if let Some(remote_trackers) = &self.remote_trackers {
+            validate_web_cache_url(&remote_trackers.url).map_err(|reason| {
+                Error::InvalidRemoteTrackersUrl {
+                    url: remote_trackers.url.clone(),
+                    reason,
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_web_cache_url(url: &str) -> Result<(), String> {
+    let url = url::Url::parse(url).map_err(|err| err.to_string())?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "unsupported scheme {}, expected http or https",
+            url.scheme()
+        ));
+    }
+
+    Ok(())
+}
+
+/// The client listens on the first free port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListenPorts {
+    Range(Range<u16>),
+    /// The ports are tried in order. Useful for non-contiguous sets of ports,
+    /// like the ones forwarded to a container.
+    List(Vec<u16>),
+}
+
+impl ListenPorts {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        match self {
+            ListenPorts::Range(range) => range.is_empty(),
+            ListenPorts::List(ports) => ports.is_empty(),
+        }
+    }
+}
+
+impl From<Range<u16>> for ListenPorts {
+    fn from(range: Range<u16>) -> Self {
+        ListenPorts::Range(range)
+    }
 }
 
 #[derive(Clone)]
 pub struct Api {
-    pub bind_address: SocketAddr,
+    /// The API listens on all of them. For example, `0.0.0.0:3000` and
+    /// `[::]:3000` for IPv4 and IPv6.
+    pub bind_addresses: Vec<SocketAddr>,
     pub torrents_cache_dir: Utf8PathBuf,
+    /// Minimum value accepted for the `timeout_ms` query param.
+    pub min_resolve_timeout: Duration,
+    /// Maximum value accepted for the `timeout_ms` query param.
+    pub max_resolve_timeout: Duration,
+    /// Time to wait for in-flight requests to finish on shutdown.
+    pub shutdown_grace_period: Duration,
+    /// Time to wait for in-flight requests to finish before listening on the
+    /// new bind addresses, when a config reload changes them. The
+    /// connections still open are closed when it elapses.
+    pub rebind_drain_deadline: Duration,
+    /// Serve the API over HTTPS. Plain HTTP is used when it's `None`.
+    pub tls: Option<Tls>,
+    /// Per-IP rate limit for the torrent endpoints. Disabled when it's `None`.
+    pub rate_limit: Option<RateLimit>,
+    /// Tracks the most frequently failing info hashes for the admin
+    /// endpoint. Disabled when it's `None`.
+    pub top_failures: Option<TopFailures>,
+    /// `max-age` of the `Cache-Control` header sent with the torrent files.
+    /// It can be long, as the torrent file for an info hash never changes.
+    pub torrent_max_age: Duration,
+    /// What the `ETag` of the torrent files is computed from.
+    pub etag: ETag,
+    /// `Content-Type` of the torrent files.
+    pub torrent_content_type: TorrentContentType,
+    /// Maximum number of info hashes in a batch request.
+    pub max_batch_size: usize,
+    /// Maximum number of info hashes of a batch request resolved at the same
+    /// time, so a big batch can't take all the client resolution slots (see
+    /// `Client::max_concurrent_resolutions`) and starve the single-hash
+    /// requests. It should be lower than the client limit.
+    pub max_batch_concurrency: usize,
+    /// Maximum number of resolutions in progress, including the ones waiting
+    /// for a free slot in the `BitTorrent` client (see
+    /// `Client::max_concurrent_resolutions`). New resolutions are rejected
+    /// with a `503` when it's reached. There is no limit when it's `None`.
+    pub max_pending_resolutions: Option<usize>,
+    /// New connections are closed right away, before reading their requests,
+    /// while there are more resolutions in progress than this. It's disabled
+    /// when it's `None`.
+    pub connection_shedding_threshold: Option<usize>,
+    /// Maximum number of connections open at the same time. New connections
+    /// are closed right away while it's reached. There is no limit when it's
+    /// `None`.
+    pub max_connections: Option<usize>,
+    /// Connection timeouts. Increase them for clients on high-latency
+    /// networks.
+    pub http_timeouts: HttpTimeouts,
+    /// Maximum time to handle the requests, by endpoint.
+    pub request_timeouts: RequestTimeouts,
+    /// Maximum size of the requests, as a protection against clients sending
+    /// huge headers or bodies.
+    pub request_limits: RequestLimits,
+    /// Delay sent in the `Retry-After` header of the errors that are likely
+    /// transient, like torrents not found yet or a busy client.
+    pub retry_after: Duration,
+    /// Bearer token for the admin endpoints (purging the cache). They are
+    /// disabled when it's `None`.
+    pub admin_token: Option<String>,
+    /// Bearer token required by all the endpoints, except the health checks.
+    /// The API is open when it's `None`.
+    pub auth_token: Option<String>,
+    /// Only serve the torrents already in the cache. Misses fail immediately
+    /// with a `404` instead of resolving the magnet link. Useful during DHT
+    /// outages and for load testing.
+    pub cache_only: bool,
+    /// Torrents that can't be resolved get a `200` with a placeholder torrent
+    /// file instead of an error, for clients that can't handle them. See
+    /// [`placeholder`](crate::api::placeholder).
+    pub placeholder_on_failure: bool,
+    /// Maximum number of files of the torrents returned by the metadata and
+    /// file tree endpoints. Torrents with more files are rejected with a
+    /// `413`. The torrent file endpoint is not affected. There is no limit
+    /// when it's `None`.
+    pub max_files: Option<usize>,
+    /// Prefix of the torrent file headers with the info hash and its version,
+    /// so organizations can namespace them. With `x-torrust-torrent`, the
+    /// headers are `x-torrust-torrent-infohash` and
+    /// `x-torrust-torrent-infohash-version`.
+    pub info_hash_header_prefix: String,
+    /// Filename of the downloaded torrent files. See
+    /// [`filename`](crate::api::filename) for the placeholders.
+    pub filename_template: String,
+    /// CORS headers for browser-based clients. No CORS headers are sent when
+    /// it's `None`.
+    pub cors: Option<Cors>,
+    /// Maintenance mode for planned downtime. It can also be toggled with the
+    /// admin endpoint.
+    pub maintenance: Maintenance,
+    /// Info hashes that are refused, or the only ones served.
+    pub access_lists: AccessLists,
+}
+
+impl Default for Api {
+    fn default() -> Self {
+        Self {
+            // Add `[::]:3000` to also listen on IPv6.
+            bind_addresses: vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 3000)],
+            torrents_cache_dir: "/var/lib/torrust/hash2torrent/torrents".into(),
+            min_resolve_timeout: Duration::from_millis(100),
+            max_resolve_timeout: Duration::from_secs(10),
+            shutdown_grace_period: Duration::from_secs(10),
+            rebind_drain_deadline: Duration::from_secs(10),
+            tls: None,
+            rate_limit: None,
+            // For example, `Some(TopFailures { size: 20, max_tracked: 10_000 })`.
+            top_failures: None,
+            // One year
+            torrent_max_age: Duration::from_secs(31_536_000),
+            etag: ETag::default(),
+            torrent_content_type: TorrentContentType::default(),
+            max_batch_size: 50,
+            max_batch_concurrency: 10,
+            max_pending_resolutions: Some(200),
+            // For example, `Some(400)` to close new connections under heavier
+            // overload.
+            connection_shedding_threshold: None,
+            max_connections: None,
+            http_timeouts: HttpTimeouts::default(),
+            request_timeouts: RequestTimeouts::default(),
+            request_limits: RequestLimits::default(),
+            retry_after: Duration::from_secs(30),
+            admin_token: None,
+            auth_token: None,
+            cache_only: false,
+            placeholder_on_failure: false,
+            max_files: Some(10_000),
+            info_hash_header_prefix: "x-torrust-torrent".to_string(),
+            filename_template: "{info_hash}.torrent".to_string(),
+            // For example, `Some(Cors { allowed_origins, allowed_methods, .. })`.
+            cors: None,
+            maintenance: Maintenance::default(),
+            // For example, `denylist: Some(InfoHashList::File(path))`.
+            access_lists: AccessLists::default(),
+        }
+    }
+}
+
+/// Requests that take longer get a `408`. Torrent resolutions have their own
+/// budget, so monitoring stays responsive when they are backed up.
+#[derive(Clone)]
+pub struct RequestTimeouts {
+    /// For the health checks, the entrypoint, the metrics and the magnet
+    /// links, which should respond in milliseconds.
+    pub default: Duration,
+    /// For the endpoints that resolve torrents and the admin ones. It should
+    /// be longer than the client `resolve_timeout`, so torrents that can't be
+    /// resolved are reported as not found, and it must be longer than the
+    /// `max_resolve_timeout`, so the resolutions with a `timeout_ms` are
+    /// reported as timed out (`504`). With web caches, it must also be longer
+    /// than the `resolve_timeout` plus the timeout of each web cache, as they
+    /// are tried after it, one after the other.
+    pub resolve: Duration,
+}
+
+impl Default for RequestTimeouts {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(1),
+            // Some headroom over the default `max_resolve_timeout`.
+            resolve: Duration::from_secs(12),
+        }
+    }
+}
+
+/// Larger requests are rejected with a `431` (headers) or a `413` (body).
+#[derive(Clone)]
+pub struct RequestLimits {
+    /// Maximum size in bytes of the request line and headers (HTTP/1) or the
+    /// header list (HTTP/2). The minimum is [`MIN_MAX_HEADER_SIZE`].
+    pub max_header_size: usize,
+    /// Maximum size in bytes of the request body. Only the batch endpoint
+    /// has a body.
+    pub max_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_header_size: 16_384,
+            max_body_size: 65_536,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Cors {
+    pub allowed_origins: AllowedOrigins,
+    /// Methods allowed in cross-origin requests. Preflight `OPTIONS`
+    /// requests are always answered.
+    pub allowed_methods: Vec<Method>,
+    /// Let the browser read the info hash headers (see
+    /// `Api::info_hash_header_prefix`).
+    pub expose_info_hash_header: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    /// `*`
+    Any,
+    /// For example, `https://app.example.com`.
+    List(Vec<String>),
+}
+
+/// While enabled, the endpoints that resolve torrents return an error with
+/// the message, so users don't get confusing errors during planned downtime.
+/// The health checks are not affected.
+#[derive(Clone)]
+pub struct Maintenance {
+    /// Whether it's enabled on startup.
+    pub enabled: bool,
+    pub message: String,
+    /// It must be an error status, usually `503 Service Unavailable`.
+    pub status: StatusCode,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "The service is under maintenance, try again later".to_string(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+/// Lists of info hashes for legal compliance. See
+/// [`access_list`](crate::api::access_list).
+#[derive(Clone, Default)]
+pub struct AccessLists {
+    /// Info hashes that are never served. They get a
+    /// `451 Unavailable For Legal Reasons`.
+    pub denylist: Option<InfoHashList>,
+    /// Only these info hashes are served, the others get a `403`. All of
+    /// them are served when it's `None`.
+    pub allowlist: Option<InfoHashList>,
+}
+
+#[derive(Clone)]
+pub enum InfoHashList {
+    Inline(Vec<InfoHash>),
+    /// A file with an info hash (hex or base32) per line, for large lists.
+    /// Empty lines and lines starting with `#` are ignored.
+    File(Utf8PathBuf),
+}
+
+#[derive(Clone)]
+pub struct HttpTimeouts {
+    /// Maximum time to receive the request headers (HTTP/1).
+    pub header_read_timeout: Duration,
+    /// Maximum time to wait for a reply to a keep-alive ping (HTTP/2).
+    pub keep_alive_timeout: Duration,
+    /// Interval between keep-alive pings (HTTP/2).
+    pub keep_alive_interval: Duration,
+    /// Maximum time a connection can be idle before sending a request (see
+    /// [`slowloris`](crate::api::slowloris)). Increase it for clients on
+    /// high-latency links, like satellite ones.
+    pub slowloris_timeout: Duration,
+    /// Maximum random delay added to the slowloris timeout of each
+    /// connection, so they are not closed at the same instant during
+    /// slow-connection attacks. It can't be longer than the timeout.
+    pub slowloris_jitter: Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            header_read_timeout: Duration::from_secs(1),
+            keep_alive_timeout: Duration::from_secs(1),
+            keep_alive_interval: Duration::from_secs(1),
+            slowloris_timeout: slowloris::DEFAULT_TIMEOUT,
+            slowloris_jitter: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Tls {
+    /// Path to the PEM-encoded certificate (chain).
+    pub cert_path: Utf8PathBuf,
+    /// Path to the PEM-encoded private key.
+    pub key_path: Utf8PathBuf,
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    /// Cached torrents older than this are treated as misses and resolved
+    /// again. `None` means cached torrents never expire.
+    pub ttl: Option<Duration>,
+    /// Maximum number of torrents kept in memory. The least recently used
+    /// torrent is evicted when the limit is reached.
+    pub max_entries: usize,
+    /// Write the resolved torrents to the torrents cache dir, so they are
+    /// not resolved again after they are evicted from memory or a restart.
+    pub disk: bool,
+    /// Maximum total size in bytes of the torrents in the cache dir. The
+    /// oldest ones are removed when it's exceeded. `None` means no limit.
+    pub max_disk_size: Option<u64>,
+    /// Maximum number of torrent files written to the cache dir at the same
+    /// time, so many resolutions completing at once don't saturate slow
+    /// storage. The other writes wait for a free slot.
+    pub max_concurrent_disk_writes: usize,
+    /// How long the torrents that could not be resolved are remembered, so
+    /// repeated requests for them fail immediately. `None` disables the
+    /// negative cache.
+    pub negative_ttl: Option<Duration>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            ttl: None,
+            max_entries: 1000,
+            disk: true,
+            // 1 GiB
+            max_disk_size: Some(1_073_741_824),
+            max_concurrent_disk_writes: 4,
+            negative_ttl: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Canary {
+    /// A well-seeded torrent, so it can always be resolved when the network
+    /// works.
+    pub info_hash: InfoHash,
+    /// Maximum time to resolve it.
+    pub timeout: Duration,
+}
+
+/// See [`top_failures`](crate::api::top_failures).
+#[derive(Clone)]
+pub struct TopFailures {
+    /// Number of info hashes returned, the N of the top N.
+    pub size: usize,
+    /// Maximum number of info hashes whose failures are counted, so the
+    /// memory is bounded. It should be much larger than the `size`.
+    pub max_tracked: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ETag {
+    /// The info hash. Conditional requests are answered with a
+    /// `304 Not Modified` without getting the torrent, but identical torrent
+    /// files have different `ETag`s in each URL.
+    #[default]
+    InfoHash,
+    /// The SHA-256 of the torrent file, so caching proxies can coalesce
+    /// identical content. Conditional requests need the torrent, so they are
+    /// resolved (or read from the cache) before answering.
+    Content,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TorrentContentType {
+    /// `application/x-bittorrent`.
+    #[default]
+    BitTorrent,
+    /// `application/octet-stream`, for HTTP clients rejecting the `BitTorrent`
+    /// one.
+    OctetStream,
+}
+
+impl TorrentContentType {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TorrentContentType::BitTorrent => "application/x-bittorrent",
+            TorrentContentType::OctetStream => "application/octet-stream",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Logging {
+    pub format: LogFormat,
+    /// The most verbose level logged.
+    pub level: LevelFilter,
+    /// Writes the logs to stderr instead of stdout. The `resolve` command
+    /// always does, as it writes the torrent file to stdout.
+    pub to_stderr: bool,
+    /// Exports the spans to an OpenTelemetry collector. Disabled when it's
+    /// `None`.
+    pub otlp: Option<Otlp>,
+}
+
+/// OTLP (HTTP with protobuf) export of the traces.
+#[derive(Clone, Debug)]
+pub struct Otlp {
+    /// The URL of the traces, for example, `http://localhost:4318/v1/traces`.
+    pub endpoint: String,
+    /// The `service.name` of the traces.
+    pub service_name: String,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::default(),
+            level: LevelFilter::INFO,
+            to_stderr: false,
+            otlp: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable, a line per event.
+    #[default]
+    Full,
+    /// Human-readable, multi-line, for local development.
+    Pretty,
+    /// A JSON object per event, including the fields of its spans, for log
+    /// pipelines.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "full" => Ok(Self::Full),
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err(Error::InvalidLogFormat {
+                format: format.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WarmUp {
+    /// The known popular torrents, resolved in order.
+    pub info_hashes: InfoHashList,
+    /// Maximum number of them resolved at the same time. The client also
+    /// limits the resolutions of all the requests.
+    pub max_concurrency: usize,
+    /// Maximum time to warm the cache. The torrents not resolved in time are
+    /// skipped.
+    pub budget: Duration,
+}
+
+#[derive(Clone)]
+pub struct SessionCleanup {
+    /// Files not modified for longer than this are removed on startup, so
+    /// the ones of recent resolutions are kept.
+    pub max_age: Duration,
+    /// Also remove them on graceful shutdown, once the session is stopped.
+    pub on_shutdown: bool,
+}
+
+/// See [`disk_space`](crate::api::disk_space).
+#[derive(Clone)]
+pub struct FreeDiskSpace {
+    pub min_free_bytes: u64,
+    /// How often the free space is checked, after the check on startup.
+    pub check_interval: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Sustained number of requests per second allowed for each client IP.
+    pub requests_per_second: f64,
+    /// Maximum number of requests a client IP can make in a burst.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// # Errors
+    ///
+    /// Will return an error if the requests per second is not a positive
+    /// number or the burst is zero.
+    pub fn validate(&self) -> Result<(), Error> {
+        // It's also `false` for NaN.
+        let valid_rate = self.requests_per_second.is_finite() && self.requests_per_second > 0.0;
+
+        if !valid_rate || self.burst == 0 {
+            return Err(Error::InvalidRateLimit {
+                requests_per_second: self.requests_per_second,
+                burst: self.burst,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        Api, Client, Config, Error, ListenPorts, LogFormat, RateLimit, RemoteTrackers, WebCaches,
+    };
+
+    fn client() -> Client {
+        Client {
+            listen_ports: None,
+            session_output_dir: "session".into(),
+            ..Client::default()
+        }
+    }
+
+    #[test]
+    fn it_should_parse_the_log_format() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("pretty".parse::<LogFormat>().unwrap(), LogFormat::Pretty);
+        assert!(matches!(
+            "xml".parse::<LogFormat>(),
+            Err(Error::InvalidLogFormat { format }) if format == "xml"
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_tracker_urls() {
+        let client = Client {
+            trackers: vec!["tracker.example.com".to_string()],
+            ..client()
+        };
+
+        assert!(matches!(
+            client.validate(),
+            Err(Error::InvalidTrackerUrl { .. })
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_web_cache_urls_that_are_not_http() {
+        let client = Client {
+            web_caches: Some(WebCaches {
+                base_urls: vec!["ftp://cache.example.com".to_string()],
+                timeout: Duration::from_secs(5),
+            }),
+            ..client()
+        };
+
+        assert!(matches!(
+            client.validate(),
+            Err(Error::InvalidWebCacheUrl { .. })
+        ));
+    }
+
+    #[test]
+    fn it_should_reject_remote_tracker_list_urls_that_are_not_http() {
+        let client = Client {
+            remote_trackers: Some(RemoteTrackers {
+                url: "ftp://example.com/trackers.txt".to_string(),
+                refresh_interval: Duration::from_secs(86_400),
+                timeout: Duration::from_secs(5),
+                cache_path: "trackers.txt".into(),
+            }),
+            ..client()
+        };
+
+        assert!(matches!(
+            client.validate(),
+            Err(Error::InvalidRemoteTrackersUrl { .. })
+        ));
+    }
+
+    #[test]
+    fn it_should_require_at_least_one_tracker_when_the_dht_is_disabled() {
+        let client = Client {
+            disable_dht: true,
+            ..client()
+        };
+
+        assert!(matches!(
+            client.validate(),
+            Err(Error::NoTrackersWithoutDht)
+        ));
+
+        let client = Client {
+            trackers: vec!["udp://tracker.example.com:6969/announce".to_string()],
+            ..client
+        };
+
+        assert!(client.validate().is_ok());
+    }
+
+    #[test]
+    fn it_should_require_the_dht_for_a_minimum_number_of_peers() {
+        let client = Client {
+            disable_dht: true,
+            trackers: vec!["udp://tracker.example.com:6969/announce".to_string()],
+            min_peers: Some(3),
+            ..client()
+        };
+
+        assert!(matches!(client.validate(), Err(Error::MinPeersWithoutDht)));
+    }
+
+    #[test]
+    fn it_should_require_at_least_one_listen_port() {
+        let client = Client {
+            listen_ports: Some(ListenPorts::List(vec![])),
+            ..client()
+        };
+
+        assert!(matches!(client.validate(), Err(Error::NoListenPorts)));
+
+        let client = Client {
+            listen_ports: Some(ListenPorts::List(vec![51000, 51413])),
+            ..client
+        };
+
+        assert!(client.validate().is_ok());
+    }
+
+    #[test]
+    fn it_should_reject_a_rate_limit_that_never_refills_the_buckets() {
+        for (requests_per_second, burst) in [(0.0, 1), (-1.0, 1), (f64::NAN, 1), (1.0, 0)] {
+            let api = Api {
+                rate_limit: Some(RateLimit {
+                    requests_per_second,
+                    burst,
+                }),
+                ..Api::default()
+            };
+
+            assert!(matches!(
+                api.validate(),
+                Err(Error::InvalidRateLimit { .. })
+            ));
+        }
+
+        let api = Api {
+            rate_limit: Some(RateLimit {
+                requests_per_second: 0.5,
+                burst: 1,
+            }),
+            ..Api::default()
+        };
+
+        assert!(api.validate().is_ok());
+    }
+
+    #[test]
+    fn it_should_require_the_web_caches_to_be_tried_before_the_resolve_request_timeout() {
+        // 8 seconds of resolve timeout, and 12 of resolve request timeout.
+        let config = |base_urls: usize| Config {
+            client: Client {
+                web_caches: Some(WebCaches {
+                    base_urls: vec!["https://cache.example.com".to_string(); base_urls],
+                    timeout: Duration::from_secs(1),
+                }),
+                ..client()
+            },
+            ..Config::default()
+        };
+
+        assert!(config(3).validate().is_ok());
+        assert!(matches!(
+            config(4).validate(),
+            Err(Error::InvalidWebCachesTimeout { .. })
+        ));
+    }
 }
@@ -1,20 +1,64 @@
 use std::sync::Arc;
 
+use api::access_list::AccessList;
 use api::cache::Cache;
-use bit_torrent::client::Client;
+use api::disk_space::DiskSpaceGuard;
+use api::load_shedding::PendingResolutions;
+use api::maintenance::MaintenanceMode;
+use api::negative_cache::NegativeCache;
+use api::rate_limit::RateLimiter;
+use api::reload::ConfigReloader;
+use api::single_flight::SingleFlight;
+use api::top_failures::TopFailures;
+use bit_torrent::client::ResolveMagnetResult;
+use bit_torrent::info_hash::InfoHash;
+use bit_torrent::resolver::MagnetResolver;
 use config::Config;
+use metrics::Metrics;
+use stats::Stats;
 
 pub mod api;
 pub mod bit_torrent;
+pub mod cli;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod clock;
 pub mod config;
+pub mod logging;
+pub mod metrics;
+pub mod stats;
 
 pub struct AppState {
+    /// The config on startup. The settings changed by a reload are in the
+    /// [`reloader`](AppState::reloader).
     pub config: Arc<Config>,
-    pub client: Arc<Client>,
+    /// Resolves the magnet links. It's the librqbit-backed
+    /// [`Client`](bit_torrent::client::Client) in production, and a fake one
+    /// in the tests.
+    pub resolver: Arc<dyn MagnetResolver>,
     pub cache: Arc<Cache>,
-}
-
-#[must_use]
-pub fn run_app_for_integration_tests() -> String {
-    "No integration tests yet :-(".to_string()
+    /// Torrents that could not be resolved recently.
+    pub negative_cache: Arc<NegativeCache>,
+    /// Info hashes that failed to be resolved most often.
+    pub top_failures: Arc<TopFailures>,
+    /// Magnet resolutions in progress. Concurrent requests for the same
+    /// info-hash share the same resolution.
+    pub resolutions: Arc<SingleFlight<InfoHash, ResolveMagnetResult>>,
+    /// Number of resolutions in progress, to shed load when there are too
+    /// many.
+    pub pending_resolutions: Arc<PendingResolutions>,
+    pub metrics: Arc<Metrics>,
+    /// Recent resolution latencies and cache hit rate for the health check.
+    pub stats: Arc<Stats>,
+    /// It doesn't limit the requests when rate limiting is disabled.
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Whether the maintenance mode is enabled. It starts as configured.
+    pub maintenance: Arc<MaintenanceMode>,
+    /// Whether the disk of the session output dir is almost full.
+    pub disk_space: Arc<DiskSpaceGuard>,
+    /// The configured allowlist and denylist, with the list files loaded.
+    pub access_list: Arc<AccessList>,
+    /// The settings that can be changed at runtime, reloading the config
+    /// file.
+    pub reloader: Arc<ConfigReloader>,
 }
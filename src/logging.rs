@@ -0,0 +1,101 @@
+//! Logging setup.
+//!
+//! The request spans (`torrent_request`, `resolve_magnet`, ...) and their
+//! fields are included in all the formats. The JSON format adds them to each
+//! event as the `span` (the current one) and `spans` (all of them) objects.
+//!
+//! When an OTLP endpoint is configured, the spans are also exported to it as
+//! OpenTelemetry traces, with their fields (info hash, cache hit, outcome,
+//! ...) as attributes.
+use std::io;
+
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use crate::config::{LogFormat, Logging, Otlp};
+
+/// Initializes the global `tracing` subscriber.
+///
+/// It must be called from a Tokio runtime when the OTLP export is enabled,
+/// the spans are exported in the background.
+///
+/// # Errors
+///
+/// Will return an error if the OTLP exporter can't be built, for example,
+/// when the endpoint is not a valid URL.
+///
+/// # Panics
+///
+/// Will panic if the global subscriber is already set.
+pub fn init(config: &Logging) -> Result<(), TraceError> {
+    let writer = if config.to_stderr {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    };
+
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(writer);
+
+    let fmt_layer = match config.format {
+        LogFormat::Full => fmt_layer.boxed(),
+        LogFormat::Pretty => fmt_layer.pretty().boxed(),
+        LogFormat::Json => fmt_layer
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    let otlp_layer = config.otlp.as_ref().map(otlp_layer).transpose()?;
+
+    tracing_subscriber::registry()
+        .with(config.level)
+        .with(fmt_layer)
+        .with(otlp_layer)
+        .init();
+
+    Ok(())
+}
+
+/// Exports the spans not exported yet. It must be called before exiting when
+/// the OTLP export is enabled.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+fn otlp_layer<S>(config: &Otlp) -> Result<OpenTelemetryLayer<S, Tracer>, TraceError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(&config.endpoint),
+        )
+        .with_trace_config(opentelemetry_sdk::trace::Config::default().with_resource(
+            Resource::new(vec![KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )]),
+        ))
+        .install_batch(runtime::Tokio)?;
+
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+    // The global provider is kept to flush the spans on shutdown.
+    global::set_tracer_provider(provider);
+
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
@@ -1,56 +1,252 @@
+use camino::Utf8PathBuf;
 use std::{
     fs,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    io::{self, Write},
     sync::Arc,
+    time::Instant,
 };
 use torrust_hash2torrent::bit_torrent::client::Client;
+use torrust_hash2torrent::bit_torrent::metadata::Metadata;
+use torrust_hash2torrent::cli::{self, Command, Resolve};
 use torrust_hash2torrent::config::{self, Config};
+use torrust_hash2torrent::logging;
+use torrust_hash2torrent::metrics::Metrics;
+use torrust_hash2torrent::stats::Stats;
 use torrust_hash2torrent::{
-    api::{self, cache::Cache},
+    api::{
+        self,
+        access_list::{load_info_hashes, AccessList},
+        cache::Cache,
+        disk_space::DiskSpaceGuard,
+        load_shedding::PendingResolutions,
+        maintenance::MaintenanceMode,
+        negative_cache::NegativeCache,
+        rate_limit::RateLimiter,
+        reload::{self, ConfigReloader},
+        single_flight::SingleFlight,
+        top_failures::TopFailures,
+        warm_up,
+    },
     AppState,
 };
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+use tracing_subscriber::filter::LevelFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    tracing_subscriber::fmt().init();
+    let command = cli::parse(std::env::args().skip(1))?;
 
-    let session_output_dir = "/var/lib/torrust/hash2torrent/session";
-    let torrents_cache_dir = "/var/lib/torrust/hash2torrent/torrents";
-    let bind_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 3000);
-
-    let config = Config {
+    // See the `Default` impls in the `config` module for the other settings.
+    let mut config = Config {
         api: config::Api {
-            bind_address,
-            torrents_cache_dir: torrents_cache_dir.into(),
+            admin_token: std::env::var("TORRUST_HASH2TORRENT_ADMIN_TOKEN").ok(),
+            auth_token: std::env::var("TORRUST_HASH2TORRENT_AUTH_TOKEN").ok(),
+            ..config::Api::default()
         },
-        client: config::Client {
-            listen_port_range: Some(51000..51010),
-            session_output_dir: session_output_dir.into(),
+        client: config::Client::default(),
+        cache: config::Cache::default(),
+        // For example, `Some(config::Canary { info_hash, timeout })`.
+        canary: None,
+        // For example, `Some(config::WarmUp { info_hashes, max_concurrency, budget })`.
+        warm_up: None,
+        logging: config::Logging {
+            // `full`, `pretty` or `json`.
+            format: match std::env::var("TORRUST_HASH2TORRENT_LOG_FORMAT") {
+                Ok(format) => format.parse()?,
+                Err(_) => config::LogFormat::default(),
+            },
+            // `off`, `error`, `warn`, `info`, `debug` or `trace`.
+            level: match std::env::var("TORRUST_HASH2TORRENT_LOG_LEVEL") {
+                Ok(level) => level.parse()?,
+                Err(_) => LevelFilter::INFO,
+            },
+            to_stderr: false,
+            // For example, `http://localhost:4318/v1/traces`.
+            otlp: std::env::var("TORRUST_HASH2TORRENT_OTLP_ENDPOINT")
+                .ok()
+                .map(|endpoint| config::Otlp {
+                    endpoint,
+                    service_name: "torrust-hash2torrent".to_string(),
+                }),
         },
     };
 
-    check_storage(&config)?;
+    if matches!(command, Command::Resolve(_)) {
+        config.logging.to_stderr = true;
+    }
+
+    // The settings that can be changed at runtime, reloading the file with
+    // `POST /admin/reload`. See the `reload` module for the format.
+    let config_file = std::env::var("TORRUST_HASH2TORRENT_CONFIG_FILE")
+        .ok()
+        .map(Utf8PathBuf::from);
+    let base_config = config.clone();
+
+    if let Some(path) = &config_file {
+        config = reload::with_config_file(&base_config, path)?;
+    }
+
+    let bind_addresses = config.api.bind_addresses.clone();
+
+    logging::init(&config.logging)?;
+
+    config.validate()?;
+
+    // The `resolve` subcommand doesn't use the torrents cache.
+    check_storage(&config, !matches!(command, Command::Resolve(_)))?;
+
+    if let Command::Resolve(resolve) = command {
+        let result = resolve_one(config, resolve).await;
+
+        logging::shutdown();
+
+        return result;
+    }
 
     info!("creating BitTorrent client and starting the session ...");
 
     let mut client = Client::new(config.client.clone());
     client.start_session().await?;
+    let client = Arc::new(client);
 
-    info!("starting API on: http://{bind_address} ..."); // DevSkim: ignore DS137138
+    if let Some(remote_trackers) = client.remote_trackers() {
+        // It runs in the background. The list saved by the last fetch is
+        // used meanwhile.
+        tokio::spawn(async move { remote_trackers.run().await });
+    }
 
-    let app_state = AppState {
-        config: Arc::new(config),
-        client: Arc::new(client),
-        cache: Arc::new(Cache::new(torrents_cache_dir.into())),
+    if let Some(canary) = config.canary.clone() {
+        // It runs in the background, so it doesn't delay the API startup.
+        tokio::spawn(self_check(client.clone(), canary));
+    }
+
+    for bind_address in &bind_addresses {
+        info!("starting API on: http://{bind_address} ..."); // DevSkim: ignore DS137138
+    }
+
+    let cache = Cache::new(config.api.torrents_cache_dir.clone(), &config.cache);
+    let negative_cache = NegativeCache::new(&config.cache);
+    let top_failures = TopFailures::new(config.api.top_failures.as_ref());
+    let rate_limiter = Arc::new(RateLimiter::new(config.api.rate_limit.as_ref()));
+    let reloader = Arc::new(ConfigReloader::new(config_file, base_config, &config));
+    let maintenance = Arc::new(MaintenanceMode::new(config.api.maintenance.enabled));
+    let disk_space = Arc::new(DiskSpaceGuard::new(
+        &config.client.session_output_dir,
+        config.client.free_disk_space.clone(),
+    ));
+    let access_list = Arc::new(AccessList::load(&config.api.access_lists)?);
+    let warm_up = match config.warm_up.clone() {
+        Some(warm_up) => Some((load_info_hashes(&warm_up.info_hashes)?, warm_up)),
+        None => None,
     };
+    let metrics = Arc::new(Metrics::new());
+    let pending_resolutions = Arc::new(PendingResolutions::new(
+        config.api.max_pending_resolutions,
+        metrics.pending_resolutions(),
+    ));
+
+    let app_state = Arc::new(AppState {
+        config: Arc::new(config),
+        resolver: client.clone(),
+        cache: Arc::new(cache),
+        negative_cache: Arc::new(negative_cache),
+        top_failures: Arc::new(top_failures),
+        resolutions: Arc::new(SingleFlight::new()),
+        pending_resolutions,
+        metrics,
+        stats: Arc::new(Stats::new()),
+        rate_limiter,
+        maintenance,
+        disk_space: disk_space.clone(),
+        access_list,
+        reloader,
+    });
+
+    tokio::spawn(async move { disk_space.run().await });
+
+    if let Some((info_hashes, warm_up)) = warm_up {
+        let app_state = app_state.clone();
+
+        // It runs in the background, so it doesn't delay the API startup.
+        tokio::spawn(async move { warm_up::warm_up(&app_state, info_hashes, &warm_up).await });
+    }
+
+    api::start(app_state).await;
+
+    info!("stopping BitTorrent client session ...");
+
+    client.stop_session().await;
+
+    logging::shutdown();
+
+    Ok(())
+}
+
+/// Resolves the canary torrent and logs whether the `BitTorrent` network is
+/// reachable.
+async fn self_check(client: Arc<Client>, canary: config::Canary) {
+    info!(
+        "self-check: resolving canary torrent {} ...",
+        canary.info_hash.to_hex_string()
+    );
+
+    let start = Instant::now();
+
+    match tokio::time::timeout(canary.timeout, client.resolve_magnet(canary.info_hash)).await {
+        Ok(Ok(_)) => info!(
+            "self-check: canary torrent resolved in {} ms",
+            start.elapsed().as_millis()
+        ),
+        Ok(Err(err)) => error!(
+            "self-check FAILED: the canary torrent could not be resolved ({err}). \
+             Check the DHT, tracker and firewall settings."
+        ),
+        Err(_elapsed) => error!(
+            "self-check FAILED: the canary torrent was not resolved in {} seconds. \
+             Check the DHT, tracker and firewall settings.",
+            canary.timeout.as_secs()
+        ),
+    }
+}
+
+/// Resolves a single info hash without starting the API. The torrent file is
+/// written to stdout, or the `--out` path, and a summary of its metadata to
+/// stderr.
+async fn resolve_one(mut config: Config, resolve: Resolve) -> Result<(), anyhow::Error> {
+    if let Some(timeout) = resolve.timeout {
+        config.client.resolve_timeout = timeout;
+    }
+
+    let mut client = Client::new(config.client);
+    client.start_session().await?;
+
+    let result = client.resolve_magnet(resolve.info_hash).await;
+
+    client.stop_session().await;
+
+    let resolved = result?;
+
+    match &resolve.out {
+        Some(path) => fs::write(path, &resolved.torrent_bytes)?,
+        None => {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(&resolved.torrent_bytes)?;
+            stdout.flush()?;
+        }
+    }
+
+    let metadata = Metadata::from_info(&resolve.info_hash, &resolved.info);
 
-    api::start(&bind_address, app_state).await;
+    eprintln!("info hash:    {}", metadata.info_hash);
+    eprintln!("name:         {}", metadata.name);
+    eprintln!("total size:   {} bytes", metadata.total_size);
+    eprintln!("files:        {}", metadata.files.len());
+    eprintln!("piece length: {} bytes", metadata.piece_length);
 
     Ok(())
 }
 
-fn check_storage(config: &Config) -> Result<(), anyhow::Error> {
+fn check_storage(config: &Config, check_cache_dir: bool) -> Result<(), anyhow::Error> {
     // Check if the directories exist
     if fs::metadata(config.client.session_output_dir.clone()).is_err() {
         warn!(
@@ -63,7 +259,7 @@ fn check_storage(config: &Config) -> Result<(), anyhow::Error> {
         ));
     }
 
-    if fs::metadata(config.api.torrents_cache_dir.clone()).is_err() {
+    if check_cache_dir && fs::metadata(config.api.torrents_cache_dir.clone()).is_err() {
         warn!(
             "Torrents cache directory not found: {}",
             config.api.torrents_cache_dir
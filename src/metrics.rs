@@ -0,0 +1,269 @@
+//! Prometheus metrics.
+//!
+//! The metrics are registered in a [`Registry`] owned by [`Metrics`], which is
+//! shared via the `AppState`, so handlers can update them without global
+//! statics. They are exposed in the `OpenMetrics` text format at `/metrics`.
+use std::fmt::Write;
+use std::time::Duration;
+
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, LabelValueEncoder};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+use crate::bit_torrent::client::ResolveMagnetError;
+
+/// Implements [`EncodeLabelValue`] using the `snake_case` label values, as
+/// it's the convention for Prometheus labels.
+macro_rules! label_value {
+    ($name:ident { $($variant:ident => $value:literal),+ $(,)? }) => {
+        #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            #[must_use]
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value),+
+                }
+            }
+        }
+
+        impl EncodeLabelValue for $name {
+            fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+                encoder.write_str(self.as_str())
+            }
+        }
+    };
+}
+
+label_value!(Endpoint {
+    Torrent => "torrent",
+    Metadata => "metadata",
+    Batch => "batch",
+    Files => "files",
+    AnnounceList => "announce_list",
+    Status => "status",
+});
+
+label_value!(Outcome {
+    Success => "success",
+    NoSession => "no_session",
+    NotAdded => "not_added",
+    AddedForDownloading => "added_for_downloading",
+    Busy => "busy",
+    NotFound => "not_found",
+    MetadataTooLarge => "metadata_too_large",
+    NotEnoughPeers => "not_enough_peers",
+    InfoHashMismatch => "info_hash_mismatch",
+    InvalidMetadata => "invalid_metadata",
+    ContentNotVerified => "content_not_verified",
+    VerificationFailed => "verification_failed",
+});
+
+label_value!(CacheResult {
+    Hit => "hit",
+    Miss => "miss",
+});
+
+impl From<&ResolveMagnetError> for Outcome {
+    fn from(err: &ResolveMagnetError) -> Self {
+        match err {
+            ResolveMagnetError::NoSession => Outcome::NoSession,
+            ResolveMagnetError::NotAdded => Outcome::NotAdded,
+            ResolveMagnetError::AddedForDownloading => Outcome::AddedForDownloading,
+            ResolveMagnetError::Busy => Outcome::Busy,
+            ResolveMagnetError::NotFound => Outcome::NotFound,
+            ResolveMagnetError::MetadataTooLarge { .. } => Outcome::MetadataTooLarge,
+            ResolveMagnetError::NotEnoughPeers { .. } => Outcome::NotEnoughPeers,
+            ResolveMagnetError::InfoHashMismatch { .. } => Outcome::InfoHashMismatch,
+            ResolveMagnetError::InvalidMetadata => Outcome::InvalidMetadata,
+            ResolveMagnetError::ContentNotVerified => Outcome::ContentNotVerified,
+            ResolveMagnetError::VerificationFailed => Outcome::VerificationFailed,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct EndpointLabels {
+    pub endpoint: Endpoint,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct OutcomeLabels {
+    pub outcome: Outcome,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+pub struct CacheLabels {
+    pub result: CacheResult,
+}
+
+pub struct Metrics {
+    registry: Registry,
+    requests: Family<EndpointLabels, Counter>,
+    resolutions: Family<OutcomeLabels, Counter>,
+    cache_lookups: Family<CacheLabels, Counter>,
+    negative_cache_hits: Counter,
+    resolve_duration: Histogram,
+    pending_resolutions: Gauge,
+    open_connections: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("hash2torrent");
+
+        let requests = Family::<EndpointLabels, Counter>::default();
+        registry.register(
+            "requests",
+            "Number of requests to the torrent endpoints",
+            requests.clone(),
+        );
+
+        let resolutions = Family::<OutcomeLabels, Counter>::default();
+        registry.register(
+            "resolutions",
+            "Number of magnet link resolutions by outcome",
+            resolutions.clone(),
+        );
+
+        let cache_lookups = Family::<CacheLabels, Counter>::default();
+        registry.register(
+            "cache_lookups",
+            "Number of torrent cache lookups by result",
+            cache_lookups.clone(),
+        );
+
+        let negative_cache_hits = Counter::default();
+        registry.register(
+            "negative_cache_hits",
+            "Number of requests for torrents that could not be resolved recently, rejected without resolving them again",
+            negative_cache_hits.clone(),
+        );
+
+        // From 100ms to ~51s
+        let resolve_duration = Histogram::new(exponential_buckets(0.1, 2.0, 10));
+        registry.register(
+            "resolve_duration_seconds",
+            "Time spent resolving magnet links",
+            resolve_duration.clone(),
+        );
+
+        let pending_resolutions = Gauge::default();
+        registry.register(
+            "pending_resolutions",
+            "Number of magnet link resolutions in progress or waiting for a free slot",
+            pending_resolutions.clone(),
+        );
+
+        let open_connections = Gauge::default();
+        registry.register(
+            "open_connections",
+            "Number of open connections to the API",
+            open_connections.clone(),
+        );
+
+        Self {
+            registry,
+            requests,
+            resolutions,
+            cache_lookups,
+            negative_cache_hits,
+            resolve_duration,
+            pending_resolutions,
+            open_connections,
+        }
+    }
+
+    pub fn inc_requests(&self, endpoint: Endpoint) {
+        self.requests
+            .get_or_create(&EndpointLabels { endpoint })
+            .inc();
+    }
+
+    pub fn inc_cache_lookups(&self, result: CacheResult) {
+        self.cache_lookups
+            .get_or_create(&CacheLabels { result })
+            .inc();
+    }
+
+    pub fn inc_negative_cache_hits(&self) {
+        self.negative_cache_hits.inc();
+    }
+
+    /// Returns the number of cache lookups with the result since startup.
+    #[must_use]
+    pub fn cache_lookups(&self, result: CacheResult) -> u64 {
+        self.cache_lookups
+            .get_or_create(&CacheLabels { result })
+            .get()
+    }
+
+    /// Records the outcome and duration of a magnet link resolution.
+    pub fn observe_resolution(&self, outcome: Outcome, duration: Duration) {
+        self.resolutions
+            .get_or_create(&OutcomeLabels { outcome })
+            .inc();
+        self.resolve_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Returns the gauge of the pending resolutions. It's shared, so it's
+    /// updated by the owner of the count.
+    #[must_use]
+    pub fn pending_resolutions(&self) -> Gauge {
+        self.pending_resolutions.clone()
+    }
+
+    /// Returns the gauge of the open connections. It's shared, so it's
+    /// updated by the owner of the count.
+    #[must_use]
+    pub fn open_connections(&self) -> Gauge {
+        self.open_connections.clone()
+    }
+
+    /// Returns the metrics in the `OpenMetrics` text format.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the metrics can't be encoded.
+    pub fn encode(&self) -> Result<String, std::fmt::Error> {
+        let mut buffer = String::new();
+        prometheus_client::encoding::text::encode(&mut buffer, &self.registry)?;
+        Ok(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CacheResult, Endpoint, Metrics, Outcome};
+
+    #[test]
+    fn it_should_encode_the_metrics_in_the_open_metrics_text_format() {
+        let metrics = Metrics::new();
+
+        metrics.inc_requests(Endpoint::Torrent);
+        metrics.inc_cache_lookups(CacheResult::Miss);
+        metrics.observe_resolution(Outcome::NotAdded, Duration::from_millis(150));
+
+        let output = metrics.encode().unwrap();
+
+        assert!(output.contains(r#"hash2torrent_requests_total{endpoint="torrent"} 1"#));
+        assert!(output.contains(r#"hash2torrent_cache_lookups_total{result="miss"} 1"#));
+        assert!(output.contains(r#"hash2torrent_resolutions_total{outcome="not_added"} 1"#));
+        assert!(output.contains("hash2torrent_resolve_duration_seconds_count 1"));
+    }
+}
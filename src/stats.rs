@@ -0,0 +1,175 @@
+//! Recent resolution latencies and cache hit rate.
+//!
+//! Unlike the Prometheus [`metrics`](crate::metrics), which are cumulative,
+//! these only cover the last [`WINDOW_SIZE`] samples, so they give a quick
+//! view of the current behavior of the service in the health check.
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of recent samples kept for each statistic.
+pub const WINDOW_SIZE: usize = 1000;
+
+#[derive(Default)]
+pub struct Stats {
+    resolve_durations: Mutex<VecDeque<Duration>>,
+    cache_lookups: Mutex<VecDeque<bool>>,
+}
+
+/// The summary of the recent samples. The values are `None` when there are no
+/// samples yet.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Summary {
+    pub resolve_duration_p50_ms: Option<u128>,
+    pub resolve_duration_p95_ms: Option<u128>,
+    pub resolve_duration_p99_ms: Option<u128>,
+    /// From 0 to 1.
+    pub cache_hit_rate: Option<f64>,
+}
+
+impl Stats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the duration of a magnet link resolution.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn observe_resolution(&self, duration: Duration) {
+        push(
+            &mut self.resolve_durations.lock().expect("stats lock poisoned"),
+            duration,
+        );
+    }
+
+    /// Records whether a cache lookup was a hit or a miss.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    pub fn observe_cache_lookup(&self, hit: bool) {
+        push(
+            &mut self.cache_lookups.lock().expect("stats lock poisoned"),
+            hit,
+        );
+    }
+
+    /// Returns the percentiles of the recent resolution durations and the
+    /// recent cache hit rate.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if the internal lock is poisoned.
+    #[must_use]
+    pub fn summary(&self) -> Summary {
+        let mut durations: Vec<Duration> = self
+            .resolve_durations
+            .lock()
+            .expect("stats lock poisoned")
+            .iter()
+            .copied()
+            .collect();
+
+        durations.sort();
+
+        let cache_hit_rate = {
+            let cache_lookups = self.cache_lookups.lock().expect("stats lock poisoned");
+
+            let hits = cache_lookups.iter().filter(|hit| **hit).count();
+
+            #[allow(clippy::cast_precision_loss)]
+            (!cache_lookups.is_empty()).then(|| hits as f64 / cache_lookups.len() as f64)
+        };
+
+        Summary {
+            resolve_duration_p50_ms: percentile(&durations, 50).map(|d| d.as_millis()),
+            resolve_duration_p95_ms: percentile(&durations, 95).map(|d| d.as_millis()),
+            resolve_duration_p99_ms: percentile(&durations, 99).map(|d| d.as_millis()),
+            cache_hit_rate,
+        }
+    }
+}
+
+/// Adds the sample, removing the oldest one when the window is full.
+fn push<T>(samples: &mut VecDeque<T>, sample: T) {
+    if samples.len() == WINDOW_SIZE {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
+
+/// Returns the nearest-rank percentile of the sorted samples.
+fn percentile(sorted: &[Duration], percentile: usize) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = (percentile * sorted.len()).div_ceil(100).max(1);
+
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Stats, Summary, WINDOW_SIZE};
+
+    #[test]
+    fn it_should_not_have_values_without_samples() {
+        assert_eq!(
+            Stats::new().summary(),
+            Summary {
+                resolve_duration_p50_ms: None,
+                resolve_duration_p95_ms: None,
+                resolve_duration_p99_ms: None,
+                cache_hit_rate: None,
+            }
+        );
+    }
+
+    #[test]
+    fn it_should_return_the_percentiles_of_the_resolution_durations() {
+        let stats = Stats::new();
+
+        for ms in (1..=100).rev() {
+            stats.observe_resolution(Duration::from_millis(ms));
+        }
+
+        let summary = stats.summary();
+
+        assert_eq!(summary.resolve_duration_p50_ms, Some(50));
+        assert_eq!(summary.resolve_duration_p95_ms, Some(95));
+        assert_eq!(summary.resolve_duration_p99_ms, Some(99));
+    }
+
+    #[test]
+    fn it_should_return_the_cache_hit_rate() {
+        let stats = Stats::new();
+
+        stats.observe_cache_lookup(true);
+        stats.observe_cache_lookup(true);
+        stats.observe_cache_lookup(true);
+        stats.observe_cache_lookup(false);
+
+        assert_eq!(stats.summary().cache_hit_rate, Some(0.75));
+    }
+
+    #[test]
+    fn it_should_only_keep_the_most_recent_samples() {
+        let stats = Stats::new();
+
+        stats.observe_cache_lookup(false);
+
+        for _ in 0..WINDOW_SIZE {
+            stats.observe_cache_lookup(true);
+        }
+
+        assert_eq!(stats.summary().cache_hit_rate, Some(1.0));
+    }
+}
@@ -1,13 +1,1306 @@
+//! API tests with a fake resolver, so torrents are "resolved" without a real
+//! DHT.
+//!
 //! There must be at least one integration test otherwise `cargo next archive ...`
 //! command in the Containerfile will not include the main binary.
 //!
 //! See: <https://github.com/nextest-rs/nextest/issues/423>
-use torrust_hash2torrent::run_app_for_integration_tests;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::extract::connect_info::MockConnectInfo;
+use axum::Router;
+use bytes::Bytes;
+use camino::Utf8PathBuf;
+use futures_util::future::BoxFuture;
+use hyper::{header, Request, StatusCode};
+use librqbit::{torrent_from_bytes, ByteBufOwned};
+use prometheus_client::metrics::gauge::Gauge;
+use tempfile::TempDir;
+use torrust_hash2torrent::api;
+use torrust_hash2torrent::api::access_list::AccessList;
+use torrust_hash2torrent::api::cache::Cache;
+use torrust_hash2torrent::api::disk_space::DiskSpaceGuard;
+use torrust_hash2torrent::api::error::ErrorBody;
+use torrust_hash2torrent::api::handler::{CacheKeys, CacheStats};
+use torrust_hash2torrent::api::load_shedding::PendingResolutions;
+use torrust_hash2torrent::api::maintenance::MaintenanceMode;
+use torrust_hash2torrent::api::negative_cache::NegativeCache;
+use torrust_hash2torrent::api::rate_limit::RateLimiter;
+use torrust_hash2torrent::api::reload::{self, ConfigReloader, ReloadReport};
+use torrust_hash2torrent::api::single_flight::SingleFlight;
+use torrust_hash2torrent::api::top_failures::{TopFailure, TopFailures};
+use torrust_hash2torrent::api::warm_up::{self, WarmUpReport};
+use torrust_hash2torrent::bit_torrent::client::{
+    ResolveMagnetError, ResolveMagnetResult, ResolvedTorrent,
+};
+use torrust_hash2torrent::bit_torrent::info_hash::InfoHash;
+use torrust_hash2torrent::bit_torrent::resolver::MagnetResolver;
+use torrust_hash2torrent::config::{self, Config};
+use torrust_hash2torrent::metrics::Metrics;
+use torrust_hash2torrent::stats::Stats;
+use torrust_hash2torrent::AppState;
+use tower::ServiceExt;
+
+const INFO_HASH: &str = "443c7602b4fde83d1154d6d9da48808418b181b6";
+
+/// It returns the same result for all the info hashes, after the delay, and
+/// counts the calls.
+struct FakeResolver {
+    result: ResolveMagnetResult,
+    delay: Duration,
+    calls: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl FakeResolver {
+    fn new(result: ResolveMagnetResult) -> Arc<Self> {
+        Self::with_delay(result, Duration::ZERO)
+    }
+
+    fn with_delay(result: ResolveMagnetResult, delay: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            result,
+            delay,
+            calls: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// The maximum number of resolutions at the same time.
+    fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::Relaxed)
+    }
+}
+
+impl MagnetResolver for FakeResolver {
+    fn resolve_magnet(&self, _info_hash: InfoHash) -> BoxFuture<'_, ResolveMagnetResult> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+
+        Box::pin(async {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::Relaxed);
+
+            tokio::time::sleep(self.delay).await;
+
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            self.result.clone()
+        })
+    }
+
+    fn is_session_started(&self) -> bool {
+        true
+    }
+}
+
+fn sample_torrent() -> Bytes {
+    let mut torrent =
+        b"d4:infod6:lengthi11e4:name10:sample.txt12:piece lengthi16384e6:pieces20:".to_vec();
+    torrent.extend_from_slice(&[0u8; 20]);
+    torrent.extend_from_slice(b"ee");
+    Bytes::from(torrent)
+}
+
+fn resolved_torrent() -> ResolvedTorrent {
+    let torrent_bytes = sample_torrent();
+
+    ResolvedTorrent {
+        info: torrent_from_bytes::<ByteBufOwned>(&torrent_bytes)
+            .unwrap()
+            .info,
+        torrent_bytes,
+        seen_peers: Some(1),
+    }
+}
+
+fn config(dir: &TempDir) -> Config {
+    let dir = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+
+    Config {
+        api: config::Api {
+            bind_addresses: vec!["127.0.0.1:0".parse().unwrap()],
+            torrents_cache_dir: dir.clone(),
+            torrent_max_age: Duration::from_secs(100),
+            max_batch_size: 2,
+            max_batch_concurrency: 2,
+            max_pending_resolutions: None,
+            max_files: None,
+            maintenance: config::Maintenance {
+                message: "Under maintenance".to_string(),
+                ..config::Maintenance::default()
+            },
+            ..config::Api::default()
+        },
+        client: config::Client {
+            listen_ports: None,
+            session_output_dir: dir,
+            max_concurrent_resolutions: 1,
+            resolution_queue_timeout: None,
+            resolve_timeout: Duration::from_secs(1),
+            session_cleanup: None,
+            free_disk_space: None,
+            ..config::Client::default()
+        },
+        cache: config::Cache {
+            max_entries: 10,
+            disk: false,
+            max_disk_size: None,
+            max_concurrent_disk_writes: 1,
+            negative_ttl: None,
+            ..config::Cache::default()
+        },
+        ..Config::default()
+    }
+}
+
+/// The API router with the fake resolver.
+fn app(dir: &TempDir, resolver: Arc<FakeResolver>) -> Router {
+    app_with_config(config(dir), resolver)
+}
+
+fn app_with_config(config: Config, resolver: Arc<FakeResolver>) -> Router {
+    api::router(Arc::new(app_state(config, resolver)))
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 8080))))
+}
+
+fn app_state(config: Config, resolver: Arc<FakeResolver>) -> AppState {
+    app_state_with_reloader(
+        ConfigReloader::without_config_file(&config),
+        config,
+        resolver,
+    )
+}
+
+/// The `config` must be the base config with the config file applied.
+fn app_state_with_reloader(
+    reloader: ConfigReloader,
+    config: Config,
+    resolver: Arc<FakeResolver>,
+) -> AppState {
+    AppState {
+        reloader: Arc::new(reloader),
+        resolver,
+        cache: Arc::new(Cache::new(
+            config.api.torrents_cache_dir.clone(),
+            &config.cache,
+        )),
+        negative_cache: Arc::new(NegativeCache::new(&config.cache)),
+        top_failures: Arc::new(TopFailures::new(config.api.top_failures.as_ref())),
+        pending_resolutions: Arc::new(PendingResolutions::new(
+            config.api.max_pending_resolutions,
+            Gauge::default(),
+        )),
+        access_list: Arc::new(AccessList::load(&config.api.access_lists).unwrap()),
+        disk_space: Arc::new(DiskSpaceGuard::new(
+            &config.client.session_output_dir,
+            config.client.free_disk_space.clone(),
+        )),
+        rate_limiter: Arc::new(RateLimiter::new(config.api.rate_limit.as_ref())),
+        config: Arc::new(config),
+        resolutions: Arc::new(SingleFlight::new()),
+        metrics: Arc::new(Metrics::new()),
+        stats: Arc::new(Stats::new()),
+        maintenance: Arc::new(MaintenanceMode::new(false)),
+    }
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+async fn error_body(body: Body) -> ErrorBody {
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// It returns the app with the config file applied, and the path of the
+/// config file, so it can be changed and reloaded.
+fn app_with_config_file(
+    dir: &TempDir,
+    contents: &str,
+    resolver: Arc<FakeResolver>,
+) -> (Router, Utf8PathBuf) {
+    let path = Utf8PathBuf::from_path_buf(dir.path().join("config.json")).unwrap();
+    std::fs::write(&path, contents).unwrap();
+
+    let mut base = config(dir);
+    base.api.admin_token = Some("admin".to_string());
+
+    let config = reload::with_config_file(&base, &path).unwrap();
+    let reloader = ConfigReloader::new(Some(path.clone()), base, &config);
+
+    let app = api::router(Arc::new(app_state_with_reloader(
+        reloader, config, resolver,
+    )))
+    .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 8080))));
+
+    (app, path)
+}
+
+fn reload_request() -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri("/admin/reload")
+        .header(header::AUTHORIZATION, "Bearer admin")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn it_should_apply_the_reloaded_settings_and_report_the_ones_requiring_a_restart() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let (app, path) = app_with_config_file(&dir, "{}", resolver);
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}?timeout_ms=5000")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::write(
+        &path,
+        r#"{
+            "trackers": ["udp://tracker.example.com:6969/announce"],
+            "max_resolve_timeout_ms": 1000,
+            "rate_limit": {"requests_per_second": 0.001, "burst": 1},
+            "listen_ports": [6881]
+        }"#,
+    )
+    .unwrap();
+
+    let response = app.clone().oneshot(reload_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<ReloadReport>(&body).unwrap(),
+        ReloadReport {
+            applied: vec![
+                "trackers".to_string(),
+                "max_resolve_timeout".to_string(),
+                "rate_limit".to_string()
+            ],
+            requires_restart: vec!["listen_ports".to_string()],
+        }
+    );
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}/announce-list")))
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<Vec<String>>(&body).unwrap(),
+        vec!["udp://tracker.example.com:6969/announce"]
+    );
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}?timeout_ms=5000")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn it_should_keep_the_current_settings_when_the_reloaded_config_is_not_valid() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let (app, path) = app_with_config_file(&dir, r#"{"max_resolve_timeout_ms": 1000}"#, resolver);
+
+    // The resolve request timeout is 12 seconds.
+    std::fs::write(&path, r#"{"max_resolve_timeout_ms": 60000}"#).unwrap();
+
+    let response = app.clone().oneshot(reload_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        error_body(response.into_body()).await.error,
+        "reload_failed"
+    );
+
+    let response = app
+        .oneshot(get(&format!("/torrents/{INFO_HASH}?timeout_ms=5000")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Addresses with free ports.
+fn free_addresses() -> (SocketAddr, SocketAddr) {
+    let first = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let second = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    (first.local_addr().unwrap(), second.local_addr().unwrap())
+}
+
+/// It retries until the API is listening on the address.
+async fn get_when_listening(http_client: &reqwest::Client, url: &str) -> reqwest::Response {
+    for _ in 0..100 {
+        if let Ok(response) = http_client.get(url).send().await {
+            return response;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    panic!("the API is not listening on {url}");
+}
+
+#[tokio::test]
+async fn it_should_drain_the_connections_and_listen_on_the_reloaded_bind_addresses() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::with_delay(Ok(resolved_torrent()), Duration::from_millis(500));
+    let (old, new) = free_addresses();
+
+    let path = Utf8PathBuf::from_path_buf(dir.path().join("config.json")).unwrap();
+    std::fs::write(&path, format!(r#"{{"bind_addresses": ["{old}"]}}"#)).unwrap();
+
+    let base = config(&dir);
+    let config = reload::with_config_file(&base, &path).unwrap();
+    let reloader = ConfigReloader::new(Some(path.clone()), base, &config);
+    let state = Arc::new(app_state_with_reloader(reloader, config, resolver));
+
+    tokio::spawn(api::start(state.clone()));
+
+    let http_client = reqwest::Client::builder().no_proxy().build().unwrap();
+
+    get_when_listening(&http_client, &format!("http://{old}/health/live")).await;
+
+    let in_flight = tokio::spawn({
+        let http_client = http_client.clone();
+        async move {
+            http_client
+                .get(format!("http://{old}/torrents/{INFO_HASH}"))
+                .send()
+                .await
+        }
+    });
+
+    // The request is waiting for the resolver.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    std::fs::write(&path, format!(r#"{{"bind_addresses": ["{new}"]}}"#)).unwrap();
+
+    assert_eq!(
+        state.reloader.reload(&state).unwrap(),
+        ReloadReport {
+            applied: vec!["bind_addresses".to_string()],
+            requires_restart: vec![],
+        }
+    );
+
+    // It's answered before the server stops listening.
+    assert_eq!(
+        in_flight.await.unwrap().unwrap().status(),
+        reqwest::StatusCode::OK
+    );
+
+    let response = get_when_listening(&http_client, &format!("http://{new}/health/live")).await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    assert!(http_client
+        .get(format!("http://{old}/health/live"))
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn it_should_reject_a_reloaded_rate_limit_of_zero_requests_per_second() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let (app, path) = app_with_config_file(
+        &dir,
+        r#"{"rate_limit": {"requests_per_second": 1.0, "burst": 1}}"#,
+        resolver,
+    );
+
+    std::fs::write(
+        &path,
+        r#"{"rate_limit": {"requests_per_second": 0.0, "burst": 1}}"#,
+    )
+    .unwrap();
+
+    let response = app.clone().oneshot(reload_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(
+        error_body(response.into_body()).await.error,
+        "reload_failed"
+    );
+
+    // The current limit is kept, and the throttled requests don't panic.
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+}
+
+#[tokio::test]
+async fn it_should_apply_the_reloaded_access_list_files() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+    let denylist = Utf8PathBuf::from_path_buf(dir.path().join("denylist.txt")).unwrap();
+    std::fs::write(&denylist, "").unwrap();
+
+    let mut config = config(&dir);
+    config.api.admin_token = Some("admin".to_string());
+    config.api.access_lists.denylist = Some(config::InfoHashList::File(denylist.clone()));
+    let app = app_with_config(config, resolver);
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::write(&denylist, format!("{INFO_HASH}\n")).unwrap();
+
+    // There is no config file, only the access lists are reloaded.
+    let response = app.clone().oneshot(reload_request()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<ReloadReport>(&body).unwrap(),
+        ReloadReport {
+            applied: vec!["access_lists".to_string()],
+            requires_restart: vec![],
+        }
+    );
+
+    let response = app
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+}
 
 #[test]
-fn test_app() {
+fn it_should_reject_a_max_resolve_timeout_not_shorter_than_the_resolve_request_timeout() {
+    let dir = TempDir::new().unwrap();
+
+    let mut config = config(&dir);
+    assert!(config.validate().is_ok());
+
+    config.api.max_resolve_timeout = config.api.request_timeouts.resolve;
+    assert!(matches!(
+        config.validate(),
+        Err(config::Error::InvalidMaxResolveTimeout { .. })
+    ));
+}
+
+#[tokio::test]
+async fn it_should_return_the_resolved_torrent_file() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let response = app(&dir, resolver.clone())
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "application/x-bittorrent"
+    );
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        format!(
+            "attachment; filename=\"{INFO_HASH}.torrent\"; filename*=UTF-8''{INFO_HASH}.torrent"
+        )
+    );
+    assert_eq!(response.headers()["x-torrust-torrent-infohash"], INFO_HASH);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(body, sample_torrent());
+    assert_eq!(resolver.calls(), 1);
+}
+
+#[tokio::test]
+async fn it_should_serve_the_resolved_torrents_from_the_cache() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+    let app = app(&dir, resolver.clone());
+
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    assert_eq!(resolver.calls(), 1);
+}
+
+#[tokio::test]
+async fn it_should_break_down_the_request_duration_in_the_server_timing_header() {
+    let dir = TempDir::new().unwrap();
+    let app = app(&dir, FakeResolver::new(Ok(resolved_torrent())));
+
+    let metric_names = |response: &axum::response::Response| -> Vec<String> {
+        response.headers()["server-timing"]
+            .to_str()
+            .unwrap()
+            .split(", ")
+            .map(|metric| metric.split(";dur=").next().unwrap().to_string())
+            .collect()
+    };
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(
+        metric_names(&response),
+        ["cache_lookup", "dht_resolve", "total"]
+    );
+
+    // Cached torrents are not resolved.
+    let response = app
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(metric_names(&response), ["cache_lookup", "total"]);
+}
+
+#[tokio::test]
+async fn it_should_reject_invalid_info_hashes_without_resolving_them() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let response = app(&dir, resolver.clone())
+        .oneshot(get("/torrents/not-an-info-hash"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     assert_eq!(
-        "No integration tests yet :-(",
-        run_app_for_integration_tests()
+        error_body(response.into_body()).await.error,
+        "invalid_info_hash"
     );
+    assert_eq!(resolver.calls(), 0);
+}
+
+#[tokio::test]
+async fn it_should_return_a_not_found_error_when_the_torrent_cant_be_resolved() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::NotFound));
+
+    let response = app(&dir, resolver.clone())
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    assert_eq!(error_body(response.into_body()).await.error, "not_found");
+    assert_eq!(resolver.calls(), 1);
+}
+
+#[tokio::test]
+async fn it_should_not_resolve_again_the_torrents_not_found_recently() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::NotFound));
+
+    let mut config = config(&dir);
+    config.cache.negative_ttl = Some(Duration::from_secs(60));
+    let app = app_with_config(config, resolver.clone());
+
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(error_body(response.into_body()).await.error, "not_found");
+    }
+
+    assert_eq!(resolver.calls(), 1);
+
+    let response = app.oneshot(get("/metrics")).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    assert!(String::from_utf8_lossy(&body).contains("hash2torrent_negative_cache_hits_total 1"));
+}
+
+#[tokio::test]
+async fn it_should_resolve_again_the_torrents_whose_content_could_not_be_verified_because_of_a_local_error(
+) {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::VerificationFailed));
+
+    let mut config = config(&dir);
+    config.cache.negative_ttl = Some(Duration::from_secs(60));
+    let app = app_with_config(config, resolver.clone());
+
+    for _ in 0..2 {
+        let response = app
+            .clone()
+            .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error_body(response.into_body()).await.error, "client_error");
+    }
+
+    assert_eq!(resolver.calls(), 2);
+}
+
+#[tokio::test]
+async fn it_should_not_resolve_torrents_when_the_disk_is_almost_full() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.client.free_disk_space = Some(config::FreeDiskSpace {
+        min_free_bytes: u64::MAX,
+        check_interval: Duration::from_secs(60),
+    });
+
+    let response = app_with_config(config, resolver.clone())
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        error_body(response.into_body()).await.error,
+        "low_disk_space"
+    );
+    assert_eq!(resolver.calls(), 0);
+}
+
+#[tokio::test]
+async fn it_should_serve_a_placeholder_when_the_torrent_cant_be_resolved_if_enabled() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::NotFound));
+
+    let mut config = config(&dir);
+    config.api.placeholder_on_failure = true;
+
+    let response = app_with_config(config, resolver.clone())
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["x-torrust-resolution"], "failed");
+    assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let torrent = torrent_from_bytes::<ByteBufOwned>(&body).unwrap();
+
+    assert_eq!(torrent.info.length, Some(0));
+    assert_eq!(resolver.calls(), 1);
+}
+
+#[tokio::test]
+async fn it_should_not_serve_a_placeholder_when_the_service_is_overloaded() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.api.placeholder_on_failure = true;
+    config.api.max_pending_resolutions = Some(0);
+
+    let response = app_with_config(config, resolver)
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(response.headers().get("x-torrust-resolution").is_none());
+    assert_eq!(error_body(response.into_body()).await.error, "busy");
+}
+
+#[tokio::test]
+async fn it_should_not_serve_a_placeholder_for_the_cache_misses_of_cache_only_requests() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::NotFound));
+
+    let mut config = config(&dir);
+    config.api.placeholder_on_failure = true;
+
+    let response = app_with_config(config, resolver)
+        .oneshot(get(&format!("/torrents/{INFO_HASH}?cache_only=true")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(error_body(response.into_body()).await.error, "not_cached");
+}
+
+#[tokio::test]
+async fn it_should_refuse_the_denied_and_not_allowed_info_hashes_without_resolving_them() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+    let other = "0000000000000000000000000000000000000001";
+
+    let mut config = config(&dir);
+    config.api.access_lists = config::AccessLists {
+        denylist: Some(config::InfoHashList::Inline(vec![InfoHash::from_str(
+            INFO_HASH,
+        )
+        .unwrap()])),
+        allowlist: Some(config::InfoHashList::Inline(vec![])),
+    };
+    let app = app_with_config(config, resolver.clone());
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+    assert_eq!(error_body(response.into_body()).await.error, "denied");
+
+    let response = app
+        .oneshot(get(&format!("/torrents/{other}/metadata")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(error_body(response.into_body()).await.error, "not_allowed");
+    assert_eq!(resolver.calls(), 0);
+}
+
+#[tokio::test]
+async fn it_should_return_the_cache_stats_to_admins() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.api.admin_token = Some("admin".to_string());
+    let app = app_with_config(config, resolver);
+
+    for _ in 0..2 {
+        app.clone()
+            .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+            .await
+            .unwrap();
+    }
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/cache/stats")
+                .header(header::AUTHORIZATION, "Bearer admin")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<CacheStats>(&body).unwrap(),
+        CacheStats {
+            entries: 1,
+            memory_bytes: sample_torrent().len() as u64,
+            hits: 1,
+            misses: 1,
+            disk_bytes: None,
+        }
+    );
+
+    let response = app.oneshot(get("/admin/cache/stats")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn it_should_list_the_cached_info_hashes_to_admins() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.api.admin_token = Some("admin".to_string());
+    let app = app_with_config(config, resolver);
+
+    app.clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    let cache_keys = |uri: &'static str| {
+        let app = app.clone();
+
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, "Bearer admin")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<CacheKeys>(&body).unwrap()
+        }
+    };
+
+    let page = cache_keys("/admin/cache/keys").await;
+
+    assert_eq!((page.total, page.offset, page.limit), (1, 0, 100));
+    assert_eq!(page.keys.len(), 1);
+    assert_eq!(
+        page.keys[0].info_hash,
+        InfoHash::from_str(INFO_HASH).unwrap()
+    );
+    assert_eq!(page.keys[0].size, sample_torrent().len() as u64);
+
+    let page = cache_keys("/admin/cache/keys?limit=5000&offset=1").await;
+
+    assert_eq!((page.total, page.offset, page.limit), (1, 1, 1000));
+    assert!(page.keys.is_empty());
+
+    let response = app.oneshot(get("/admin/cache/keys")).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn it_should_return_the_info_hashes_failing_most_often_to_admins() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Err(ResolveMagnetError::InvalidMetadata));
+
+    let mut config = config(&dir);
+    config.api.admin_token = Some("admin".to_string());
+    config.api.top_failures = Some(config::TopFailures {
+        size: 10,
+        max_tracked: 100,
+    });
+    let app = app_with_config(config, resolver);
+
+    for _ in 0..2 {
+        app.clone()
+            .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+            .await
+            .unwrap();
+    }
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri("/admin/top-failures")
+                .header(header::AUTHORIZATION, "Bearer admin")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<Vec<TopFailure>>(&body).unwrap(),
+        vec![TopFailure {
+            info_hash: InfoHash::from_str(INFO_HASH).unwrap(),
+            failures: 2,
+            last_outcome: "invalid_metadata".to_string(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn it_should_return_the_metadata_of_the_resolved_torrent() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let response = app(&dir, resolver)
+        .oneshot(get(&format!("/torrents/{INFO_HASH}/metadata")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let metadata: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(
+        InfoHash::from_str(metadata["info_hash"].as_str().unwrap()).unwrap(),
+        InfoHash::from_str(INFO_HASH).unwrap()
+    );
+    assert_eq!(metadata["name"], "sample.txt");
+    assert_eq!(metadata["total_size"], 11);
+}
+
+#[tokio::test]
+async fn it_should_return_the_trackers_of_the_torrent_and_the_configured_ones() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.client.trackers = vec!["udp://tracker.example.com:6969/announce".to_string()];
+
+    let response = app_with_config(config, resolver)
+        .oneshot(get(&format!("/torrents/{INFO_HASH}/announce-list")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        serde_json::from_slice::<Vec<String>>(&body).unwrap(),
+        vec!["udp://tracker.example.com:6969/announce"]
+    );
+}
+
+#[tokio::test]
+async fn it_should_add_the_configured_trackers_to_the_torrent_file_keeping_the_info_hash() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.client.trackers = vec!["udp://tracker.example.com:6969/announce".to_string()];
+
+    let response = app_with_config(config, resolver)
+        .oneshot(get(&format!("/torrents/{INFO_HASH}?inject_trackers=true")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let torrent = torrent_from_bytes::<ByteBufOwned>(&body).unwrap();
+
+    assert_eq!(
+        torrent.info_hash.as_string(),
+        torrent_from_bytes::<ByteBufOwned>(&sample_torrent())
+            .unwrap()
+            .info_hash
+            .as_string()
+    );
+    assert_eq!(
+        torrent
+            .announce_list
+            .iter()
+            .flatten()
+            .map(|tracker| std::str::from_utf8(tracker.as_ref()).unwrap())
+            .collect::<Vec<_>>(),
+        ["udp://tracker.example.com:6969/announce"]
+    );
+}
+
+#[tokio::test]
+async fn it_should_return_a_json_error_when_the_client_is_rate_limited() {
+    let dir = TempDir::new().unwrap();
+
+    let mut config = config(&dir);
+    config.api.rate_limit = Some(config::RateLimit {
+        requests_per_second: 0.5,
+        burst: 1,
+    });
+
+    let app = app_with_config(config, FakeResolver::new(Ok(resolved_torrent())));
+
+    let response = app
+        .clone()
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    // The time until the next token, not the configured `retry_after`.
+    assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+    assert_eq!(
+        error_body(response.into_body()).await.error,
+        "too_many_requests"
+    );
+}
+
+#[tokio::test]
+async fn it_should_shed_the_load_when_there_are_too_many_pending_resolutions() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.api.max_pending_resolutions = Some(0);
+
+    let response = app_with_config(config, resolver.clone())
+        .oneshot(get(&format!("/torrents/{INFO_HASH}")))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+    assert_eq!(error_body(response.into_body()).await.error, "busy");
+    assert_eq!(resolver.calls(), 0);
+}
+
+#[tokio::test]
+async fn it_should_limit_the_resolutions_of_a_batch_at_the_same_time() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::with_delay(Ok(resolved_torrent()), Duration::from_millis(50));
+
+    let mut config = config(&dir);
+    config.api.max_batch_size = 3;
+    config.api.max_batch_concurrency = 2;
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/torrents")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"["443c7602b4fde83d1154d6d9da48808418b181b6",
+                "c4b0ff1e0b5c4eef43b9fe2c3a8b0e1f3f9d4b9a",
+                "0b5c4eef43b9fe2c3a8b0e1f3f9d4b9ac4b0ff1e"]"#,
+        ))
+        .unwrap();
+
+    let response = app_with_config(config, resolver.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0]["info_hash"], INFO_HASH);
+    assert_eq!(resolver.calls(), 3);
+    assert_eq!(resolver.max_in_flight(), 2);
+}
+
+#[tokio::test]
+async fn it_should_warm_the_cache_with_the_torrents_resolved_within_the_budget() {
+    let dir = TempDir::new().unwrap();
+    let info_hashes = vec![
+        InfoHash::from_str(INFO_HASH).unwrap(),
+        InfoHash::from_str("0000000000000000000000000000000000000001").unwrap(),
+    ];
+    let warm_up = config::WarmUp {
+        info_hashes: config::InfoHashList::Inline(info_hashes.clone()),
+        max_concurrency: 2,
+        budget: Duration::from_secs(10),
+    };
+
+    let state = app_state(config(&dir), FakeResolver::new(Ok(resolved_torrent())));
+
+    assert_eq!(
+        warm_up::warm_up(&state, info_hashes.clone(), &warm_up).await,
+        WarmUpReport {
+            warmed: 2,
+            failed: 0,
+            skipped: 0,
+        }
+    );
+    assert!(info_hashes
+        .iter()
+        .all(|info_hash| state.cache.contains(info_hash)));
+
+    // The resolutions take longer than the budget.
+    let dir = TempDir::new().unwrap();
+    let state = app_state(
+        config(&dir),
+        FakeResolver::with_delay(Ok(resolved_torrent()), Duration::from_secs(10)),
+    );
+
+    let report = warm_up::warm_up(
+        &state,
+        info_hashes,
+        &config::WarmUp {
+            budget: Duration::from_millis(50),
+            ..warm_up
+        },
+    )
+    .await;
+
+    assert_eq!(report.skipped, 2);
+    assert!(state.cache.is_empty());
+}
+
+#[tokio::test]
+async fn it_should_reject_request_bodies_larger_than_the_limit() {
+    let dir = TempDir::new().unwrap();
+    let resolver = FakeResolver::new(Ok(resolved_torrent()));
+
+    let mut config = config(&dir);
+    config.api.request_limits.max_body_size = 16;
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/torrents")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"["443c7602b4fde83d1154d6d9da48808418b181b6"]"#,
+        ))
+        .unwrap();
+
+    let response = app_with_config(config, resolver.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resolver.calls(), 0);
+}
+
+#[cfg(feature = "client")]
+mod client {
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+
+    use axum::Router;
+    use hyper::StatusCode;
+    use tempfile::TempDir;
+    use torrust_hash2torrent::bit_torrent::client::ResolveMagnetError;
+    use torrust_hash2torrent::bit_torrent::info_hash::InfoHash;
+    use torrust_hash2torrent::client::{Client, Error};
+
+    use super::{
+        app, app_with_config, config, resolved_torrent, sample_torrent, FakeResolver, INFO_HASH,
+    };
+
+    /// Serves the router on a free local port and returns the API client.
+    async fn serve(router: Router) -> Client {
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        Client::new(&format!("http://{address}/"))
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_torrent_file_and_the_metadata() {
+        let dir = TempDir::new().unwrap();
+        let client = serve(app(&dir, FakeResolver::new(Ok(resolved_torrent())))).await;
+        let info_hash = InfoHash::from_str(INFO_HASH).unwrap();
+
+        assert_eq!(client.resolve(&info_hash).await.unwrap(), sample_torrent());
+
+        let metadata = client.metadata(&info_hash).await.unwrap();
+
+        assert_eq!(metadata.info_hash, info_hash);
+        assert_eq!(metadata.name, "sample.txt");
+        assert_eq!(metadata.total_size, 11);
+    }
+
+    #[tokio::test]
+    async fn it_should_return_the_api_errors() {
+        let dir = TempDir::new().unwrap();
+        let client = serve(app(
+            &dir,
+            FakeResolver::new(Err(ResolveMagnetError::NotFound)),
+        ))
+        .await;
+
+        let err = client
+            .resolve(&InfoHash::from_str(INFO_HASH).unwrap())
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(err, Error::Api { status, body } if status == StatusCode::NOT_FOUND && body.error == "not_found")
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_send_the_auth_token() {
+        let dir = TempDir::new().unwrap();
+        let mut config = config(&dir);
+        config.api.auth_token = Some("secret".to_string());
+
+        let client = serve(app_with_config(
+            config,
+            FakeResolver::new(Ok(resolved_torrent())),
+        ))
+        .await;
+        let info_hash = InfoHash::from_str(INFO_HASH).unwrap();
+
+        assert!(matches!(
+            client.metadata(&info_hash).await,
+            Err(Error::Api { status, .. }) if status == StatusCode::UNAUTHORIZED
+        ));
+        assert!(client
+            .with_auth_token("secret")
+            .metadata(&info_hash)
+            .await
+            .is_ok());
+    }
 }